@@ -0,0 +1,56 @@
+/// Largest offset a compression pointer can refer to (the pointer has 14 bits)
+const MAX_POINTER: usize = 0x3FFF;
+
+/// How many name suffixes are remembered, later ones are written without being remembered
+const MAX_SUFFIXES: usize = 128;
+
+/// Tracks where names (and name suffixes) have already been written in a message
+/// so that later occurrences can be replaced by a pointer (RFC 1035 4.1.4)
+///
+/// The suffixes are borrowed from the names being written and kept in a fixed table,
+/// so encoding a message doesn't allocate for compression
+#[derive(Debug)]
+pub(crate) struct NameCompressor<'m> {
+    suffixes: [(&'m [Vec<u8>], u16); MAX_SUFFIXES],
+    len: usize,
+}
+
+impl<'m> NameCompressor<'m> {
+    pub fn new() -> Self {
+        Self {
+            suffixes: [(&[], 0); MAX_SUFFIXES],
+            len: 0,
+        }
+    }
+
+    /// Get the offset of a previously written name suffix, if it was written before `position`
+    pub fn get(&self, suffix: &[Vec<u8>], position: usize) -> Option<u16> {
+        self.suffixes[..self.len]
+            .iter()
+            .find(|(written, _)| suffix_eq(written, suffix))
+            .map(|&(_, offset)| offset)
+            .filter(|&offset| (offset as usize) < position)
+    }
+
+    /// Remember that `suffix` was written at `offset` from the start of the message
+    pub fn insert(&mut self, suffix: &'m [Vec<u8>], offset: usize) {
+        if offset > MAX_POINTER || self.len == MAX_SUFFIXES {
+            return;
+        }
+
+        if self.suffixes[..self.len]
+            .iter()
+            .any(|(written, _)| suffix_eq(written, suffix))
+        {
+            return;
+        }
+
+        self.suffixes[self.len] = (suffix, offset as u16);
+        self.len += 1;
+    }
+}
+
+/// Compare the labels of two suffixes ignoring ASCII case, like `Name` does
+fn suffix_eq(a: &[Vec<u8>], b: &[Vec<u8>]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.eq_ignore_ascii_case(b))
+}
@@ -0,0 +1,125 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::{DnsError, UnknownValue};
+
+/// The class of a resource record or question
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum DnsClass {
+    /// Internet
+    #[default]
+    In,
+    /// Chaos
+    Ch,
+    /// Hesiod
+    Hs,
+    /// Only valid in updates (RFC 2136)
+    None,
+    /// Only valid in questions, matches every class
+    Any,
+    Unknown(UnknownValue<u16>),
+}
+
+impl DnsClass {
+    pub fn to_int(&self) -> u16 {
+        match self {
+            Self::In => 1,
+            Self::Ch => 3,
+            Self::Hs => 4,
+            Self::None => 254,
+            Self::Any => 255,
+            Self::Unknown(v) => v.get(),
+        }
+    }
+
+    pub fn from_int(v: u16) -> Self {
+        match v {
+            1 => Self::In,
+            3 => Self::Ch,
+            4 => Self::Hs,
+            254 => Self::None,
+            255 => Self::Any,
+            v => Self::Unknown(UnknownValue::new(v)),
+        }
+    }
+
+    /// Whether a record of class `other` answers a question of this class
+    pub fn matches(&self, other: DnsClass) -> bool {
+        *self == Self::Any || *self == other
+    }
+}
+
+impl From<u16> for DnsClass {
+    fn from(value: u16) -> Self {
+        Self::from_int(value)
+    }
+}
+
+impl From<DnsClass> for u16 {
+    fn from(value: DnsClass) -> Self {
+        value.to_int()
+    }
+}
+
+impl Display for DnsClass {
+    /// The mnemonic of the class, or `CLASS123` for classes without one (RFC 3597 5)
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::In => f.write_str("IN"),
+            Self::Ch => f.write_str("CH"),
+            Self::Hs => f.write_str("HS"),
+            Self::None => f.write_str("NONE"),
+            Self::Any => f.write_str("ANY"),
+            Self::Unknown(v) => write!(f, "CLASS{v}"),
+        }
+    }
+}
+
+impl FromStr for DnsClass {
+    type Err = DnsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let class = match s.to_ascii_uppercase().as_str() {
+            "IN" => Self::In,
+            "CH" => Self::Ch,
+            "HS" => Self::Hs,
+            "NONE" => Self::None,
+            "ANY" => Self::Any,
+            other => other
+                .strip_prefix("CLASS")
+                .and_then(|v| v.parse::<u16>().ok())
+                .map(Self::from_int)
+                .ok_or(DnsError::FormatError)?,
+        };
+
+        Ok(class)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::DnsClass;
+
+    #[test]
+    fn converts_classes() {
+        assert_eq!(DnsClass::from_int(1), DnsClass::In);
+        assert_eq!(DnsClass::from_int(255), DnsClass::Any);
+        assert!(matches!(DnsClass::from_int(2), DnsClass::Unknown(v) if v.get() == 2));
+        assert_eq!(DnsClass::from_int(2).to_int(), 2);
+
+        assert_eq!(DnsClass::Ch.to_string(), "CH");
+        assert_eq!(DnsClass::from_int(2).to_string(), "CLASS2");
+        assert_eq!("in".parse::<DnsClass>().unwrap(), DnsClass::In);
+        assert_eq!("CLASS3".parse::<DnsClass>().unwrap(), DnsClass::Ch);
+        assert!("CLASS".parse::<DnsClass>().is_err());
+        assert!("XX".parse::<DnsClass>().is_err());
+    }
+
+    #[test]
+    fn any_matches_every_class() {
+        assert!(DnsClass::Any.matches(DnsClass::Ch));
+        assert!(DnsClass::In.matches(DnsClass::In));
+        assert!(!DnsClass::In.matches(DnsClass::Ch));
+        assert!(!DnsClass::In.matches(DnsClass::Any));
+    }
+}
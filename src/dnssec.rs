@@ -0,0 +1,546 @@
+//! Checking DNSSEC signatures, digests and proofs of nonexistence (RFC 4033, 4034, 4035)
+
+use ring::{digest, signature};
+
+use crate::{
+    ExtendedErrorCode, FailureReason, Name, Networkable, RecordData, RecordType, ResourceRecord,
+};
+
+mod denial;
+pub use denial::{
+    prove_no_data, prove_nonexistence, prove_unsigned_delegation, prove_wildcard_expansion, Denial,
+    MAX_NSEC3_ITERATIONS,
+};
+
+/// RSA/SHA-256 (RFC 5702)
+const RSASHA256: u8 = 8;
+/// ECDSA with curve P-256 and SHA-256 (RFC 6605)
+const ECDSAP256SHA256: u8 = 13;
+/// Ed25519 (RFC 8080)
+const ED25519: u8 = 15;
+
+const DIGEST_SHA1: u8 = 1;
+const DIGEST_SHA256: u8 = 2;
+const DIGEST_SHA384: u8 = 4;
+
+/// The DNSKEY flag of keys that sign the zone's records (RFC 4034 2.1.1)
+pub const ZONE_KEY_FLAG: u16 = 0x0100;
+
+/// The only protocol a DNSKEY can have (RFC 4034 2.1.2)
+const DNSKEY_PROTOCOL: u8 = 3;
+
+/// Whether signatures of a DNSKEY algorithm can be checked
+pub fn is_supported_algorithm(algorithm: u8) -> bool {
+    matches!(algorithm, RSASHA256 | ECDSAP256SHA256 | ED25519)
+}
+
+/// Whether DS digests of a type can be checked
+pub fn is_supported_digest(digest_type: u8) -> bool {
+    matches!(digest_type, DIGEST_SHA1 | DIGEST_SHA256 | DIGEST_SHA384)
+}
+
+/// Whether the DS data is a digest of the DNSKEY record `key` (RFC 4034 5.1.4)
+pub fn ds_matches(ds: &RecordData, key: &ResourceRecord) -> bool {
+    let (
+        RecordData::Ds {
+            key_tag,
+            algorithm,
+            digest_type,
+            digest,
+        },
+        RecordData::Dnskey {
+            algorithm: key_algorithm,
+            ..
+        },
+    ) = (ds, &key.data)
+    else {
+        return false;
+    };
+
+    let hash = match *digest_type {
+        DIGEST_SHA1 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+        DIGEST_SHA256 => &digest::SHA256,
+        DIGEST_SHA384 => &digest::SHA384,
+        _ => return false,
+    };
+
+    if algorithm != key_algorithm || key.data.key_tag() != Some(*key_tag) {
+        return false;
+    }
+
+    let mut context = digest::Context::new(hash);
+    context.update(&key.name.to_lowercase().to_bytes());
+    context.update(&key.data.to_bytes());
+    context.finish().as_ref() == digest.as_slice()
+}
+
+/// Check that the RRSIG record `rrsig` is a signature by the DNSKEY record `key` over `rrset`,
+/// which are the records of one owner, type and class.
+/// `now` is in seconds since the epoch (RFC 4035 5.3)
+pub fn verify_rrset(
+    rrset: &[ResourceRecord],
+    rrsig: &ResourceRecord,
+    key: &ResourceRecord,
+    now: u32,
+) -> Result<(), FailureReason> {
+    let RecordData::Rrsig {
+        type_covered,
+        algorithm,
+        labels,
+        original_ttl,
+        expiration,
+        inception,
+        key_tag,
+        signer,
+        signature,
+    } = &rrsig.data
+    else {
+        return Err(bogus(ExtendedErrorCode::DnssecBogus, "not an RRSIG record"));
+    };
+
+    let RecordData::Dnskey {
+        flags,
+        protocol,
+        algorithm: key_algorithm,
+        public_key,
+    } = &key.data
+    else {
+        return Err(bogus(ExtendedErrorCode::DnssecBogus, "not a DNSKEY record"));
+    };
+
+    let Some(first) = rrset.first() else {
+        return Err(bogus(ExtendedErrorCode::DnssecBogus, "no records to check"));
+    };
+
+    if rrsig.name != first.name
+        || rrsig.class != first.class
+        || *type_covered != first.type_
+        || rrset
+            .iter()
+            .any(|rr| rr.name != first.name || rr.type_ != first.type_ || rr.class != first.class)
+    {
+        return Err(bogus(
+            ExtendedErrorCode::DnssecBogus,
+            "the RRSIG doesn't cover the records",
+        ));
+    }
+
+    if key.name != *signer || !first.name.is_subdomain_of(signer) {
+        return Err(bogus(
+            ExtendedErrorCode::DnssecBogus,
+            "the RRSIG isn't signed by the key's zone",
+        ));
+    }
+
+    if *labels as usize > label_count(&first.name) {
+        return Err(bogus(
+            ExtendedErrorCode::DnssecBogus,
+            "the RRSIG has more labels than its owner",
+        ));
+    }
+
+    if serial_lt(now, *inception) {
+        return Err(bogus(
+            ExtendedErrorCode::SignatureNotYetValid,
+            "the RRSIG isn't valid yet",
+        ));
+    }
+
+    if serial_lt(*expiration, now) {
+        return Err(bogus(
+            ExtendedErrorCode::SignatureExpired,
+            "the RRSIG has expired",
+        ));
+    }
+
+    if algorithm != key_algorithm
+        || *protocol != DNSKEY_PROTOCOL
+        || key.data.key_tag() != Some(*key_tag)
+    {
+        return Err(bogus(
+            ExtendedErrorCode::DnssecBogus,
+            "the RRSIG wasn't made by the key",
+        ));
+    }
+
+    if flags & ZONE_KEY_FLAG == 0 {
+        return Err(bogus(
+            ExtendedErrorCode::NoZoneKeyBitSet,
+            "the key isn't a zone key",
+        ));
+    }
+
+    if !is_supported_algorithm(*algorithm) {
+        return Err(bogus(
+            ExtendedErrorCode::UnsupportedDnskeyAlgorithm,
+            &format!("algorithm {algorithm} isn't supported"),
+        ));
+    }
+
+    // The RRSIG's own fields are signed too, everything but the signature
+    let rrsig_bytes = rrsig.data.to_canonical_bytes(RecordType::Rrsig);
+    let rrsig_fields = &rrsig_bytes[..rrsig_bytes.len() - signature.len()];
+
+    let data = signed_data(rrset, rrsig_fields, *labels, *original_ttl);
+    if !verify_signature(*algorithm, public_key, &data, signature) {
+        return Err(bogus(
+            ExtendedErrorCode::DnssecBogus,
+            "the signature doesn't match the records",
+        ));
+    }
+
+    Ok(())
+}
+
+/// The wildcard that an RRset at `owner` was expanded from when its RRSIG has `labels` labels,
+/// if it was expanded from one (RFC 4035 5.3.2)
+pub fn expanded_wildcard(owner: &Name, labels: u8) -> Option<Name> {
+    let count = label_count(owner);
+    if labels as usize >= count {
+        return None;
+    }
+
+    let mut wildcard = vec![b"*".to_vec()];
+    wildcard.extend_from_slice(owner.suffix(count - labels as usize).labels());
+    Name::from_labels(wildcard).ok()
+}
+
+/// The NSEC3 hash of a name: SHA-1 over the name and the salt,
+/// then `iterations` more times over the hash and the salt (RFC 5155 5)
+pub fn nsec3_hash(name: &Name, salt: &[u8], iterations: u16) -> Vec<u8> {
+    let mut hash = name.to_lowercase().to_bytes().to_vec();
+    for _ in 0..=iterations {
+        let mut context = digest::Context::new(&digest::SHA1_FOR_LEGACY_USE_ONLY);
+        context.update(&hash);
+        context.update(salt);
+        hash = context.finish().as_ref().to_vec();
+    }
+
+    hash
+}
+
+fn bogus(code: ExtendedErrorCode, text: &str) -> FailureReason {
+    FailureReason::Bogus(code, text.to_owned())
+}
+
+/// The number of labels in a name, not counting a leading `*` (RFC 4034 3.1.3)
+fn label_count(name: &Name) -> usize {
+    let labels = name.labels();
+    labels.len() - usize::from(labels.first().is_some_and(|label| label == b"*"))
+}
+
+/// Whether `a` is before `b` in serial number arithmetic, which is how signature times
+/// wrap around (RFC 1982 3.2)
+fn serial_lt(a: u32, b: u32) -> bool {
+    a != b && (b.wrapping_sub(a) as i32) > 0
+}
+
+/// What an RRSIG signs: its own fields without the signature,
+/// then the records in canonical form and order (RFC 4034 3.1.8.1)
+fn signed_data(
+    rrset: &[ResourceRecord],
+    rrsig_fields: &[u8],
+    labels: u8,
+    original_ttl: u32,
+) -> Vec<u8> {
+    let mut data = rrsig_fields.to_vec();
+
+    let first = &rrset[0];
+    let owner = first.name.to_lowercase();
+    let owner = expanded_wildcard(&owner, labels)
+        .unwrap_or(owner)
+        .to_bytes();
+
+    let mut rdatas: Vec<_> = rrset
+        .iter()
+        .map(|rr| rr.data.to_canonical_bytes(rr.type_))
+        .collect();
+    rdatas.sort();
+    rdatas.dedup();
+
+    for rdata in rdatas {
+        data.extend_from_slice(&owner);
+        data.extend_from_slice(&first.type_.to_int().to_be_bytes());
+        data.extend_from_slice(&first.class.to_int().to_be_bytes());
+        data.extend_from_slice(&original_ttl.to_be_bytes());
+        data.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        data.extend_from_slice(&rdata);
+    }
+
+    data
+}
+
+fn verify_signature(algorithm: u8, public_key: &[u8], message: &[u8], sig: &[u8]) -> bool {
+    match algorithm {
+        RSASHA256 => {
+            let Some((e, n)) = split_rsa_key(public_key) else {
+                return false;
+            };
+
+            signature::RsaPublicKeyComponents { n, e }
+                .verify(
+                    &signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY,
+                    message,
+                    sig,
+                )
+                .is_ok()
+        }
+        ECDSAP256SHA256 => {
+            // The key is the point's coordinates without the prefix of an uncompressed point
+            let mut key = Vec::with_capacity(1 + public_key.len());
+            key.push(0x04);
+            key.extend_from_slice(public_key);
+
+            signature::UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, key)
+                .verify(message, sig)
+                .is_ok()
+        }
+        ED25519 => signature::UnparsedPublicKey::new(&signature::ED25519, public_key)
+            .verify(message, sig)
+            .is_ok(),
+        _ => false,
+    }
+}
+
+/// Split an RSA key into its exponent and modulus (RFC 3110 2)
+fn split_rsa_key(key: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (exponent_len, rest) = match key.first()? {
+        0 => (
+            u16::from_be_bytes([*key.get(1)?, *key.get(2)?]) as usize,
+            &key[3..],
+        ),
+        len => (*len as usize, &key[1..]),
+    };
+
+    (rest.len() > exponent_len).then(|| rest.split_at(exponent_len))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::slice;
+
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+
+    use super::{
+        ds_matches, expanded_wildcard, nsec3_hash, signed_data, verify_rrset, ZONE_KEY_FLAG,
+    };
+    use crate::text::encode_base32hex;
+    use crate::{
+        parse_zone, CharacterString, DnsClass, ExtendedErrorCode, FailureReason, Name, RecordData,
+        RecordType, ResourceRecord,
+    };
+
+    fn code(result: Result<(), FailureReason>) -> Option<ExtendedErrorCode> {
+        match result {
+            Ok(()) => None,
+            Err(FailureReason::Bogus(code, _)) => Some(code),
+            Err(e) => panic!("unexpected failure {e:?}"),
+        }
+    }
+
+    #[test]
+    fn verifies_ed25519_signatures() {
+        // The example of RFC 8080 6.1
+        let zone = r#"
+example.com. 3600 IN DNSKEY 257 3 15 l02Woi0iS8Aa25FQkUd9RMzZHJpBoRQwAQEX1SxZJA4=
+example.com. 3600 IN DS 3613 15 2 (
+            3aa5ab37efce57f737fc1627013fee07bdf241bd10f3b1964ab55c78e79a304b )
+example.com. 3600 IN MX 10 mail.example.com.
+example.com. 3600 IN RRSIG MX 15 2 3600 1440021600 1438207200 3613 example.com. (
+            oL9krJun7xfBOIWcGHi7mag5/hdZrKWw15jPGrHpjQeRAvTdszaPD+QLs3fx8A4M3e23mRZ9VrbpMngwcrqNAg== )
+"#;
+        let records = parse_zone(zone, &Name::root()).unwrap();
+        let (key, ds, rrsig) = (&records[0], &records[1], &records[3]);
+
+        assert!(ds_matches(&ds.data, key));
+        assert_eq!(
+            code(verify_rrset(&records[2..3], rrsig, key, 1439000000)),
+            None
+        );
+
+        // The signature is only valid between its inception and expiration
+        assert_eq!(
+            code(verify_rrset(&records[2..3], rrsig, key, 1438000000)),
+            Some(ExtendedErrorCode::SignatureNotYetValid)
+        );
+        assert_eq!(
+            code(verify_rrset(&records[2..3], rrsig, key, 1441000000)),
+            Some(ExtendedErrorCode::SignatureExpired)
+        );
+
+        let mut changed = records[2].clone();
+        changed.data = RecordData::Mx {
+            preference: 20,
+            exchange: Name::new("mail.example.com"),
+        };
+        assert_eq!(
+            code(verify_rrset(&[changed], rrsig, key, 1439000000)),
+            Some(ExtendedErrorCode::DnssecBogus)
+        );
+    }
+
+    #[test]
+    fn verifies_rsa_sha256_signatures() {
+        // Records are signed in lowercase and in canonical order, whatever order they're in
+        let zone = r#"
+example.net. 3600 IN DNSKEY 256 3 8 (
+            AwEAAafJ2I0B4Cz3M3icuJqFUFenCWGW1KqOmX6oUQUbiDqLZsxCIYLRuGfDN/45VIEU7o2/
+            5kiCBvswGZfCB3Ykfzxis6PtuPTPGBBHuy/hofgA28VdJpYKd7omOu2HxOMXk8HZSvUIAOQK
+            HQs5OCao3Zqb4WFkyOjTEKb3sdUOg/4WUkxJFow72W3Xc8o1sHU+gHk9nvrsed5tMNQxjB/i
+            QFZzN3IkJS9a/siEvuNUs17IUwZ0v5nWpbDKnEttiGTN4tIvM0WBtU6JNUO/J5s8HkmkET6L
+            8t/w7iBCeVznAlP2mJb7ZgG39UZXxm+R3GsGUZYh4QvpguLwmq+GEMfGWlk= )
+WWW.Example.NET. 3600 IN A 192.0.2.92
+www.example.net. 3600 IN A 192.0.2.91
+www.example.net. 3600 IN RRSIG A 8 3 3600 20300101000000 20000101000000 46743 Example.NET. (
+            b5IIF2wRJ7SqSNY9rHebRPhvgvWVh/MUt3uz3pBXqAP2hS05BaImsrTynEYezZLyjq0KZbsA
+            /NOIIRklY4UOQgLRGJBZRgaEg0UuoH7p2q1GMs6hQOLacwOf8zBLKqCIyYUe7dYraFL1Owww
+            o2+wHwQp/4Yofke4KbQyaN2CAIYEPFR58xeTm8Ubgr8GNfkVWSDIFcp9xVFEI6w8ygZ/Y5d6
+            19KUSIRkDSv4MV8JmQ9W3O5phWrUiSiTgit6frW8k/tjP0qccRyf702/FDOxBwA263HVAC7J
+            0HbWLSIOWOt4WZ97rXjnddZSJcSWLxbhznzG87cPokzyLnKmoAjGaQ== )
+"#;
+        let records = parse_zone(zone, &Name::root()).unwrap();
+        let (key, rrsig) = (&records[0], &records[3]);
+
+        assert_eq!(key.data.key_tag(), Some(46743));
+        assert_eq!(
+            code(verify_rrset(&records[1..3], rrsig, key, 1700000000)),
+            None
+        );
+
+        let reversed = [records[2].clone(), records[1].clone()];
+        assert_eq!(code(verify_rrset(&reversed, rrsig, key, 1700000000)), None);
+
+        assert_eq!(
+            code(verify_rrset(&records[1..2], rrsig, key, 1700000000)),
+            Some(ExtendedErrorCode::DnssecBogus)
+        );
+    }
+
+    #[test]
+    fn verifies_ecdsa_signatures_over_wildcards() {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+            .unwrap();
+
+        let zone = Name::new("example.org");
+        let mut key = ResourceRecord {
+            name: zone.clone(),
+            type_: RecordType::Dnskey,
+            class: DnsClass::In,
+            ttl: 3600,
+            data: RecordData::Dnskey {
+                flags: ZONE_KEY_FLAG,
+                protocol: 3,
+                algorithm: 13,
+                public_key: pair.public_key().as_ref()[1..].to_vec(),
+            },
+        };
+
+        // An answer for a.b.example.org from *.example.org
+        let txt = ResourceRecord {
+            name: Name::new("a.b.example.org"),
+            type_: RecordType::Txt,
+            class: DnsClass::In,
+            ttl: 300,
+            data: RecordData::Txt(vec![CharacterString::new("hello").unwrap()]),
+        };
+        let mut rrsig = ResourceRecord {
+            name: txt.name.clone(),
+            type_: RecordType::Rrsig,
+            class: DnsClass::In,
+            ttl: 300,
+            data: RecordData::Rrsig {
+                type_covered: RecordType::Txt,
+                algorithm: 13,
+                labels: 2,
+                original_ttl: 300,
+                expiration: 2000000000,
+                inception: 1000000000,
+                key_tag: key.data.key_tag().unwrap(),
+                signer: zone,
+                signature: Vec::new(),
+            },
+        };
+
+        let signature = pair
+            .sign(
+                &rng,
+                // Without a signature yet, the whole RRSIG data is signed
+                &signed_data(
+                    slice::from_ref(&txt),
+                    &rrsig.data.to_canonical_bytes(RecordType::Rrsig),
+                    2,
+                    300,
+                ),
+            )
+            .unwrap();
+        if let RecordData::Rrsig { signature: sig, .. } = &mut rrsig.data {
+            *sig = signature.as_ref().to_vec();
+        }
+
+        assert_eq!(
+            expanded_wildcard(&txt.name, 2),
+            Some(Name::new("*.example.org"))
+        );
+        assert_eq!(expanded_wildcard(&txt.name, 4), None);
+        assert_eq!(
+            code(verify_rrset(
+                slice::from_ref(&txt),
+                &rrsig,
+                &key,
+                1500000000
+            )),
+            None
+        );
+
+        // Keys without the zone flag can't sign records
+        if let RecordData::Dnskey { flags, .. } = &mut key.data {
+            *flags = 0;
+        }
+        let new_tag = key.data.key_tag().unwrap();
+        if let RecordData::Rrsig { key_tag, .. } = &mut rrsig.data {
+            *key_tag = new_tag;
+        }
+        assert_eq!(
+            code(verify_rrset(&[txt], &rrsig, &key, 1500000000)),
+            Some(ExtendedErrorCode::NoZoneKeyBitSet)
+        );
+    }
+
+    #[test]
+    fn matches_ds_digests() {
+        // The example of RFC 4034 5.4
+        let zone = r#"
+dskey.example.com. 86400 IN DNSKEY 256 3 5 (
+            AQOeiiR0GOMYkDshWoSKz9XzfwJr1AYtsmx3TGkJaNXVbfi/2pHm822aJ5iI9BMzNXxeYCmZ
+            DRD99WYwYqUSdjMmmAphXdvxegXd/M5+X7OrzKBaMbCVdFLUUh6DhweJBjEVv5f2wwjM9Xzc
+            nOf+EPbtG9DMBmADjFDc2w/rljwvFw== )
+dskey.example.com. 86400 IN DS 60485 5 1 2BB183AF5F22588179A53B0A98631FAD1A292118
+dskey.example.com. 86400 IN DS 60485 5 1 2BB183AF5F22588179A53B0A98631FAD1A292119
+dskey.example.com. 86400 IN DS 60485 5 3 2BB183AF5F22588179A53B0A98631FAD1A292118
+"#;
+        let records = parse_zone(zone, &Name::root()).unwrap();
+
+        assert!(ds_matches(&records[1].data, &records[0]));
+        assert!(!ds_matches(&records[2].data, &records[0]));
+        // Unknown digest types never match
+        assert!(!ds_matches(&records[3].data, &records[0]));
+    }
+
+    #[test]
+    fn hashes_nsec3_names() {
+        // The hashes of RFC 5155 A
+        let salt = [0xaa, 0xbb, 0xcc, 0xdd];
+        for (name, hash) in [
+            ("example", "0p9mhaveqvm6t7vbl5lop2u3t2rp3tom"),
+            ("a.example", "35mthgpgcu1qg68fab165klnsnk3dpvl"),
+            ("*.w.example", "r53bq7cc2uvmubfu5ocmm6pers9tk9en"),
+            ("xx.example", "t644ebqk9bibcna874givr6joj62mlhv"),
+        ] {
+            let hashed = nsec3_hash(&Name::new(name), &salt, 12);
+            assert_eq!(encode_base32hex(&hashed), hash);
+        }
+    }
+}
@@ -0,0 +1,613 @@
+use super::{bogus, nsec3_hash};
+use crate::text::decode_base32hex;
+use crate::{
+    ExtendedErrorCode, FailureReason, Name, RecordData, RecordType, ResourceRecord, TypeBitmap,
+};
+
+/// NSEC3 hashes that take more iterations than this aren't computed,
+/// answers that depend on them are insecure (RFC 9276 3.2)
+pub const MAX_NSEC3_ITERATIONS: u16 = 150;
+
+/// SHA-1, the only NSEC3 hash algorithm (RFC 5155 11)
+const NSEC3_SHA1: u8 = 1;
+
+/// The NSEC3 flag of records that may skip unsigned delegations (RFC 5155 3.1.2.1)
+const OPT_OUT_FLAG: u8 = 0x01;
+
+/// What NSEC or NSEC3 records show about something that doesn't exist
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Denial {
+    /// The records prove it
+    Proven,
+    /// The proof depends on an NSEC3 record that opts out of unsigned delegations,
+    /// or on hashes that are too expensive to check, so the answer is insecure
+    Insecure,
+}
+
+/// Check that `name` doesn't exist, using the NSEC or NSEC3 records of a
+/// name error response (RFC 4035 5.4, RFC 5155 8.4)
+pub fn prove_nonexistence(
+    name: &Name,
+    records: &[ResourceRecord],
+) -> Result<Denial, FailureReason> {
+    match Proof::new(records)? {
+        Proof::Nsec(chain) => chain.nonexistence(name),
+        Proof::Nsec3(chain) => chain.nonexistence(name),
+        Proof::Unusable => Ok(Denial::Insecure),
+    }
+}
+
+/// Check that `name` exists without records of `type_`, using the NSEC or NSEC3 records
+/// of a response without answers (RFC 4035 5.4, RFC 5155 8.5 to 8.7)
+pub fn prove_no_data(
+    name: &Name,
+    type_: RecordType,
+    records: &[ResourceRecord],
+) -> Result<Denial, FailureReason> {
+    match Proof::new(records)? {
+        Proof::Nsec(chain) => chain.no_data(name, type_),
+        Proof::Nsec3(chain) => chain.no_data(name, type_),
+        Proof::Unusable => Ok(Denial::Insecure),
+    }
+}
+
+/// Check that `name` is a delegation without DS records, which makes the zone
+/// below it unsigned (RFC 4035 5.2, RFC 5155 8.9)
+pub fn prove_unsigned_delegation(
+    name: &Name,
+    records: &[ResourceRecord],
+) -> Result<Denial, FailureReason> {
+    match Proof::new(records)? {
+        Proof::Nsec(chain) => chain.unsigned_delegation(name),
+        Proof::Nsec3(chain) => chain.unsigned_delegation(name),
+        Proof::Unusable => Ok(Denial::Insecure),
+    }
+}
+
+/// Check that an answer for `name` expanded from `wildcard` is right,
+/// because `name` itself doesn't exist (RFC 4035 5.3.4, RFC 5155 8.8)
+pub fn prove_wildcard_expansion(
+    name: &Name,
+    wildcard: &Name,
+    records: &[ResourceRecord],
+) -> Result<Denial, FailureReason> {
+    match Proof::new(records)? {
+        Proof::Nsec(chain) => chain.wildcard_expansion(name),
+        Proof::Nsec3(chain) => chain.wildcard_expansion(name, wildcard),
+        Proof::Unusable => Ok(Denial::Insecure),
+    }
+}
+
+fn missing(text: &str) -> FailureReason {
+    bogus(ExtendedErrorCode::NsecMissing, text)
+}
+
+/// The wildcard directly below a name
+fn wildcard_of(name: &Name) -> Result<Name, FailureReason> {
+    let mut labels = vec![b"*".to_vec()];
+    labels.extend_from_slice(name.labels());
+    Name::from_labels(labels).map_err(|_| missing("the wildcard name is too long"))
+}
+
+/// Whether types that exist at a name include `type_` or would answer it with a CNAME,
+/// the side of a delegation that has the name also has to be the one that can deny the type.
+/// The parent side only has authority for DS records, the child side for the rest (RFC 6840 4.1)
+fn denies_type(types: &TypeBitmap, type_: RecordType) -> bool {
+    let wrong_side = if type_ == RecordType::Ds {
+        types.contains(RecordType::Soa)
+    } else {
+        is_delegation(types)
+    };
+
+    !wrong_side && !types.contains(type_) && !types.contains(RecordType::Cname)
+}
+
+/// Whether the types are those of the parent side of a delegation
+fn is_delegation(types: &TypeBitmap) -> bool {
+    types.contains(RecordType::Ns) && !types.contains(RecordType::Soa)
+}
+
+fn proves_unsigned_delegation(types: &TypeBitmap) -> bool {
+    is_delegation(types) && !types.contains(RecordType::Ds)
+}
+
+enum Proof<'a> {
+    Nsec(NsecChain<'a>),
+    Nsec3(Nsec3Chain<'a>),
+    /// NSEC3 records that can't be checked
+    Unusable,
+}
+
+impl<'a> Proof<'a> {
+    fn new(records: &'a [ResourceRecord]) -> Result<Self, FailureReason> {
+        let nsecs: Vec<_> = records
+            .iter()
+            .filter_map(|rr| match &rr.data {
+                RecordData::Nsec { next, types } => Some(Nsec {
+                    owner: &rr.name,
+                    next,
+                    types,
+                }),
+                _ => None,
+            })
+            .collect();
+
+        if !nsecs.is_empty() {
+            return Ok(Self::Nsec(NsecChain(nsecs)));
+        }
+
+        if !records.iter().any(|rr| rr.type_ == RecordType::Nsec3) {
+            return Err(missing("no NSEC or NSEC3 records"));
+        }
+
+        Ok(Nsec3Chain::new(records).map_or(Self::Unusable, Self::Nsec3))
+    }
+}
+
+struct Nsec<'a> {
+    owner: &'a Name,
+    next: &'a Name,
+    types: &'a TypeBitmap,
+}
+
+impl Nsec<'_> {
+    /// Whether the name is between the owner and the next name in canonical order.
+    /// The last NSEC of a zone wraps around to the apex
+    fn covers(&self, name: &Name) -> bool {
+        if self.owner >= name {
+            return false;
+        }
+
+        if self.next > self.owner {
+            name < self.next
+        } else {
+            name.is_subdomain_of(self.next)
+        }
+    }
+
+    /// Whether the NSEC is at a zone cut above the name, where it can't say anything about it
+    fn is_above_cut(&self, name: &Name) -> bool {
+        name != self.owner && name.is_subdomain_of(self.owner) && is_delegation(self.types)
+    }
+}
+
+struct NsecChain<'a>(Vec<Nsec<'a>>);
+
+impl NsecChain<'_> {
+    fn matching(&self, name: &Name) -> Option<&Nsec<'_>> {
+        self.0.iter().find(|nsec| nsec.owner == name)
+    }
+
+    fn covering(&self, name: &Name) -> Option<&Nsec<'_>> {
+        self.0
+            .iter()
+            .find(|nsec| nsec.covers(name) && !nsec.is_above_cut(name))
+    }
+
+    /// The closest encloser of a name that an NSEC covers is the longest name
+    /// above it that's also above the owner or the next name
+    fn closest_encloser(name: &Name, covering: &Nsec<'_>) -> Name {
+        let level = name
+            .matching_level(covering.owner)
+            .max(name.matching_level(covering.next));
+        name.suffix(name.labels().len() - level)
+    }
+
+    fn nonexistence(&self, name: &Name) -> Result<Denial, FailureReason> {
+        let covering = self
+            .covering(name)
+            .ok_or_else(|| missing("no NSEC shows that the name doesn't exist"))?;
+
+        let wildcard = wildcard_of(&Self::closest_encloser(name, covering))?;
+        if self.covering(&wildcard).is_none() {
+            return Err(missing("no NSEC shows that there's no wildcard"));
+        }
+
+        Ok(Denial::Proven)
+    }
+
+    fn no_data(&self, name: &Name, type_: RecordType) -> Result<Denial, FailureReason> {
+        if let Some(nsec) = self.matching(name) {
+            return if denies_type(nsec.types, type_) {
+                Ok(Denial::Proven)
+            } else {
+                Err(missing("the NSEC doesn't deny the type"))
+            };
+        }
+
+        let covering = self
+            .covering(name)
+            .ok_or_else(|| missing("no NSEC shows that the type doesn't exist"))?;
+
+        // An empty non-terminal has no NSEC of its own, but the names below it come right after
+        if covering.next.is_subdomain_of(name) {
+            return Ok(Denial::Proven);
+        }
+
+        // Otherwise the name matches a wildcard that doesn't have the type
+        let wildcard = wildcard_of(&Self::closest_encloser(name, covering))?;
+        match self.matching(&wildcard) {
+            Some(nsec) if denies_type(nsec.types, type_) => Ok(Denial::Proven),
+            _ => Err(missing("no NSEC shows that the type doesn't exist")),
+        }
+    }
+
+    fn unsigned_delegation(&self, name: &Name) -> Result<Denial, FailureReason> {
+        match self.matching(name) {
+            Some(nsec) if proves_unsigned_delegation(nsec.types) => Ok(Denial::Proven),
+            _ => Err(missing("no NSEC shows that the delegation is unsigned")),
+        }
+    }
+
+    fn wildcard_expansion(&self, name: &Name) -> Result<Denial, FailureReason> {
+        match self.covering(name) {
+            Some(_) => Ok(Denial::Proven),
+            None => Err(missing("no NSEC shows that the name didn't exist")),
+        }
+    }
+}
+
+struct Nsec3<'a> {
+    hash: Vec<u8>,
+    next: &'a [u8],
+    flags: u8,
+    types: &'a TypeBitmap,
+}
+
+impl Nsec3<'_> {
+    /// Whether the hash is between the owner's hash and the next one.
+    /// The last NSEC3 of a zone wraps around to the first
+    fn covers(&self, hash: &[u8]) -> bool {
+        if self.next > self.hash.as_slice() {
+            self.hash.as_slice() < hash && hash < self.next
+        } else {
+            self.hash.as_slice() < hash || hash < self.next
+        }
+    }
+
+    fn opts_out(&self) -> bool {
+        self.flags & OPT_OUT_FLAG != 0
+    }
+}
+
+/// The NSEC3 records of one zone with the same hash parameters
+struct Nsec3Chain<'a> {
+    zone: Name,
+    salt: &'a [u8],
+    iterations: u16,
+    records: Vec<Nsec3<'a>>,
+}
+
+impl<'a> Nsec3Chain<'a> {
+    /// The chain of the first record with a known hash algorithm, other records are ignored
+    /// (RFC 5155 8.1). There's no chain to check if no record can be used
+    fn new(records: &'a [ResourceRecord]) -> Option<Self> {
+        let mut chain: Option<Self> = None;
+
+        for rr in records {
+            let RecordData::Nsec3 {
+                hash_algorithm: NSEC3_SHA1,
+                flags,
+                iterations,
+                salt,
+                next_hashed,
+                types,
+            } = &rr.data
+            else {
+                continue;
+            };
+
+            let (Some(label), Some(zone)) = (rr.name.labels().first(), rr.name.parent()) else {
+                continue;
+            };
+            let Ok(hash) = std::str::from_utf8(label)
+                .map_err(|_| ())
+                .and_then(|label| decode_base32hex(label).map_err(|_| ()))
+            else {
+                continue;
+            };
+
+            let chain = chain.get_or_insert_with(|| Self {
+                zone,
+                salt,
+                iterations: *iterations,
+                records: Vec::new(),
+            });
+            if rr.name.parent().as_ref() != Some(&chain.zone)
+                || chain.salt != salt.as_slice()
+                || chain.iterations != *iterations
+            {
+                continue;
+            }
+
+            chain.records.push(Nsec3 {
+                hash,
+                next: next_hashed,
+                flags: *flags,
+                types,
+            });
+        }
+
+        chain.filter(|chain| chain.iterations <= MAX_NSEC3_ITERATIONS)
+    }
+
+    fn hash(&self, name: &Name) -> Vec<u8> {
+        nsec3_hash(name, self.salt, self.iterations)
+    }
+
+    fn matching(&self, name: &Name) -> Option<&Nsec3<'a>> {
+        let hash = self.hash(name);
+        self.records.iter().find(|record| record.hash == hash)
+    }
+
+    fn covering(&self, name: &Name) -> Option<&Nsec3<'a>> {
+        let hash = self.hash(name);
+        self.records.iter().find(|record| record.covers(&hash))
+    }
+
+    /// The closest encloser of the name, the longest name above it that exists,
+    /// and the record that covers the next closer name below that (RFC 5155 8.3)
+    fn closest_encloser(&self, name: &Name) -> Result<(Name, &Nsec3<'a>), FailureReason> {
+        if !name.is_subdomain_of(&self.zone) {
+            return Err(missing("the NSEC3 records are from another zone"));
+        }
+
+        let depth = name.labels().len() - self.zone.labels().len();
+        for start in 1..=depth {
+            let encloser = name.suffix(start);
+            let Some(record) = self.matching(&encloser) else {
+                continue;
+            };
+
+            // Nothing below a delegation is in this zone
+            if is_delegation(record.types) {
+                return Err(missing("the closest encloser is a delegation"));
+            }
+
+            let next_closer = name.suffix(start - 1);
+            let covering = self
+                .covering(&next_closer)
+                .ok_or_else(|| missing("no NSEC3 covers the next closer name"))?;
+
+            return Ok((encloser, covering));
+        }
+
+        Err(missing("no NSEC3 matches a closest encloser"))
+    }
+
+    fn nonexistence(&self, name: &Name) -> Result<Denial, FailureReason> {
+        if self.matching(name).is_some() {
+            return Err(missing("an NSEC3 shows that the name exists"));
+        }
+
+        let (encloser, next_closer) = self.closest_encloser(name)?;
+        if self.covering(&wildcard_of(&encloser)?).is_none() {
+            return Err(missing("no NSEC3 shows that there's no wildcard"));
+        }
+
+        if next_closer.opts_out() {
+            Ok(Denial::Insecure)
+        } else {
+            Ok(Denial::Proven)
+        }
+    }
+
+    fn no_data(&self, name: &Name, type_: RecordType) -> Result<Denial, FailureReason> {
+        if let Some(record) = self.matching(name) {
+            return if denies_type(record.types, type_) {
+                Ok(Denial::Proven)
+            } else {
+                Err(missing("the NSEC3 doesn't deny the type"))
+            };
+        }
+
+        let (encloser, next_closer) = self.closest_encloser(name)?;
+
+        // A DS record is only missing without a matching NSEC3 if an opt-out record covers it
+        if type_ == RecordType::Ds {
+            return if next_closer.opts_out() {
+                Ok(Denial::Insecure)
+            } else {
+                Err(missing("no NSEC3 shows that the DS record doesn't exist"))
+            };
+        }
+
+        match self.matching(&wildcard_of(&encloser)?) {
+            Some(record) if denies_type(record.types, type_) => Ok(Denial::Proven),
+            _ => Err(missing("no NSEC3 shows that the type doesn't exist")),
+        }
+    }
+
+    fn unsigned_delegation(&self, name: &Name) -> Result<Denial, FailureReason> {
+        if let Some(record) = self.matching(name) {
+            return if proves_unsigned_delegation(record.types) {
+                Ok(Denial::Proven)
+            } else {
+                Err(missing("the NSEC3 doesn't show an unsigned delegation"))
+            };
+        }
+
+        let (_, next_closer) = self.closest_encloser(name)?;
+        if next_closer.opts_out() {
+            Ok(Denial::Insecure)
+        } else {
+            Err(missing("no NSEC3 shows that the delegation is unsigned"))
+        }
+    }
+
+    fn wildcard_expansion(&self, name: &Name, wildcard: &Name) -> Result<Denial, FailureReason> {
+        let encloser_labels = wildcard.labels().len() - 1;
+        if name.labels().len() <= encloser_labels {
+            return Err(missing("the name isn't below the wildcard"));
+        }
+
+        let next_closer = name.suffix(name.labels().len() - encloser_labels - 1);
+        match self.covering(&next_closer) {
+            Some(_) => Ok(Denial::Proven),
+            None => Err(missing("no NSEC3 shows that the name didn't exist")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        prove_no_data, prove_nonexistence, prove_unsigned_delegation, prove_wildcard_expansion,
+        Denial,
+    };
+    use crate::{parse_zone, FailureReason, Name, RecordType, ResourceRecord};
+
+    fn records(zone: &str) -> Vec<ResourceRecord> {
+        parse_zone(&format!("$TTL 3600\n{zone}"), &Name::root()).unwrap()
+    }
+
+    fn name(name: &str) -> Name {
+        Name::new(name)
+    }
+
+    #[test]
+    fn proves_denials_with_nsec() {
+        // The examples of RFC 4035 B
+        let nxdomain = records(
+            r#"
+b.example. NSEC ns1.example. NS RRSIG NSEC
+example. NSEC a.example. NS SOA MX RRSIG NSEC DNSKEY
+"#,
+        );
+        assert_eq!(
+            prove_nonexistence(&name("ml.example"), &nxdomain),
+            Ok(Denial::Proven)
+        );
+        // Without the second record a wildcard could exist
+        assert!(prove_nonexistence(&name("ml.example"), &nxdomain[..1]).is_err());
+        // b.example is a delegation, so its NSEC says nothing about names below it
+        assert!(prove_nonexistence(&name("c.b.example"), &nxdomain).is_err());
+
+        let no_data = records("ns1.example. NSEC ns2.example. A RRSIG NSEC");
+        assert_eq!(
+            prove_no_data(&name("ns1.example"), RecordType::Mx, &no_data),
+            Ok(Denial::Proven)
+        );
+        assert!(prove_no_data(&name("ns1.example"), RecordType::A, &no_data).is_err());
+
+        // y.example is an empty non-terminal
+        let empty = records("a.example. NSEC x.y.example. NS DS RRSIG NSEC");
+        assert_eq!(
+            prove_no_data(&name("y.example"), RecordType::A, &empty),
+            Ok(Denial::Proven)
+        );
+        // The parent side of a delegation can't deny the child's types
+        assert!(prove_no_data(&name("a.example"), RecordType::Txt, &empty).is_err());
+        assert!(prove_unsigned_delegation(&name("a.example"), &empty).is_err());
+        assert_eq!(
+            prove_unsigned_delegation(&name("b.example"), &nxdomain),
+            Ok(Denial::Proven)
+        );
+
+        let wildcard = records(
+            r#"
+x.y.w.example. NSEC xx.example. MX RRSIG NSEC
+*.w.example. NSEC x.w.example. MX RRSIG NSEC
+"#,
+        );
+        assert_eq!(
+            prove_wildcard_expansion(&name("a.z.w.example"), &name("*.w.example"), &wildcard),
+            Ok(Denial::Proven)
+        );
+        assert_eq!(
+            prove_no_data(&name("a.z.w.example"), RecordType::Aaaa, &wildcard),
+            Ok(Denial::Proven)
+        );
+        assert!(prove_no_data(&name("a.z.w.example"), RecordType::Mx, &wildcard).is_err());
+    }
+
+    #[test]
+    fn proves_denials_with_nsec3() {
+        // The examples of RFC 5155 B, which opt out of unsigned delegations
+        let nxdomain = r#"
+0p9mhaveqvm6t7vbl5lop2u3t2rp3tom.example. NSEC3 1 FLAGS 12 aabbccdd (
+    2t7b4g4vsa5smi47k61mv5bv1a22bojr MX DNSKEY NS SOA NSEC3PARAM RRSIG )
+b4um86eghhds6nea196smvmlo4ors995.example. NSEC3 1 FLAGS 12 aabbccdd (
+    gjeqe526plbf1g8mklp59enfd789njgi MX RRSIG )
+35mthgpgcu1qg68fab165klnsnk3dpvl.example. NSEC3 1 FLAGS 12 aabbccdd (
+    b4um86eghhds6nea196smvmlo4ors995 NS DS RRSIG )
+"#;
+        let opt_out = records(&nxdomain.replace("FLAGS", "1"));
+        let signed = records(&nxdomain.replace("FLAGS", "0"));
+        assert_eq!(
+            prove_nonexistence(&name("a.c.x.w.example"), &opt_out),
+            Ok(Denial::Insecure)
+        );
+        assert_eq!(
+            prove_nonexistence(&name("a.c.x.w.example"), &signed),
+            Ok(Denial::Proven)
+        );
+        // The closest encloser exists
+        assert!(prove_nonexistence(&name("x.w.example"), &signed).is_err());
+
+        // c.example is an unsigned delegation that's only covered by an opt-out record
+        assert_eq!(
+            prove_unsigned_delegation(&name("c.example"), &opt_out),
+            Ok(Denial::Insecure)
+        );
+        assert!(prove_unsigned_delegation(&name("c.example"), &signed).is_err());
+
+        let no_data = records(
+            r#"
+2t7b4g4vsa5smi47k61mv5bv1a22bojr.example. NSEC3 1 1 12 aabbccdd (
+    2vptu5timamqttgl4luu9kg21e0aor3s A RRSIG )
+ji6neoaepv8b5o6k4ev33abha8ht9fgc.example. NSEC3 1 1 12 aabbccdd (
+    k8udemvp1j2f7eg6jebps17vp3n8i58h )
+"#,
+        );
+        assert_eq!(
+            prove_no_data(&name("ns1.example"), RecordType::Mx, &no_data),
+            Ok(Denial::Proven)
+        );
+        assert!(prove_no_data(&name("ns1.example"), RecordType::A, &no_data).is_err());
+        assert_eq!(
+            prove_no_data(&name("y.w.example"), RecordType::A, &no_data),
+            Ok(Denial::Proven)
+        );
+
+        let wildcard = records(
+            r#"
+k8udemvp1j2f7eg6jebps17vp3n8i58h.example. NSEC3 1 1 12 aabbccdd (
+    kohar7mbb8dc2ce8a9qvl8hon4k53uhi )
+q04jkcevqvmu85r014c7dkba38o0ji5r.example. NSEC3 1 1 12 aabbccdd (
+    r53bq7cc2uvmubfu5ocmm6pers9tk9en A RRSIG )
+r53bq7cc2uvmubfu5ocmm6pers9tk9en.example. NSEC3 1 1 12 aabbccdd (
+    t644ebqk9bibcna874givr6joj62mlhv MX RRSIG )
+"#,
+        );
+        assert_eq!(
+            prove_wildcard_expansion(&name("a.z.w.example"), &name("*.w.example"), &wildcard),
+            Ok(Denial::Proven)
+        );
+        assert_eq!(
+            prove_no_data(&name("a.z.w.example"), RecordType::Aaaa, &wildcard),
+            Ok(Denial::Proven)
+        );
+        assert!(prove_no_data(&name("a.z.w.example"), RecordType::Mx, &wildcard).is_err());
+    }
+
+    #[test]
+    fn gives_up_on_unusable_proofs() {
+        // Hashes with too many iterations aren't checked (RFC 9276 3.2)
+        let expensive = records(
+            r#"
+0p9mhaveqvm6t7vbl5lop2u3t2rp3tom.example. NSEC3 1 0 500 aabbccdd (
+    2t7b4g4vsa5smi47k61mv5bv1a22bojr A RRSIG )
+"#,
+        );
+        assert_eq!(
+            prove_nonexistence(&name("a.example"), &expensive),
+            Ok(Denial::Insecure)
+        );
+
+        assert!(matches!(
+            prove_nonexistence(&name("a.example"), &[]),
+            Err(FailureReason::Bogus(..))
+        ));
+    }
+}
@@ -0,0 +1,258 @@
+use std::fmt::Display;
+use std::io::Cursor;
+
+use bitfield::bitfield;
+use bytes::Buf;
+
+use crate::encode::Sink;
+use crate::{DnsClass, DnsError, Name, RecordData, RecordType, ResourceRecord};
+
+mod client_subnet;
+pub use client_subnet::ClientSubnet;
+
+mod cookie;
+pub use cookie::{Cookie, CLIENT_COOKIE_LENGTH};
+
+mod extended_error;
+pub use extended_error::{ExtendedError, ExtendedErrorCode};
+
+mod option;
+pub use option::EdnsOption;
+
+/// The smallest payload size a client can advertise, smaller values are treated as this
+pub const MIN_UDP_PAYLOAD_SIZE: u16 = 512;
+
+bitfield! {
+    #[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+    pub struct EdnsFlags(u16);
+    impl Debug;
+    // DNSSEC OK (RFC 3225)
+    pub dnssec_ok, set_dnssec_ok: 15;
+    // reserved
+    pub z, set_z: 14, 0;
+}
+
+/// The OPT pseudo-record (RFC 6891)
+///
+/// It's kept out of the additional section of a `Message`
+/// and written as the last additional record when the message is encoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edns {
+    /// The largest UDP payload the sender can reassemble
+    pub udp_payload_size: u16,
+    /// The upper 8 bits of the 12 bit response code
+    pub extended_rcode: u8,
+    pub version: u8,
+    pub flags: EdnsFlags,
+    pub options: Vec<EdnsOption>,
+}
+
+impl Edns {
+    pub fn new(udp_payload_size: u16) -> Self {
+        Self {
+            udp_payload_size,
+            extended_rcode: 0,
+            version: 0,
+            flags: EdnsFlags::default(),
+            options: Vec::new(),
+        }
+    }
+
+    /// The payload size to use when sending to whoever sent this record
+    pub fn max_payload_size(&self) -> u16 {
+        self.udp_payload_size.max(MIN_UDP_PAYLOAD_SIZE)
+    }
+
+    pub fn option(&self, code: u16) -> Option<&EdnsOption> {
+        self.options.iter().find(|option| option.code() == code)
+    }
+
+    pub fn client_subnet(&self) -> Option<&ClientSubnet> {
+        self.options.iter().find_map(|option| match option {
+            EdnsOption::ClientSubnet(subnet) => Some(subnet),
+            _ => None,
+        })
+    }
+
+    pub fn cookie(&self) -> Option<&Cookie> {
+        self.options.iter().find_map(|option| match option {
+            EdnsOption::Cookie(cookie) => Some(cookie),
+            _ => None,
+        })
+    }
+
+    /// The extended errors in the message, there can be more than one (RFC 8914 2)
+    pub fn extended_errors(&self) -> impl Iterator<Item = &ExtendedError> {
+        self.options.iter().filter_map(|option| match option {
+            EdnsOption::ExtendedError(error) => Some(error),
+            _ => None,
+        })
+    }
+
+    /// Read the fields of an OPT record that was parsed like any other record
+    pub fn from_record(record: ResourceRecord) -> Result<Self, DnsError> {
+        if record.type_ != RecordType::Opt || !record.name.is_root() {
+            return Err(DnsError::FormatError);
+        }
+
+        let RecordData::Unknown(data) = record.data else {
+            return Err(DnsError::FormatError);
+        };
+
+        let mut options = Vec::new();
+        let mut cursor = Cursor::new(&data[..]);
+        while cursor.has_remaining() {
+            options.push(EdnsOption::from_bytes(&mut cursor)?);
+        }
+
+        let [extended_rcode, version, flags @ ..] = record.ttl.to_be_bytes();
+
+        Ok(Self {
+            udp_payload_size: record.class.to_int(),
+            extended_rcode,
+            version,
+            flags: EdnsFlags(u16::from_be_bytes(flags)),
+            options,
+        })
+    }
+
+    /// The OPT record as a plain record, the inverse of `from_record`
+    pub fn to_record(&self) -> ResourceRecord {
+        let mut data = Vec::new();
+        for option in self.options.iter() {
+            option.write(&mut data);
+        }
+
+        let [flags_high, flags_low] = self.flags.0.to_be_bytes();
+        ResourceRecord {
+            name: Name::root(),
+            type_: RecordType::Opt,
+            class: DnsClass::from_int(self.udp_payload_size),
+            ttl: u32::from_be_bytes([self.extended_rcode, self.version, flags_high, flags_low]),
+            data: RecordData::Unknown(data),
+        }
+    }
+
+    /// Write the OPT record, it never has a name to compress
+    pub(crate) fn write(&self, buf: &mut impl Sink) {
+        // Root name
+        buf.put_u8(0);
+        buf.put_u16(RecordType::Opt.to_int());
+        buf.put_u16(self.udp_payload_size);
+        buf.put_u8(self.extended_rcode);
+        buf.put_u8(self.version);
+        buf.put_u16(self.flags.0);
+
+        let data_len: usize = self.options.iter().map(EdnsOption::len).sum();
+        buf.put_u16(data_len as u16);
+        for option in self.options.iter() {
+            option.write(buf);
+        }
+    }
+}
+
+impl Display for Edns {
+    /// The lines of dig's OPT pseudo-section
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let flags = if self.flags.dnssec_ok() { " do" } else { "" };
+        writeln!(
+            f,
+            "; EDNS: version: {}, flags:{}; udp: {}",
+            self.version, flags, self.udp_payload_size
+        )?;
+
+        for option in self.options.iter() {
+            writeln!(f, "; {option}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use bytes::BytesMut;
+
+    use crate::{
+        DnsError, Edns, EdnsOption, Header, Message, Name, Networkable, Question, RecordType,
+        ResourceRecord,
+    };
+
+    fn edns() -> Edns {
+        let mut edns = Edns::new(1232);
+        edns.extended_rcode = 1;
+        edns.flags.set_dnssec_ok(true);
+        edns.options.push(EdnsOption::Unknown {
+            code: 65001,
+            data: vec![1, 2, 3],
+        });
+        edns
+    }
+
+    #[test]
+    fn encodes_opt_record() {
+        let mut buf = BytesMut::new();
+        edns().write(&mut buf);
+
+        let expected: &[u8] = &[
+            0, 0, 41, 0x04, 0xD0, 1, 0, 0x80, 0, 0, 7, 0xFD, 0xE9, 0, 3, 1, 2, 3,
+        ];
+        assert_eq!(&buf[..], expected);
+
+        let record = ResourceRecord::from_bytes(&mut Cursor::new(&buf[..])).unwrap();
+        assert_eq!(Edns::from_record(record).unwrap(), edns());
+    }
+
+    #[test]
+    fn keeps_opt_out_of_additionals() {
+        let mut message = Message::new(Header::new(1, Default::default()));
+        message.add_question(Question::new(Name::new("example.com"), RecordType::A));
+        message.edns = Some(edns());
+
+        let bytes = message.to_bytes();
+        // The OPT record is counted in the additionals on the wire
+        assert_eq!(bytes[11], 1);
+
+        let parsed = Message::from_bytes(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(parsed.edns, Some(edns()));
+        assert!(parsed.additionals.is_empty());
+        assert_eq!(parsed.header.num_additionals, 0);
+        assert_eq!(parsed.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn rejects_invalid_opt_records() {
+        let mut message = Message::new(Header::new(1, Default::default()));
+        message.edns = Some(edns());
+
+        // Two OPT records
+        let mut bytes = message.to_bytes().to_vec();
+        let mut opt = BytesMut::new();
+        edns().write(&mut opt);
+        bytes.extend_from_slice(&opt);
+        bytes[11] = 2;
+        let result = Message::from_bytes(&mut Cursor::new(&bytes));
+        assert!(matches!(result, Err(DnsError::FormatError)));
+
+        // An OPT record that isn't owned by the root
+        let mut bytes = message.to_bytes().to_vec();
+        bytes.splice(12..13, [1, b'a', 0]);
+        let result = Message::from_bytes(&mut Cursor::new(&bytes));
+        assert!(matches!(result, Err(DnsError::FormatError)));
+
+        // An option that runs past the record data
+        let mut bytes = message.to_bytes().to_vec();
+        let last = bytes.len() - 4;
+        bytes[last] = 4;
+        let result = Message::from_bytes(&mut Cursor::new(&bytes));
+        assert!(matches!(result, Err(DnsError::FormatError)));
+    }
+
+    #[test]
+    fn treats_small_payload_sizes_as_minimum() {
+        assert_eq!(Edns::new(100).max_payload_size(), 512);
+        assert_eq!(Edns::new(4096).max_payload_size(), 4096);
+    }
+}
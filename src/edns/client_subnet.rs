@@ -0,0 +1,207 @@
+use std::io::Cursor;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::encode::Sink;
+use crate::read::CheckedBuf;
+use crate::DnsError;
+
+const FAMILY_IPV4: u16 = 1;
+const FAMILY_IPV6: u16 = 2;
+
+/// The EDNS Client Subnet option (RFC 7871)
+///
+/// `address` never has bits set past `source_prefix`
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct ClientSubnet {
+    pub address: IpAddr,
+    /// How many bits of the address the sender revealed
+    pub source_prefix: u8,
+    /// How many bits of the address the answer depends on, 0 in queries
+    pub scope_prefix: u8,
+}
+
+impl ClientSubnet {
+    /// The subnet made of the first `source_prefix` bits of `address`,
+    /// the prefix is clamped to the length of the address
+    pub fn new(address: IpAddr, source_prefix: u8) -> Self {
+        let source_prefix = source_prefix.min(max_prefix(address));
+
+        Self {
+            address: truncate(address, source_prefix),
+            source_prefix,
+            scope_prefix: 0,
+        }
+    }
+
+    /// The address family number (1 for ipv4, 2 for ipv6)
+    pub fn family(&self) -> u16 {
+        match self.address {
+            IpAddr::V4(_) => FAMILY_IPV4,
+            IpAddr::V6(_) => FAMILY_IPV6,
+        }
+    }
+
+    /// Whether `address` is in the network given by the first `prefix` bits of this subnet
+    pub fn contains(&self, address: IpAddr, prefix: u8) -> bool {
+        if self.address.is_ipv4() != address.is_ipv4() {
+            return false;
+        }
+
+        truncate(self.address, prefix) == truncate(address, prefix)
+    }
+
+    /// Whether `address` is in the network that the answer was scoped to
+    pub fn scope_contains(&self, address: IpAddr) -> bool {
+        self.contains(address, self.scope_prefix)
+    }
+
+    pub(crate) fn from_bytes(bytes: &mut Cursor<&[u8]>, length: u16) -> Result<Self, DnsError> {
+        let family = bytes.read_u16()?;
+        let source_prefix = bytes.read_u8()?;
+        let scope_prefix = bytes.read_u8()?;
+
+        // Only as many address bytes as the source prefix needs are sent
+        let address_len = (source_prefix as usize).div_ceil(8);
+        if length as usize != 4 + address_len {
+            return Err(DnsError::FormatError);
+        }
+        let address = bytes.read_bytes(address_len)?;
+
+        let address = match family {
+            FAMILY_IPV4 if address_len <= 4 => {
+                let mut octets = [0; 4];
+                octets[..address_len].copy_from_slice(&address);
+                IpAddr::V4(Ipv4Addr::from(octets))
+            }
+            FAMILY_IPV6 if address_len <= 16 => {
+                let mut octets = [0; 16];
+                octets[..address_len].copy_from_slice(&address);
+                IpAddr::V6(Ipv6Addr::from(octets))
+            }
+            _ => return Err(DnsError::FormatError),
+        };
+
+        if scope_prefix > max_prefix(address) {
+            return Err(DnsError::FormatError);
+        }
+
+        // Bits past the source prefix have to be zero (RFC 7871 6)
+        if truncate(address, source_prefix) != address {
+            return Err(DnsError::FormatError);
+        }
+
+        Ok(Self {
+            address,
+            source_prefix,
+            scope_prefix,
+        })
+    }
+
+    /// The length of the option data
+    pub(crate) fn len(&self) -> usize {
+        4 + (self.source_prefix as usize).div_ceil(8)
+    }
+
+    pub(crate) fn write(&self, buf: &mut impl Sink) {
+        let address_len = (self.source_prefix as usize).div_ceil(8);
+
+        buf.put_u16(self.family());
+        buf.put_u8(self.source_prefix);
+        buf.put_u8(self.scope_prefix);
+        match self.address {
+            IpAddr::V4(address) => buf.put_slice(&address.octets()[..address_len]),
+            IpAddr::V6(address) => buf.put_slice(&address.octets()[..address_len]),
+        }
+    }
+}
+
+fn max_prefix(address: IpAddr) -> u8 {
+    match address {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+/// Clear every bit of `address` after the first `prefix` bits
+fn truncate(address: IpAddr, prefix: u8) -> IpAddr {
+    match address {
+        IpAddr::V4(address) => {
+            let mask = u32::MAX
+                .checked_shl(32 - prefix.min(32) as u32)
+                .unwrap_or(0);
+            IpAddr::V4((u32::from(address) & mask).into())
+        }
+        IpAddr::V6(address) => {
+            let mask = u128::MAX
+                .checked_shl(128 - prefix.min(128) as u32)
+                .unwrap_or(0);
+            IpAddr::V6((u128::from(address) & mask).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use crate::edns::option::tests::parse;
+    use crate::{ClientSubnet, EdnsOption};
+
+    #[test]
+    fn encodes_truncated_address() {
+        let subnet = ClientSubnet::new("192.0.2.123".parse().unwrap(), 24);
+        assert_eq!(subnet.address, "192.0.2.0".parse::<IpAddr>().unwrap());
+
+        let mut buf = Vec::new();
+        EdnsOption::ClientSubnet(subnet).write(&mut buf);
+        assert_eq!(buf, [0, 8, 0, 7, 0, 1, 24, 0, 192, 0, 2]);
+        assert_eq!(parse(&buf), Some(EdnsOption::ClientSubnet(subnet)));
+
+        let subnet = ClientSubnet::new("2001:db8:ffff::1".parse().unwrap(), 36);
+        assert_eq!(subnet.address, "2001:db8:f000::".parse::<IpAddr>().unwrap());
+
+        let mut buf = Vec::new();
+        EdnsOption::ClientSubnet(subnet).write(&mut buf);
+        assert_eq!(buf, [0, 8, 0, 9, 0, 2, 36, 0, 0x20, 0x01, 0x0D, 0xB8, 0xF0]);
+        assert_eq!(parse(&buf), Some(EdnsOption::ClientSubnet(subnet)));
+    }
+
+    #[test]
+    fn parses_scope() {
+        let option = parse(&[0, 8, 0, 7, 0, 1, 24, 16, 10, 1, 2]).unwrap();
+        let EdnsOption::ClientSubnet(subnet) = option else {
+            panic!("expected a client subnet");
+        };
+
+        assert_eq!(subnet.scope_prefix, 16);
+        assert!(subnet.scope_contains("10.1.200.1".parse().unwrap()));
+        assert!(!subnet.scope_contains("10.2.2.1".parse().unwrap()));
+        assert!(!subnet.scope_contains("::a01:0".parse().unwrap()));
+    }
+
+    #[test]
+    fn rejects_invalid_options() {
+        // Address longer than the prefix needs
+        assert_eq!(parse(&[0, 8, 0, 8, 0, 1, 16, 0, 10, 1, 2, 0]), None);
+        // Address shorter than the prefix needs
+        assert_eq!(parse(&[0, 8, 0, 6, 0, 1, 24, 0, 10, 1]), None);
+        // Bits set past the prefix
+        assert_eq!(parse(&[0, 8, 0, 7, 0, 1, 20, 0, 10, 1, 0xFF]), None);
+        // Prefix longer than an ipv4 address
+        assert_eq!(parse(&[0, 8, 0, 9, 0, 1, 40, 0, 10, 1, 2, 3, 4]), None);
+        // Scope longer than an ipv4 address
+        assert_eq!(parse(&[0, 8, 0, 7, 0, 1, 24, 33, 10, 1, 2]), None);
+        // Unknown family
+        assert_eq!(parse(&[0, 8, 0, 4, 0, 3, 0, 0]), None);
+    }
+
+    #[test]
+    fn clamps_prefix_to_address_length() {
+        let subnet = ClientSubnet::new("10.0.0.1".parse().unwrap(), 64);
+        assert_eq!(subnet.source_prefix, 32);
+        assert_eq!(subnet.address, "10.0.0.1".parse::<IpAddr>().unwrap());
+
+        let subnet = ClientSubnet::new("10.0.0.1".parse().unwrap(), 0);
+        assert_eq!(subnet.address, "0.0.0.0".parse::<IpAddr>().unwrap());
+    }
+}
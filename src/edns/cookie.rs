@@ -0,0 +1,83 @@
+use std::io::Cursor;
+
+use crate::encode::Sink;
+use crate::read::CheckedBuf;
+use crate::DnsError;
+
+/// Length of the client cookie
+pub const CLIENT_COOKIE_LENGTH: usize = 8;
+
+/// The DNS Cookie option (RFC 7873)
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct Cookie {
+    pub client: [u8; CLIENT_COOKIE_LENGTH],
+    /// Between 8 and 32 bytes, `None` until the client has learned one from the server
+    pub server: Option<Vec<u8>>,
+}
+
+impl Cookie {
+    pub fn new(client: [u8; CLIENT_COOKIE_LENGTH], server: Option<Vec<u8>>) -> Self {
+        Self { client, server }
+    }
+
+    pub(crate) fn from_bytes(bytes: &mut Cursor<&[u8]>, length: u16) -> Result<Self, DnsError> {
+        // A client cookie alone, or with a server cookie of 8 to 32 bytes (RFC 7873 4)
+        let server_length = match length as usize {
+            CLIENT_COOKIE_LENGTH => 0,
+            length @ 16..=40 => length - CLIENT_COOKIE_LENGTH,
+            _ => return Err(DnsError::FormatError),
+        };
+
+        let mut client = [0; CLIENT_COOKIE_LENGTH];
+        client.copy_from_slice(&bytes.read_bytes(CLIENT_COOKIE_LENGTH)?);
+
+        let server = match server_length {
+            0 => None,
+            length => Some(bytes.read_bytes(length)?.to_vec()),
+        };
+
+        Ok(Self { client, server })
+    }
+
+    /// The length of the option data
+    pub(crate) fn len(&self) -> usize {
+        CLIENT_COOKIE_LENGTH + self.server.as_ref().map_or(0, Vec::len)
+    }
+
+    pub(crate) fn write(&self, buf: &mut impl Sink) {
+        buf.put_slice(&self.client);
+        if let Some(server) = &self.server {
+            buf.put_slice(server);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::edns::option::tests::parse;
+    use crate::{Cookie, EdnsOption};
+
+    #[test]
+    fn round_trips_cookies() {
+        let client_only = EdnsOption::Cookie(Cookie::new([1; 8], None));
+        let mut buf = Vec::new();
+        client_only.write(&mut buf);
+        assert_eq!(buf, [0, 10, 0, 8, 1, 1, 1, 1, 1, 1, 1, 1]);
+        assert_eq!(parse(&buf), Some(client_only));
+
+        let both = EdnsOption::Cookie(Cookie::new([1; 8], Some(vec![2; 16])));
+        let mut buf = Vec::new();
+        both.write(&mut buf);
+        assert_eq!(buf[..4], [0, 10, 0, 24]);
+        assert_eq!(parse(&buf), Some(both));
+    }
+
+    #[test]
+    fn rejects_invalid_lengths() {
+        for length in [0, 7, 9, 15, 41] {
+            let mut buf = vec![0, 10, 0, length as u8];
+            buf.extend(std::iter::repeat_n(1, length));
+            assert_eq!(parse(&buf), None, "{length}");
+        }
+    }
+}
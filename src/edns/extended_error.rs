@@ -0,0 +1,185 @@
+use std::fmt::Display;
+use std::io::Cursor;
+
+use crate::encode::Sink;
+use crate::read::CheckedBuf;
+use crate::DnsError;
+
+macro_rules! info_codes {
+    ($($variant:ident = $value:literal => $purpose:literal,)*) => {
+        /// The INFO-CODE of an extended dns error (RFC 8914 5)
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+        pub enum ExtendedErrorCode {
+            $($variant,)*
+            Unknown(u16),
+        }
+
+        impl ExtendedErrorCode {
+            pub fn to_int(&self) -> u16 {
+                match self {
+                    $(Self::$variant => $value,)*
+                    Self::Unknown(v) => *v,
+                }
+            }
+
+            pub fn from_int(v: u16) -> Self {
+                match v {
+                    $($value => Self::$variant,)*
+                    v => Self::Unknown(v),
+                }
+            }
+
+            fn purpose(&self) -> Option<&'static str> {
+                match self {
+                    $(Self::$variant => Some($purpose),)*
+                    Self::Unknown(_) => None,
+                }
+            }
+        }
+    };
+}
+
+info_codes! {
+    Other = 0 => "Other Error",
+    UnsupportedDnskeyAlgorithm = 1 => "Unsupported DNSKEY Algorithm",
+    UnsupportedDsDigestType = 2 => "Unsupported DS Digest Type",
+    StaleAnswer = 3 => "Stale Answer",
+    ForgedAnswer = 4 => "Forged Answer",
+    DnssecIndeterminate = 5 => "DNSSEC Indeterminate",
+    DnssecBogus = 6 => "DNSSEC Bogus",
+    SignatureExpired = 7 => "Signature Expired",
+    SignatureNotYetValid = 8 => "Signature Not Yet Valid",
+    DnskeyMissing = 9 => "DNSKEY Missing",
+    RrsigsMissing = 10 => "RRSIGs Missing",
+    NoZoneKeyBitSet = 11 => "No Zone Key Bit Set",
+    NsecMissing = 12 => "NSEC Missing",
+    CachedError = 13 => "Cached Error",
+    NotReady = 14 => "Not Ready",
+    Blocked = 15 => "Blocked",
+    Censored = 16 => "Censored",
+    Filtered = 17 => "Filtered",
+    Prohibited = 18 => "Prohibited",
+    StaleNxdomainAnswer = 19 => "Stale NXDOMAIN Answer",
+    NotAuthoritative = 20 => "Not Authoritative",
+    NotSupported = 21 => "Not Supported",
+    NoReachableAuthority = 22 => "No Reachable Authority",
+    NetworkError = 23 => "Network Error",
+    InvalidData = 24 => "Invalid Data",
+}
+
+impl Display for ExtendedErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.purpose() {
+            Some(purpose) => write!(f, "{} ({})", self.to_int(), purpose),
+            None => write!(f, "{}", self.to_int()),
+        }
+    }
+}
+
+/// The Extended DNS Error option (RFC 8914)
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct ExtendedError {
+    pub code: ExtendedErrorCode,
+    /// Text for whoever is debugging the failure, it isn't meant for software to parse
+    pub extra_text: Option<String>,
+}
+
+impl ExtendedError {
+    pub fn new(code: ExtendedErrorCode) -> Self {
+        Self {
+            code,
+            extra_text: None,
+        }
+    }
+
+    pub fn with_text(code: ExtendedErrorCode, extra_text: impl Into<String>) -> Self {
+        Self {
+            code,
+            extra_text: Some(extra_text.into()),
+        }
+    }
+
+    pub(crate) fn from_bytes(bytes: &mut Cursor<&[u8]>, length: u16) -> Result<Self, DnsError> {
+        let text_length = (length as usize)
+            .checked_sub(2)
+            .ok_or(DnsError::FormatError)?;
+
+        let code = ExtendedErrorCode::from_int(bytes.read_u16()?);
+        let text = bytes.read_bytes(text_length)?;
+
+        // The text should be UTF-8, but a badly encoded one isn't worth failing the message over.
+        // Some implementations NUL terminate it
+        let text = text.strip_suffix(&[0]).unwrap_or(&text);
+        let extra_text = (!text.is_empty()).then(|| String::from_utf8_lossy(text).into_owned());
+
+        Ok(Self { code, extra_text })
+    }
+
+    /// The length of the option data
+    pub(crate) fn len(&self) -> usize {
+        2 + self.extra_text.as_ref().map_or(0, String::len)
+    }
+
+    pub(crate) fn write(&self, buf: &mut impl Sink) {
+        buf.put_u16(self.code.to_int());
+        if let Some(text) = &self.extra_text {
+            buf.put_slice(text.as_bytes());
+        }
+    }
+}
+
+impl Display for ExtendedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code)?;
+        if let Some(text) = &self.extra_text {
+            write!(f, ": {text:?}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::edns::option::tests::parse;
+    use crate::{EdnsOption, ExtendedError, ExtendedErrorCode};
+
+    #[test]
+    fn round_trips_extended_errors() {
+        let error = EdnsOption::ExtendedError(ExtendedError::with_text(
+            ExtendedErrorCode::NoReachableAuthority,
+            "timed out",
+        ));
+
+        let mut buf = Vec::new();
+        error.write(&mut buf);
+        assert_eq!(buf[..6], [0, 15, 0, 11, 0, 22]);
+        assert_eq!(&buf[6..], b"timed out");
+        assert_eq!(parse(&buf), Some(error));
+
+        let without_text =
+            EdnsOption::ExtendedError(ExtendedError::new(ExtendedErrorCode::from_int(6)));
+        let mut buf = Vec::new();
+        without_text.write(&mut buf);
+        assert_eq!(buf, [0, 15, 0, 2, 0, 6]);
+        assert_eq!(parse(&buf), Some(without_text));
+    }
+
+    #[test]
+    fn parses_unusual_extended_errors() {
+        assert_eq!(parse(&[0, 15, 0, 1, 0]), None);
+
+        let Some(EdnsOption::ExtendedError(error)) =
+            parse(&[0, 15, 0, 5, 0x01, 0x00, b'h', b'i', 0])
+        else {
+            panic!("failed to parse");
+        };
+        assert_eq!(error.code, ExtendedErrorCode::Unknown(256));
+        assert_eq!(error.extra_text.as_deref(), Some("hi"));
+        assert_eq!(error.to_string(), "256: \"hi\"");
+        assert_eq!(
+            ExtendedError::new(ExtendedErrorCode::DnssecBogus).to_string(),
+            "6 (DNSSEC Bogus)"
+        );
+    }
+}
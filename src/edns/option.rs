@@ -0,0 +1,122 @@
+use std::fmt::Display;
+use std::io::Cursor;
+
+use super::{ClientSubnet, Cookie, ExtendedError};
+use crate::encode::Sink;
+use crate::read::CheckedBuf;
+use crate::text::encode_hex;
+use crate::DnsError;
+
+const CLIENT_SUBNET: u16 = 8;
+const COOKIE: u16 = 10;
+const EXTENDED_ERROR: u16 = 15;
+
+/// An option carried in the data of an OPT record (RFC 6891 6.1.2)
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub enum EdnsOption {
+    ClientSubnet(ClientSubnet),
+    Cookie(Cookie),
+    ExtendedError(ExtendedError),
+    /// An option that dnrs doesn't parse, kept as its raw data
+    Unknown {
+        code: u16,
+        data: Vec<u8>,
+    },
+}
+
+impl EdnsOption {
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::ClientSubnet(_) => CLIENT_SUBNET,
+            Self::Cookie(_) => COOKIE,
+            Self::ExtendedError(_) => EXTENDED_ERROR,
+            Self::Unknown { code, .. } => *code,
+        }
+    }
+
+    pub(crate) fn from_bytes(bytes: &mut Cursor<&[u8]>) -> Result<Self, DnsError> {
+        let code = bytes.read_u16()?;
+        let length = bytes.read_u16()?;
+        bytes.ensure_remaining(length as usize)?;
+
+        match code {
+            CLIENT_SUBNET => Ok(Self::ClientSubnet(ClientSubnet::from_bytes(bytes, length)?)),
+            COOKIE => Ok(Self::Cookie(Cookie::from_bytes(bytes, length)?)),
+            EXTENDED_ERROR => Ok(Self::ExtendedError(ExtendedError::from_bytes(
+                bytes, length,
+            )?)),
+            code => Ok(Self::Unknown {
+                code,
+                data: bytes.read_bytes(length as usize)?.to_vec(),
+            }),
+        }
+    }
+
+    /// The length of the option on the wire, including the code and length
+    pub(crate) fn len(&self) -> usize {
+        let data_len = match self {
+            Self::ClientSubnet(subnet) => subnet.len(),
+            Self::Cookie(cookie) => cookie.len(),
+            Self::ExtendedError(error) => error.len(),
+            Self::Unknown { data, .. } => data.len(),
+        };
+
+        4 + data_len
+    }
+
+    pub(crate) fn write(&self, buf: &mut impl Sink) {
+        buf.put_u16(self.code());
+
+        match self {
+            Self::ClientSubnet(subnet) => {
+                buf.put_u16(subnet.len() as u16);
+                subnet.write(buf);
+            }
+            Self::Cookie(cookie) => {
+                buf.put_u16(cookie.len() as u16);
+                cookie.write(buf);
+            }
+            Self::ExtendedError(error) => {
+                buf.put_u16(error.len() as u16);
+                error.write(buf);
+            }
+            Self::Unknown { data, .. } => {
+                buf.put_u16(data.len() as u16);
+                buf.put_slice(data);
+            }
+        }
+    }
+}
+
+impl Display for EdnsOption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ClientSubnet(subnet) => write!(
+                f,
+                "CLIENT-SUBNET: {}/{}/{}",
+                subnet.address, subnet.source_prefix, subnet.scope_prefix
+            ),
+            Self::Cookie(cookie) => {
+                write!(f, "COOKIE: {}", encode_hex(&cookie.client))?;
+                if let Some(server) = &cookie.server {
+                    write!(f, " {}", encode_hex(server))?;
+                }
+                Ok(())
+            }
+            Self::ExtendedError(error) => write!(f, "EDE: {error}"),
+            Self::Unknown { code, data } => write!(f, "OPT={code}: {}", encode_hex(data)),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Cursor;
+
+    use super::EdnsOption;
+
+    /// Read an option from its wire format, used by the tests of each kind of option
+    pub(crate) fn parse(bytes: &[u8]) -> Option<EdnsOption> {
+        EdnsOption::from_bytes(&mut Cursor::new(bytes)).ok()
+    }
+}
@@ -0,0 +1,190 @@
+use bytes::BufMut;
+
+use crate::compression::NameCompressor;
+use crate::{Name, RecordData};
+
+/// Where encoded bytes go, any `BufMut` or a `WireLength` that only counts them
+pub(crate) trait Sink {
+    fn put_slice(&mut self, src: &[u8]);
+
+    fn put_u8(&mut self, n: u8) {
+        self.put_slice(&[n]);
+    }
+
+    fn put_u16(&mut self, n: u16) {
+        self.put_slice(&n.to_be_bytes());
+    }
+
+    fn put_u32(&mut self, n: u32) {
+        self.put_slice(&n.to_be_bytes());
+    }
+
+    fn put_u128(&mut self, n: u128) {
+        self.put_slice(&n.to_be_bytes());
+    }
+}
+
+impl<B: BufMut> Sink for B {
+    fn put_slice(&mut self, src: &[u8]) {
+        BufMut::put_slice(self, src);
+    }
+}
+
+/// A sink that counts the bytes written to it
+#[derive(Debug, Default)]
+pub(crate) struct WireLength(pub usize);
+
+impl Sink for WireLength {
+    fn put_slice(&mut self, src: &[u8]) {
+        self.0 += src.len();
+    }
+}
+
+/// Writes a message into a sink, keeping track of the offset from the start of the message
+/// and of the names that were written so later ones can be compressed
+pub(crate) struct Encoder<'m, S> {
+    sink: S,
+    position: usize,
+    compressor: Option<NameCompressor<'m>>,
+}
+
+impl<'m, S: Sink> Encoder<'m, S> {
+    /// An encoder for a whole message, which compresses names
+    pub fn new(sink: S) -> Self {
+        Self {
+            sink,
+            position: 0,
+            compressor: Some(NameCompressor::new()),
+        }
+    }
+
+    /// An encoder for a part of a message on its own, which writes names in full
+    pub fn uncompressed(sink: S) -> Self {
+        Self {
+            sink,
+            position: 0,
+            compressor: None,
+        }
+    }
+
+    /// The offset from the start of the message that the next byte is written at
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn into_sink(self) -> S {
+        self.sink
+    }
+
+    /// Write a name that may be compressed, suffixes that were already written
+    /// are replaced by a compression pointer
+    pub fn put_name(&mut self, name: &'m Name) {
+        let labels = name.labels();
+
+        for i in 0..labels.len() {
+            let suffix = &labels[i..];
+
+            if let Some(compressor) = &mut self.compressor {
+                if let Some(pointer) = compressor.get(suffix, self.position) {
+                    self.put_u16(0b1100_0000_0000_0000 | pointer);
+                    return;
+                }
+
+                compressor.insert(suffix, self.position);
+            }
+
+            self.put_u8(labels[i].len() as u8);
+            self.put_slice(&labels[i]);
+        }
+
+        self.put_u8(0);
+    }
+
+    /// Write a name in full, for the names that mustn't be compressed
+    pub fn put_name_uncompressed(&mut self, name: &Name) {
+        for label in name.labels() {
+            self.put_u8(label.len() as u8);
+            self.put_slice(label);
+        }
+
+        self.put_u8(0);
+    }
+
+    /// Write the length of record data followed by the data
+    pub fn put_record_data(&mut self, data: &'m RecordData) {
+        // The length comes first but depends on how the names in the data are compressed,
+        // so the data is encoded once only to measure it. The compressor remembers the names
+        // at the same offsets they're written at after, and only gives out pointers to names
+        // that come before the current position, so both passes write the same bytes.
+        let mut measure = Encoder {
+            sink: WireLength(0),
+            position: self.position + 2,
+            compressor: self.compressor.take(),
+        };
+        data.encode(&mut measure);
+        self.compressor = measure.compressor;
+
+        self.put_u16(measure.sink.0 as u16);
+        data.encode(self);
+    }
+}
+
+impl<S: Sink> Sink for Encoder<'_, S> {
+    fn put_slice(&mut self, src: &[u8]) {
+        self.position += src.len();
+        self.sink.put_slice(src);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Encoder, WireLength};
+    use crate::{DnsClass, Name, RecordData, RecordType, ResourceRecord};
+
+    #[test]
+    fn measures_what_it_writes() {
+        let records = [
+            ResourceRecord {
+                name: Name::new("example.com"),
+                type_: RecordType::Soa,
+                class: DnsClass::In,
+                ttl: 300,
+                data: RecordData::Soa {
+                    mname: Name::new("ns.example.com"),
+                    // Compressed against mname, which is in the same data
+                    rname: Name::new("admin.ns.example.com"),
+                    serial: 1,
+                    refresh: 2,
+                    retry: 3,
+                    expire: 4,
+                    minimum: 5,
+                },
+            },
+            ResourceRecord {
+                name: Name::new("ns.example.com"),
+                type_: RecordType::A,
+                class: DnsClass::In,
+                ttl: 300,
+                data: RecordData::A([192, 0, 2, 1].into()),
+            },
+        ];
+
+        let mut bytes = Vec::new();
+        let mut encoder = Encoder::new(&mut bytes);
+        let mut counter = Encoder::new(WireLength(0));
+        for record in records.iter() {
+            record.encode(&mut encoder);
+            record.encode(&mut counter);
+        }
+
+        assert_eq!(counter.into_sink().0, bytes.len());
+
+        // The SOA data is ns + a pointer to the owner, admin + a pointer to mname, and 5 numbers
+        let rd_length = u16::from_be_bytes([bytes[21], bytes[22]]);
+        assert_eq!(rd_length, 5 + 8 + 20);
+        assert_eq!(&bytes[23..28], &[2, b'n', b's', 0xC0, 0]);
+        assert_eq!(&bytes[28..36], &[5, b'a', b'd', b'm', b'i', b'n', 0xC0, 23]);
+        // The owner of the second record is only a pointer
+        assert_eq!(&bytes[56..58], &[0xC0, 23]);
+    }
+}
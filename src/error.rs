@@ -1,14 +1,108 @@
+use std::net::IpAddr;
+
+use crate::{ExtendedError, ExtendedErrorCode, Name};
+
 #[derive(Debug)]
 pub enum DnsError {
     FormatError,
-    ServerFailure(String),
+    ServerFailure(FailureReason),
     NameError,
     NotImplemented,
     Refused,
 }
 
+impl DnsError {
+    /// The extended error to tell the client about, if there's more to say than the rcode
+    pub fn extended_error(&self) -> Option<ExtendedError> {
+        match self {
+            Self::ServerFailure(reason) => Some(reason.extended_error()),
+            _ => None,
+        }
+    }
+}
+
 impl From<std::io::Error> for DnsError {
     fn from(value: std::io::Error) -> Self {
-        Self::ServerFailure(value.to_string())
+        Self::ServerFailure(FailureReason::Network(value.to_string()))
+    }
+}
+
+/// Why a query couldn't be answered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FailureReason {
+    /// A nameserver didn't respond in time
+    Timeout(IpAddr),
+    /// A nameserver that a zone is delegated to didn't answer for it
+    LameDelegation(Name),
+    /// Sending a query or receiving its response failed
+    Network(String),
+    /// A nameserver's response couldn't be used
+    InvalidResponse(String),
+    /// DNSSEC validation failed, the code says which part of it
+    Bogus(ExtendedErrorCode, String),
+    Other(String),
+}
+
+impl FailureReason {
+    /// The extended error for the failure (RFC 8914 4)
+    pub fn extended_error(&self) -> ExtendedError {
+        match self {
+            Self::Timeout(ip) => ExtendedError::with_text(
+                ExtendedErrorCode::NoReachableAuthority,
+                format!("timed out waiting for {ip}"),
+            ),
+            Self::LameDelegation(name) => ExtendedError::with_text(
+                ExtendedErrorCode::NoReachableAuthority,
+                format!("lame delegation to {name}"),
+            ),
+            Self::Network(text) => ExtendedError::with_text(ExtendedErrorCode::NetworkError, text),
+            Self::InvalidResponse(text) => {
+                ExtendedError::with_text(ExtendedErrorCode::InvalidData, text)
+            }
+            Self::Bogus(code, text) => ExtendedError::with_text(*code, text),
+            Self::Other(text) => ExtendedError::with_text(ExtendedErrorCode::Other, text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DnsError, ExtendedErrorCode, FailureReason, Name};
+
+    #[test]
+    fn tells_failures_apart() {
+        let timeout = DnsError::ServerFailure(FailureReason::Timeout([192, 0, 2, 1].into()))
+            .extended_error()
+            .unwrap();
+        assert_eq!(timeout.code, ExtendedErrorCode::NoReachableAuthority);
+        assert_eq!(
+            timeout.extra_text.as_deref(),
+            Some("timed out waiting for 192.0.2.1")
+        );
+
+        let lame =
+            DnsError::ServerFailure(FailureReason::LameDelegation(Name::new("ns1.example.com")))
+                .extended_error()
+                .unwrap();
+        assert_eq!(lame.code, ExtendedErrorCode::NoReachableAuthority);
+        assert_eq!(
+            lame.extra_text.as_deref(),
+            Some("lame delegation to ns1.example.com")
+        );
+
+        let io = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused");
+        let network = DnsError::from(io).extended_error().unwrap();
+        assert_eq!(network.code, ExtendedErrorCode::NetworkError);
+
+        let bogus = DnsError::ServerFailure(FailureReason::Bogus(
+            ExtendedErrorCode::SignatureExpired,
+            "the RRSIG has expired".to_owned(),
+        ))
+        .extended_error()
+        .unwrap();
+        assert_eq!(bogus.code, ExtendedErrorCode::SignatureExpired);
+        assert_eq!(bogus.extra_text.as_deref(), Some("the RRSIG has expired"));
+
+        assert!(DnsError::NameError.extended_error().is_none());
     }
 }
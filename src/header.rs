@@ -1,4 +1,3 @@
-use bytes::BufMut;
 use bytes::Bytes;
 use bytes::BytesMut;
 use std::io::Cursor;
@@ -8,7 +7,8 @@ use bytes::Buf;
 use tracing::{instrument, warn};
 
 use super::Networkable;
-use crate::DnsError;
+use crate::encode::Sink;
+use crate::{DnsError, Edns, Opcode, Rcode};
 
 bitfield! {
     #[derive(Clone, Copy, Default)]
@@ -18,7 +18,7 @@ bitfield! {
     // query or response
     pub qr, set_qr: 15;
     // query type
-    pub opcode, set_opcode: 14, 11;
+    pub from into Opcode, opcode, set_opcode: 14, 11;
     // authoritative answerer
     pub aa, set_aa: 10;
     // truncation
@@ -31,16 +31,14 @@ bitfield! {
     pub z, set_z: 6;
     pub ad, set_ad: 5;
     pub cd, set_cd: 4;
-    // response code
+    // lower 4 bits of the response code, use `Header::rcode` or `Message::rcode` for all of it
     pub rcode, set_rcode: 3, 0;
 }
 
 impl Networkable for Flags {
     #[instrument(level = "trace", skip_all)]
     fn to_bytes(&self) -> Bytes {
-        let mut ret = BytesMut::with_capacity(2);
-        ret.put_u16(self.0);
-        ret.into()
+        Bytes::copy_from_slice(&self.0.to_be_bytes())
     }
 
     #[instrument(level = "trace", skip_all)]
@@ -55,7 +53,7 @@ impl Networkable for Flags {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct Header {
     pub id: u16,
     pub flags: Flags,
@@ -73,19 +71,30 @@ impl Header {
             ..Default::default()
         }
     }
+
+    pub(crate) fn write(&self, buf: &mut impl Sink) {
+        buf.put_u16(self.id);
+        buf.put_u16(self.flags.0);
+        buf.put_u16(self.num_questions);
+        buf.put_u16(self.num_answers);
+        buf.put_u16(self.num_authorities);
+        buf.put_u16(self.num_additionals);
+    }
+
+    /// The response code, with the upper bits from the OPT record of the message if it has one
+    pub fn rcode(&self, edns: Option<&Edns>) -> Rcode {
+        Rcode::from_parts(
+            self.flags.rcode(),
+            edns.map_or(0, |edns| edns.extended_rcode),
+        )
+    }
 }
 
 impl Networkable for Header {
     #[instrument(level = "trace", skip_all)]
     fn to_bytes(&self) -> Bytes {
         let mut ret = BytesMut::with_capacity(12);
-        ret.put_u16(self.id);
-        ret.extend_from_slice(&self.flags.to_bytes());
-        ret.put_u16(self.num_questions);
-        ret.put_u16(self.num_answers);
-        ret.put_u16(self.num_authorities);
-        ret.put_u16(self.num_additionals);
-
+        self.write(&mut ret);
         ret.into()
     }
 
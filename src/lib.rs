@@ -1,27 +1,66 @@
 use std::io::Cursor;
 
+mod compression;
+
+pub mod dnssec;
+
+mod dns_class;
+pub use dns_class::DnsClass;
+
+mod edns;
+pub use edns::{
+    ClientSubnet, Cookie, Edns, EdnsFlags, EdnsOption, ExtendedError, ExtendedErrorCode,
+    CLIENT_COOKIE_LENGTH, MIN_UDP_PAYLOAD_SIZE,
+};
+
+mod encode;
+
 mod error;
 use bytes::Bytes;
-pub use error::DnsError;
+pub use error::{DnsError, FailureReason};
 
 mod header;
 pub use header::{Flags, Header};
 
 mod name;
-pub use name::Name;
+pub use name::{Labels, Name, NameRef};
 
 mod message;
-pub use message::Message;
+pub use message::{Message, MessageBuilder};
+
+mod message_ref;
+pub use message_ref::{MessageRef, QuestionRef, Questions, RecordRef, Records};
+
+mod opcode;
+pub use opcode::Opcode;
+
+mod rcode;
+pub use rcode::Rcode;
+
+mod read;
 
 mod question;
 pub use question::Question;
 
+#[cfg(feature = "serde")]
+mod rfc8427;
+
 mod resource_record;
-pub use resource_record::{RecordData, ResourceRecord};
+pub use resource_record::{
+    CharacterString, RecordData, ResourceRecord, ServiceBinding, SvcParam, SvcParamKey, TypeBitmap,
+};
 
 mod record_type;
 pub use record_type::RecordType;
 
+mod text;
+
+mod unknown_value;
+pub use unknown_value::UnknownValue;
+
+mod zone;
+pub use zone::{parse_zone, read_zone_file, ZoneError};
+
 pub trait Networkable: Sized {
     fn to_bytes(&self) -> Bytes;
     fn from_bytes(bytes: &mut Cursor<&[u8]>) -> Result<Self, DnsError>;
@@ -12,7 +12,7 @@ async fn main() {
         .with_env_filter("trace")
         .pretty()
         .init();
-    resolver::run("0.0.0.0", 3053).await;
+    resolver::run("0.0.0.0", 3053, resolver::Config::default()).await;
 }
 
 // async fn query_resolver() {
@@ -1,18 +1,28 @@
-use bytes::{Bytes, BytesMut};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::fmt::Display;
 use std::io::Cursor;
 
 use tracing::instrument;
 
 use super::{Header, Networkable, Question, ResourceRecord};
-use crate::DnsError;
+use crate::edns::MIN_UDP_PAYLOAD_SIZE;
+use crate::encode::{Encoder, Sink, WireLength};
+use crate::{DnsError, Edns, Rcode, RecordData, RecordType};
+
+mod builder;
+pub use builder::MessageBuilder;
 
 #[derive(Debug, Default)]
 pub struct Message {
+    /// The counts in the header are only kept up to date by the `add_*` methods,
+    /// the ones that are sent are always those of the sections
     pub header: Header,
     pub questions: Vec<Question>,
     pub answers: Vec<ResourceRecord>,
     pub authorities: Vec<ResourceRecord>,
     pub additionals: Vec<ResourceRecord>,
+    /// The OPT pseudo-record, which isn't part of `additionals` or counted in the header
+    pub edns: Option<Edns>,
 }
 
 impl Message {
@@ -41,55 +51,273 @@ impl Message {
         self.header.num_additionals += 1;
         self.additionals.push(answer)
     }
-}
 
-impl Networkable for Message {
-    #[instrument(level = "debug", skip_all)]
-    fn to_bytes(&self) -> Bytes {
-        let mut response = BytesMut::new();
-        response.extend_from_slice(&self.header.to_bytes());
+    /// The full response code, from the header and the OPT record
+    pub fn rcode(&self) -> Rcode {
+        self.header.rcode(self.edns.as_ref())
+    }
+
+    /// Set the response code, splitting it between the header and the OPT record.
+    /// Extended codes need an OPT record, so one is added if the message doesn't have one
+    pub fn set_rcode(&mut self, rcode: Rcode) {
+        self.header.flags.set_rcode(rcode.header_bits());
+
+        if rcode.is_extended() && self.edns.is_none() {
+            self.edns = Some(Edns::new(MIN_UDP_PAYLOAD_SIZE));
+        }
+
+        if let Some(edns) = &mut self.edns {
+            edns.extended_rcode = rcode.extended_bits();
+        }
+    }
+
+    /// Write the message in wire format into `buf`, with its names compressed.
+    /// Fails if a section has more entries than the header can count
+    pub fn encode(&self, buf: &mut impl BufMut) -> Result<(), DnsError> {
+        let records = self.records().count();
+        let header = self.wire_header(records)?;
+        self.write(&mut Encoder::new(buf), header, records);
+        Ok(())
+    }
+
+    /// Write the message in at most `max_size` bytes, e.g. the payload size of a udp client.
+    ///
+    /// Whole RRsets are left out from the end of the message, so additionals go first,
+    /// then authorities and then answers. TC is set if any answers are left out (RFC 2181 9).
+    /// The header, the questions and the OPT record are always written, and it fails like `encode`.
+    pub fn encode_limited(&self, buf: &mut impl BufMut, max_size: usize) -> Result<(), DnsError> {
+        let records = self.records_that_fit(max_size);
+        let header = self.wire_header(records)?;
+        self.write(&mut Encoder::new(buf), header, records);
+        Ok(())
+    }
+
+    /// The length of the message in wire format, which is what `encode` writes
+    pub fn wire_len(&self) -> usize {
+        // The header is the same length whatever its counts are
+        let mut encoder = Encoder::new(WireLength::default());
+        self.write(&mut encoder, self.header.clone(), self.records().count());
+        encoder.into_sink().0
+    }
+
+    /// The records of the answer, authority and additional sections, with their section
+    fn records(&self) -> impl Iterator<Item = (usize, &ResourceRecord)> {
+        let answers = self.answers.iter().map(|record| (0, record));
+        let authorities = self.authorities.iter().map(|record| (1, record));
+        let additionals = self.additionals.iter().map(|record| (2, record));
+        answers.chain(authorities).chain(additionals)
+    }
+
+    /// How many of `records` fit in `max_size` bytes, only counting whole RRsets.
+    /// Records of an RRset are expected to be next to each other in their section,
+    /// and RRSIGs count as part of the RRset they cover.
+    fn records_that_fit(&self, max_size: usize) -> usize {
+        let mut edns_len = WireLength::default();
+        if let Some(edns) = &self.edns {
+            edns.write(&mut edns_len);
+        }
+        let limit = max_size.saturating_sub(edns_len.0);
+
+        // Records that are left out don't change how the ones before them are compressed,
+        // so the end of each record is where the message would end without the ones after it
+        let mut encoder = Encoder::new(WireLength::default());
+        self.header.write(&mut encoder);
+        for question in self.questions.iter() {
+            question.encode(&mut encoder);
+        }
+
+        let mut fitting = 0;
+        let mut records = self.records().enumerate().peekable();
+        while let Some((i, (section, record))) = records.next() {
+            record.encode(&mut encoder);
+            if encoder.position() > limit {
+                break;
+            }
+
+            let rrset_ends = records.peek().is_none_or(|(_, (next_section, next))| {
+                *next_section != section
+                    || next.name != record.name
+                    || rrset_type(next) != rrset_type(record)
+                    || next.class != record.class
+            });
+            if rrset_ends {
+                fitting = i + 1;
+            }
+        }
+
+        fitting
+    }
+
+    /// The header as it's sent with only the first `records` records of the sections,
+    /// with the counts of what's in them. Fails if a count doesn't fit in 16 bits
+    fn wire_header(&self, records: usize) -> Result<Header, DnsError> {
+        let mut header = self.header.clone();
+        let answers = records.min(self.answers.len());
+        let authorities = (records - answers).min(self.authorities.len());
+        let additionals = records - answers - authorities;
+
+        // Signatures and the proofs in the authority section can only be left out
+        // by truncating the message, when they were asked for (RFC 4035 3.1.1)
+        let dnssec_ok = self
+            .edns
+            .as_ref()
+            .is_some_and(|edns| edns.flags.dnssec_ok());
+        if answers < self.answers.len() || (dnssec_ok && authorities < self.authorities.len()) {
+            header.flags.set_tc(true);
+        }
+
+        let count = |len: usize| u16::try_from(len).map_err(|_| DnsError::FormatError);
+        header.num_questions = count(self.questions.len())?;
+        header.num_answers = count(answers)?;
+        header.num_authorities = count(authorities)?;
+        header.num_additionals = count(additionals + self.edns.is_some() as usize)?;
+        Ok(header)
+    }
+
+    /// Write the message with `header` and only the first `records` records of its sections
+    fn write<'m>(&'m self, encoder: &mut Encoder<'m, impl Sink>, header: Header, records: usize) {
+        header.write(encoder);
 
         for question in self.questions.iter() {
-            response.extend_from_slice(&question.to_bytes())
+            question.encode(encoder);
+        }
+
+        for (_, record) in self.records().take(records) {
+            record.encode(encoder);
+        }
+
+        if let Some(edns) = &self.edns {
+            edns.write(encoder);
         }
+    }
+}
+
+/// The type of the RRset that a record belongs to, which for an RRSIG is the type it covers
+fn rrset_type(record: &ResourceRecord) -> RecordType {
+    match &record.data {
+        RecordData::Rrsig { type_covered, .. } => *type_covered,
+        _ => record.type_,
+    }
+}
+
+impl Display for Message {
+    /// The message the way dig prints it, with records in zone file syntax
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let flags = &self.header.flags;
+        writeln!(
+            f,
+            ";; ->>HEADER<<- opcode: {}, status: {}, id: {}",
+            flags.opcode(),
+            self.rcode(),
+            self.header.id
+        )?;
+
+        let flag_names = [
+            ("qr", flags.qr()),
+            ("aa", flags.aa()),
+            ("tc", flags.tc()),
+            ("rd", flags.rd()),
+            ("ra", flags.ra()),
+            ("ad", flags.ad()),
+            ("cd", flags.cd()),
+        ];
+        let set_flags: Vec<_> = flag_names
+            .iter()
+            .filter(|(_, set)| *set)
+            .map(|(name, _)| *name)
+            .collect();
 
-        for record in self.answers.iter() {
-            response.extend_from_slice(&record.to_bytes())
+        // Like dig, the OPT record is counted as an additional
+        writeln!(
+            f,
+            ";; flags: {}; QUERY: {}, ANSWER: {}, AUTHORITY: {}, ADDITIONAL: {}",
+            set_flags.join(" "),
+            self.questions.len(),
+            self.answers.len(),
+            self.authorities.len(),
+            self.additionals.len() + self.edns.is_some() as usize
+        )?;
+
+        if let Some(edns) = &self.edns {
+            write!(f, "\n;; OPT PSEUDOSECTION:\n{edns}")?;
         }
 
-        for record in self.authorities.iter() {
-            response.extend_from_slice(&record.to_bytes())
+        if !self.questions.is_empty() {
+            writeln!(f, "\n;; QUESTION SECTION:")?;
+            for question in self.questions.iter() {
+                writeln!(f, ";{question}")?;
+            }
         }
 
-        for record in self.additionals.iter() {
-            response.extend_from_slice(&record.to_bytes())
+        let sections = [
+            ("ANSWER", &self.answers),
+            ("AUTHORITY", &self.authorities),
+            ("ADDITIONAL", &self.additionals),
+        ];
+        for (section, records) in sections {
+            if records.is_empty() {
+                continue;
+            }
+
+            writeln!(f, "\n;; {section} SECTION:")?;
+            for record in records.iter() {
+                writeln!(f, "{record}")?;
+            }
         }
 
+        Ok(())
+    }
+}
+
+impl Networkable for Message {
+    #[instrument(level = "debug", skip_all)]
+    /// Panics if a section has more entries than the header can count,
+    /// `encode` returns an error instead
+    fn to_bytes(&self) -> Bytes {
+        let mut response = BytesMut::new();
+        self.encode(&mut response)
+            .expect("sections have at most 65535 entries");
         response.into()
     }
 
     #[instrument(level = "debug", skip_all)]
     fn from_bytes(bytes: &mut Cursor<&[u8]>) -> Result<Self, DnsError> {
-        let header = Header::from_bytes(bytes).unwrap();
+        let mut header = Header::from_bytes(bytes)?;
 
         let mut questions = Vec::new();
         for _ in 0..header.num_questions {
-            questions.push(Question::from_bytes(bytes).unwrap());
+            questions.push(Question::from_bytes(bytes)?);
         }
 
         let mut answers = Vec::new();
         for _ in 0..header.num_answers {
-            answers.push(ResourceRecord::from_bytes(bytes).unwrap());
+            answers.push(ResourceRecord::from_bytes(bytes)?);
         }
 
         let mut authorities = Vec::new();
         for _ in 0..header.num_authorities {
-            authorities.push(ResourceRecord::from_bytes(bytes).unwrap());
+            authorities.push(ResourceRecord::from_bytes(bytes)?);
         }
 
         let mut additionals = Vec::new();
+        let mut edns = None;
         for _ in 0..header.num_additionals {
-            additionals.push(ResourceRecord::from_bytes(bytes).unwrap());
+            let record = ResourceRecord::from_bytes(bytes)?;
+
+            if record.type_ == RecordType::Opt {
+                // There can only be one OPT record (RFC 6891 6.1.1)
+                if edns.is_some() {
+                    return Err(DnsError::FormatError);
+                }
+
+                edns = Some(Edns::from_record(record)?);
+            } else {
+                additionals.push(record);
+            }
+        }
+
+        if edns.is_some() {
+            header.num_additionals -= 1;
         }
 
         Ok(Self {
@@ -98,6 +326,364 @@ impl Networkable for Message {
             answers,
             authorities,
             additionals,
+            edns,
         })
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Cursor;
+
+    use proptest::prelude::*;
+
+    use crate::{
+        DnsClass, DnsError, Edns, EdnsOption, ExtendedError, ExtendedErrorCode, Flags, Header,
+        Message, Name, Networkable, Question, Rcode, RecordData, RecordType, ResourceRecord,
+    };
+
+    fn record(name: &str, data: RecordData, type_: RecordType) -> ResourceRecord {
+        ResourceRecord {
+            name: Name::new(name),
+            type_,
+            class: DnsClass::In,
+            ttl: 300,
+            data,
+        }
+    }
+
+    #[test]
+    fn displays_like_dig() {
+        let mut flags = Flags::default();
+        flags.set_qr(true);
+        flags.set_rd(true);
+        flags.set_ra(true);
+
+        let mut message = Message::new(Header::new(4660, flags));
+        message.add_question(Question::new(Name::new("www.example.com"), RecordType::A));
+        message.add_answer(record(
+            "www.example.com",
+            RecordData::A([1, 2, 3, 4].into()),
+            RecordType::A,
+        ));
+
+        let mut edns = Edns::new(1232);
+        edns.flags.set_dnssec_ok(true);
+        edns.options
+            .push(EdnsOption::ExtendedError(ExtendedError::with_text(
+                ExtendedErrorCode::NetworkError,
+                "unreachable",
+            )));
+        message.edns = Some(edns);
+        message.set_rcode(Rcode::ServFail);
+
+        let expected = "\
+;; ->>HEADER<<- opcode: QUERY, status: SERVFAIL, id: 4660
+;; flags: qr rd ra; QUERY: 1, ANSWER: 1, AUTHORITY: 0, ADDITIONAL: 1
+
+;; OPT PSEUDOSECTION:
+; EDNS: version: 0, flags: do; udp: 1232
+; EDE: 23 (Network Error): \"unreachable\"
+
+;; QUESTION SECTION:
+;www.example.com. IN A
+
+;; ANSWER SECTION:
+www.example.com. 300 IN A 1.2.3.4
+";
+        assert_eq!(message.to_string(), expected);
+    }
+
+    #[test]
+    fn splits_extended_rcodes_into_the_opt_record() {
+        let mut message = Message::new(Header::new(1, Default::default()));
+        message.set_rcode(Rcode::Refused);
+        assert!(message.edns.is_none());
+        assert_eq!(message.rcode(), Rcode::Refused);
+
+        message.set_rcode(Rcode::BadCookie);
+        assert_eq!(message.header.flags.rcode(), 7);
+        assert_eq!(message.edns.as_ref().unwrap().extended_rcode, 1);
+
+        let bytes = message.to_bytes();
+        let parsed = Message::from_bytes(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(parsed.rcode(), Rcode::BadCookie);
+        assert_eq!(parsed.header.rcode(None), Rcode::YxRrset);
+    }
+
+    #[test]
+    fn compresses_names_in_records() {
+        let mut message = Message::new(Header::new(1, Default::default()));
+        message.add_question(Question::new(Name::new("www.example.com"), RecordType::A));
+        message.add_answer(record(
+            "www.example.com",
+            RecordData::Cname(Name::new("web.example.com")),
+            RecordType::Cname,
+        ));
+        message.add_answer(record(
+            "web.example.com",
+            RecordData::A([1, 2, 3, 4].into()),
+            RecordType::A,
+        ));
+        message.add_authority(record(
+            "example.com",
+            RecordData::Soa {
+                mname: Name::new("ns1.example.com"),
+                rname: Name::new("hostmaster.example.com"),
+                serial: 1,
+                refresh: 2,
+                retry: 3,
+                expire: 4,
+                minimum: 5,
+            },
+            RecordType::Soa,
+        ));
+
+        let uncompressed_len = message.header.to_bytes().len()
+            + message
+                .questions
+                .iter()
+                .map(|q| q.to_bytes().len())
+                .sum::<usize>()
+            + message
+                .answers
+                .iter()
+                .chain(message.authorities.iter())
+                .map(|rr| rr.to_bytes().len())
+                .sum::<usize>();
+
+        let bytes = message.to_bytes();
+        assert!(bytes.len() < uncompressed_len);
+        assert_eq!(message.wire_len(), bytes.len());
+
+        // Encoding appends to what's already in the buffer
+        let mut buf = vec![0xFF];
+        message.encode(&mut buf).unwrap();
+        assert_eq!(&buf[1..], &bytes[..]);
+
+        let parsed = Message::from_bytes(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(parsed.questions[0].name, message.questions[0].name);
+        assert_eq!(parsed.answers, message.answers);
+        assert_eq!(parsed.authorities, message.authorities);
+    }
+
+    #[test]
+    fn counts_what_is_in_the_sections() {
+        let mut message = example_message();
+        // Sections changed directly, without the add_* methods
+        message.questions.clear();
+        message.answers.push(message.answers[0].clone());
+        message.header.num_authorities = 5;
+        message.edns = Some(Edns::new(1232));
+
+        let bytes = message.to_bytes();
+        assert_eq!(bytes[4..12], [0, 0, 0, 2, 0, 0, 0, 2]);
+
+        let parsed = Message::from_bytes(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(parsed.answers.len(), 2);
+        assert_eq!(parsed.additionals.len(), 1);
+    }
+
+    #[test]
+    fn truncates_whole_rrsets_to_fit() {
+        let mut message = example_message();
+        message.edns = Some(Edns::new(1232));
+        message.add_answer(record(
+            "example.com",
+            RecordData::Ns(Name::new("ns2.example.com")),
+            RecordType::Ns,
+        ));
+        message.add_authority(record(
+            "example.com",
+            RecordData::Ns(Name::new("ns3.example.com")),
+            RecordType::Ns,
+        ));
+
+        let encode_limited = |max_size| {
+            let mut buf = Vec::new();
+            message.encode_limited(&mut buf, max_size).unwrap();
+            assert!(buf.len() <= max_size);
+            Message::from_bytes(&mut Cursor::new(&buf)).unwrap()
+        };
+
+        let full = message.wire_len();
+        let parsed = encode_limited(full);
+        assert!(!parsed.header.flags.tc());
+        assert_eq!(parsed.additionals.len(), 1);
+
+        // The additional A record goes first, with its 16 bytes
+        let parsed = encode_limited(full - 1);
+        assert!(!parsed.header.flags.tc());
+        assert_eq!(parsed.answers.len(), 2);
+        assert_eq!(parsed.authorities.len(), 1);
+        assert!(parsed.additionals.is_empty());
+        assert!(parsed.edns.is_some());
+
+        let parsed = encode_limited(full - 16 - 1);
+        assert!(!parsed.header.flags.tc());
+        assert_eq!(parsed.answers.len(), 2);
+        assert!(parsed.authorities.is_empty());
+
+        // Both NS records of the answer are one RRset
+        let parsed = encode_limited(full - 16 - 18 - 1);
+        assert!(parsed.header.flags.tc());
+        assert!(parsed.answers.is_empty());
+        assert_eq!(parsed.questions.len(), 1);
+        assert!(parsed.edns.is_some());
+    }
+
+    #[test]
+    fn refuses_sections_the_header_cannot_count() {
+        let mut message = example_message();
+        message.answers = vec![message.answers[0].clone(); u16::MAX as usize + 1];
+
+        let mut buf = Vec::new();
+        assert!(matches!(
+            message.encode(&mut buf),
+            Err(DnsError::FormatError)
+        ));
+        assert!(buf.is_empty());
+
+        // Leaving records out makes the count fit
+        message.encode_limited(&mut buf, 512).unwrap();
+        let parsed = Message::from_bytes(&mut Cursor::new(&buf)).unwrap();
+        assert!(parsed.header.flags.tc());
+    }
+
+    #[test]
+    fn truncates_signatures_with_their_rrsets() {
+        let mut message = example_message();
+        message.additionals.clear();
+        message.header.num_additionals = 0;
+        message.add_answer(record(
+            "example.com",
+            RecordData::Rrsig {
+                type_covered: RecordType::Ns,
+                algorithm: 15,
+                labels: 2,
+                original_ttl: 300,
+                expiration: 2,
+                inception: 1,
+                key_tag: 12345,
+                signer: Name::new("example.com"),
+                signature: vec![0; 64],
+            },
+            RecordType::Rrsig,
+        ));
+        message.add_authority(record(
+            "example.com",
+            RecordData::Nsec {
+                next: Name::new("www.example.com"),
+                types: [RecordType::Ns, RecordType::Rrsig, RecordType::Nsec]
+                    .into_iter()
+                    .collect(),
+            },
+            RecordType::Nsec,
+        ));
+
+        let mut edns = Edns::new(1232);
+        edns.flags.set_dnssec_ok(true);
+        message.edns = Some(edns);
+
+        let encode_limited = |message: &Message, max_size| {
+            let mut buf = Vec::new();
+            message.encode_limited(&mut buf, max_size).unwrap();
+            Message::from_bytes(&mut Cursor::new(&buf)).unwrap()
+        };
+
+        // A proof that's left out truncates the message when DNSSEC records were asked for
+        let full = message.wire_len();
+        let parsed = encode_limited(&message, full - 1);
+        assert!(parsed.header.flags.tc());
+        assert_eq!(parsed.answers.len(), 2);
+        assert!(parsed.authorities.is_empty());
+
+        message.edns = Some(Edns::new(1232));
+        let parsed = encode_limited(&message, full - 1);
+        assert!(!parsed.header.flags.tc());
+
+        // The RRSIG doesn't get left out on its own
+        let rrsig = message.answers.pop().unwrap();
+        let nsec = message.authorities.pop().unwrap();
+        let unsigned = message.wire_len();
+        message.answers.push(rrsig);
+        message.authorities.push(nsec);
+
+        let parsed = encode_limited(&message, unsigned);
+        assert!(parsed.header.flags.tc());
+        assert!(parsed.answers.is_empty());
+    }
+
+    /// A small NS response, also used by the tests of the other message formats
+    pub(crate) fn example_message() -> Message {
+        let mut message = Message::new(Header::new(1, Default::default()));
+        message.add_question(Question::new(Name::new("example.com"), RecordType::Ns));
+        message.add_answer(record(
+            "example.com",
+            RecordData::Ns(Name::new("ns1.example.com")),
+            RecordType::Ns,
+        ));
+        message.add_additional(record(
+            "ns1.example.com",
+            RecordData::A([1, 2, 3, 4].into()),
+            RecordType::A,
+        ));
+        message
+    }
+
+    #[test]
+    fn rejects_truncated_messages() {
+        let bytes = example_message().to_bytes();
+
+        for len in 0..bytes.len() {
+            let result = Message::from_bytes(&mut Cursor::new(&bytes[..len]));
+            assert!(matches!(result, Err(DnsError::FormatError)), "{len}");
+        }
+    }
+
+    #[test]
+    fn rejects_mismatched_rd_length() {
+        let mut bytes = example_message().to_bytes().to_vec();
+
+        // The A record is last, so its length is just before the final 4 bytes
+        let length_position = bytes.len() - 6;
+        assert_eq!(bytes[length_position..length_position + 2], [0, 4]);
+
+        bytes[length_position + 1] = 3;
+        let result = Message::from_bytes(&mut Cursor::new(&bytes[..bytes.len() - 1]));
+        assert!(matches!(result, Err(DnsError::FormatError)));
+
+        // An NS record whose name ends before its declared length
+        let mut message = example_message();
+        message.additionals.clear();
+        message.header.num_additionals = 0;
+        let mut bytes = message.to_bytes().to_vec();
+        let length_position = bytes.len() - 8;
+        assert_eq!(bytes[length_position..length_position + 2], [0, 6]);
+
+        bytes[length_position + 1] = 7;
+        bytes.push(0);
+        let result = Message::from_bytes(&mut Cursor::new(&bytes));
+        assert!(matches!(result, Err(DnsError::FormatError)));
+    }
+
+    proptest! {
+        #[test]
+        fn parsing_random_bytes_does_not_panic(bytes in prop::collection::vec(any::<u8>(), 0..512)) {
+            let _ = Message::from_bytes(&mut Cursor::new(&bytes));
+        }
+
+        #[test]
+        fn parsing_corrupted_messages_does_not_panic(
+            corruptions in prop::collection::vec((any::<prop::sample::Index>(), any::<u8>()), 1..8)
+        ) {
+            let mut bytes = example_message().to_bytes().to_vec();
+            for (index, value) in corruptions {
+                let i = index.index(bytes.len());
+                bytes[i] = value;
+            }
+
+            let _ = Message::from_bytes(&mut Cursor::new(&bytes));
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use crate::{Edns, Flags, Header, Message, Opcode, Question, Rcode, ResourceRecord};
+
+/// Builds a message one part at a time, e.g. a query or the response to a request
+#[derive(Debug)]
+pub struct MessageBuilder {
+    message: Message,
+    rcode: Rcode,
+}
+
+impl MessageBuilder {
+    /// An empty query with `id`
+    pub fn new(id: u16) -> Self {
+        Self {
+            message: Message::new(Header::new(id, Flags::default())),
+            rcode: Rcode::NoError,
+        }
+    }
+
+    /// A standard query for `question`
+    pub fn query(id: u16, question: Question) -> Self {
+        Self::new(id).opcode(Opcode::Query).question(question)
+    }
+
+    /// A response to `request`, with its questions
+    pub fn response_to(request: &Message) -> Self {
+        Self::response_to_header(&request.header).questions(request.questions.iter().cloned())
+    }
+
+    /// A response to the request with `header`, without any questions.
+    /// The id, opcode and the RD and CD bits are copied, and QR and RA are set
+    pub fn response_to_header(header: &Header) -> Self {
+        let mut builder = Self::new(header.id)
+            .opcode(header.flags.opcode())
+            .recursion_desired(header.flags.rd())
+            .checking_disabled(header.flags.cd());
+        builder.message.header.flags.set_qr(true);
+        builder.message.header.flags.set_ra(true);
+        builder
+    }
+
+    pub fn opcode(mut self, opcode: Opcode) -> Self {
+        self.message.header.flags.set_opcode(opcode);
+        self
+    }
+
+    pub fn rcode(mut self, rcode: Rcode) -> Self {
+        self.rcode = rcode;
+        self
+    }
+
+    pub fn authoritative(mut self, aa: bool) -> Self {
+        self.message.header.flags.set_aa(aa);
+        self
+    }
+
+    pub fn recursion_desired(mut self, rd: bool) -> Self {
+        self.message.header.flags.set_rd(rd);
+        self
+    }
+
+    pub fn recursion_available(mut self, ra: bool) -> Self {
+        self.message.header.flags.set_ra(ra);
+        self
+    }
+
+    pub fn authentic_data(mut self, ad: bool) -> Self {
+        self.message.header.flags.set_ad(ad);
+        self
+    }
+
+    pub fn checking_disabled(mut self, cd: bool) -> Self {
+        self.message.header.flags.set_cd(cd);
+        self
+    }
+
+    pub fn question(mut self, question: Question) -> Self {
+        self.message.add_question(question);
+        self
+    }
+
+    pub fn questions(self, questions: impl IntoIterator<Item = Question>) -> Self {
+        questions.into_iter().fold(self, Self::question)
+    }
+
+    pub fn answer(mut self, record: ResourceRecord) -> Self {
+        self.message.add_answer(record);
+        self
+    }
+
+    pub fn answers(self, records: impl IntoIterator<Item = ResourceRecord>) -> Self {
+        records.into_iter().fold(self, Self::answer)
+    }
+
+    pub fn authority(mut self, record: ResourceRecord) -> Self {
+        self.message.add_authority(record);
+        self
+    }
+
+    pub fn authorities(self, records: impl IntoIterator<Item = ResourceRecord>) -> Self {
+        records.into_iter().fold(self, Self::authority)
+    }
+
+    pub fn additional(mut self, record: ResourceRecord) -> Self {
+        self.message.add_additional(record);
+        self
+    }
+
+    pub fn additionals(self, records: impl IntoIterator<Item = ResourceRecord>) -> Self {
+        records.into_iter().fold(self, Self::additional)
+    }
+
+    /// Set the OPT record, `None` leaves it out
+    pub fn edns(mut self, edns: impl Into<Option<Edns>>) -> Self {
+        self.message.edns = edns.into();
+        self
+    }
+
+    /// The message, with the response code split between the header and the OPT record
+    pub fn build(mut self) -> Message {
+        self.message.set_rcode(self.rcode);
+        self.message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MessageBuilder;
+    use crate::{
+        DnsClass, Edns, Flags, Header, Message, Name, Opcode, Question, Rcode, RecordData,
+        RecordType, ResourceRecord,
+    };
+
+    fn question() -> Question {
+        Question::new(Name::new("example.com"), RecordType::A)
+    }
+
+    #[test]
+    fn builds_queries() {
+        let query = MessageBuilder::query(1234, question())
+            .recursion_desired(true)
+            .edns(Edns::new(1232))
+            .build();
+
+        assert_eq!(query.header.id, 1234);
+        assert!(query.header.flags.rd());
+        assert!(!query.header.flags.qr());
+        assert_eq!(query.header.num_questions, 1);
+        assert_eq!(query.questions[0].name, Name::new("example.com"));
+        assert_eq!(query.edns, Some(Edns::new(1232)));
+    }
+
+    #[test]
+    fn builds_responses_from_requests() {
+        let mut flags = Flags::default();
+        flags.set_rd(true);
+        flags.set_aa(true);
+        flags.set_opcode(Opcode::Notify);
+        let mut request = Message::new(Header::new(77, flags));
+        request.add_question(question());
+
+        let answer = ResourceRecord {
+            name: Name::new("example.com"),
+            type_: RecordType::A,
+            class: DnsClass::In,
+            ttl: 60,
+            data: RecordData::A([192, 0, 2, 1].into()),
+        };
+        let response = MessageBuilder::response_to(&request)
+            .answer(answer.clone())
+            .rcode(Rcode::BadCookie)
+            .build();
+
+        let flags = response.header.flags;
+        assert_eq!(response.header.id, 77);
+        assert!(flags.qr() && flags.ra() && flags.rd());
+        assert!(!flags.aa());
+        assert_eq!(flags.opcode(), Opcode::Notify);
+        assert_eq!(response.questions.len(), 1);
+        assert_eq!(response.answers, [answer]);
+        // The extended code needed an OPT record
+        assert_eq!(response.rcode(), Rcode::BadCookie);
+        assert!(response.edns.is_some());
+    }
+}
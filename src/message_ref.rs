@@ -0,0 +1,395 @@
+use std::fmt::Display;
+use std::io::Cursor;
+
+use crate::{
+    DnsClass, DnsError, Edns, Header, Message, NameRef, Networkable, Question, RecordData,
+    RecordType, ResourceRecord,
+};
+
+/// Length of the header at the start of every message
+const HEADER_LENGTH: usize = 12;
+
+/// A message borrowed from its wire format, which is read as it's used instead of all at once
+///
+/// Only the header is read up front. The sections are iterators that parse one entry at a time,
+/// and the names in them are decompressed when they're read. Use `Message` for owned data.
+#[derive(Debug, Clone)]
+pub struct MessageRef<'a> {
+    bytes: &'a [u8],
+    header: Header,
+}
+
+impl<'a> MessageRef<'a> {
+    /// Read the header of the message in `bytes`
+    pub fn new(bytes: &'a [u8]) -> Result<Self, DnsError> {
+        let header = Header::from_bytes(&mut Cursor::new(bytes))?;
+        Ok(Self { bytes, header })
+    }
+
+    /// The header as it was sent, the OPT record is counted in `num_additionals`
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn questions(&self) -> Questions<'a> {
+        Questions {
+            message: self.bytes,
+            position: HEADER_LENGTH,
+            remaining: self.header.num_questions,
+        }
+    }
+
+    pub fn answers(&self) -> Records<'a> {
+        self.records(0, self.header.num_answers)
+    }
+
+    pub fn authorities(&self) -> Records<'a> {
+        self.records(self.header.num_answers as u32, self.header.num_authorities)
+    }
+
+    /// The additional records, including the OPT record
+    pub fn additionals(&self) -> Records<'a> {
+        let skip = self.header.num_answers as u32 + self.header.num_authorities as u32;
+        self.records(skip, self.header.num_additionals)
+    }
+
+    /// The records of a section, after `skip` records of the sections before it
+    fn records(&self, skip: u32, count: u16) -> Records<'a> {
+        Records {
+            message: self.bytes,
+            position: HEADER_LENGTH,
+            skip_questions: self.header.num_questions,
+            skip_records: skip,
+            remaining: count,
+        }
+    }
+
+    /// The OPT pseudo-record, which means reading up to the end of the message
+    pub fn edns(&self) -> Result<Option<Edns>, DnsError> {
+        let mut edns = None;
+
+        for record in self.additionals() {
+            let record = record?;
+            if record.type_ != RecordType::Opt {
+                continue;
+            }
+
+            // There can only be one OPT record (RFC 6891 6.1.1)
+            if edns.is_some() {
+                return Err(DnsError::FormatError);
+            }
+
+            edns = Some(Edns::from_record(record.to_record()?)?);
+        }
+
+        Ok(edns)
+    }
+
+    /// Copy the whole message out of the bytes
+    pub fn to_message(&self) -> Result<Message, DnsError> {
+        Message::from_bytes(&mut Cursor::new(self.bytes))
+    }
+}
+
+/// A question borrowed from a message
+#[derive(Debug, Clone, Copy)]
+pub struct QuestionRef<'a> {
+    pub name: NameRef<'a>,
+    pub type_: RecordType,
+    pub class: DnsClass,
+}
+
+impl QuestionRef<'_> {
+    pub fn to_question(&self) -> Question {
+        Question {
+            name: self.name.to_name(),
+            type_: self.type_,
+            class: self.class,
+        }
+    }
+}
+
+impl Display for QuestionRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#} {} {}", self.name, self.class, self.type_)
+    }
+}
+
+/// A resource record borrowed from a message, its data is only parsed by `data`
+#[derive(Debug, Clone, Copy)]
+pub struct RecordRef<'a> {
+    pub name: NameRef<'a>,
+    pub type_: RecordType,
+    pub class: DnsClass,
+    pub ttl: u32,
+    message: &'a [u8],
+    data_start: usize,
+    data_length: u16,
+}
+
+impl<'a> RecordRef<'a> {
+    /// The data as it is on the wire, names in it may be compressed
+    pub fn raw_data(&self) -> &'a [u8] {
+        &self.message[self.data_start..self.data_start + self.data_length as usize]
+    }
+
+    /// Parse the data, decompressing the names in it
+    pub fn data(&self) -> Result<RecordData, DnsError> {
+        let mut cursor = Cursor::new(self.message);
+        cursor.set_position(self.data_start as u64);
+        let data = RecordData::from_bytes(self.type_, self.data_length, &mut cursor)?;
+
+        // The data has to use exactly the length that the record declared
+        if cursor.position() as usize != self.data_start + self.data_length as usize {
+            return Err(DnsError::FormatError);
+        }
+
+        Ok(data)
+    }
+
+    pub fn to_record(&self) -> Result<ResourceRecord, DnsError> {
+        Ok(ResourceRecord {
+            name: self.name.to_name(),
+            type_: self.type_,
+            class: self.class,
+            ttl: self.ttl,
+            data: self.data()?,
+        })
+    }
+}
+
+/// Read a big-endian u16 at `position`
+fn read_u16(message: &[u8], position: usize) -> Result<u16, DnsError> {
+    match message.get(position..position + 2) {
+        Some(&[high, low]) => Ok(u16::from_be_bytes([high, low])),
+        _ => Err(DnsError::FormatError),
+    }
+}
+
+/// Read the question at `position`, returning it and the position after it
+fn read_question(message: &[u8], position: usize) -> Result<(QuestionRef<'_>, usize), DnsError> {
+    let (name, position) = NameRef::parse(message, position)?;
+    let type_ = RecordType::from_int(read_u16(message, position)?);
+    let class = DnsClass::from_int(read_u16(message, position + 2)?);
+
+    Ok((QuestionRef { name, type_, class }, position + 4))
+}
+
+/// Read the record at `position`, returning it and the position after it
+fn read_record(message: &[u8], position: usize) -> Result<(RecordRef<'_>, usize), DnsError> {
+    let (name, position) = NameRef::parse(message, position)?;
+    let type_ = RecordType::from_int(read_u16(message, position)?);
+    let class = DnsClass::from_int(read_u16(message, position + 2)?);
+    let ttl =
+        ((read_u16(message, position + 4)? as u32) << 16) | read_u16(message, position + 6)? as u32;
+    let data_length = read_u16(message, position + 8)?;
+
+    let data_start = position + 10;
+    let end = data_start + data_length as usize;
+    if end > message.len() {
+        return Err(DnsError::FormatError);
+    }
+
+    let record = RecordRef {
+        name,
+        type_,
+        class,
+        ttl,
+        message,
+        data_start,
+        data_length,
+    };
+    Ok((record, end))
+}
+
+/// Iterator over the questions of a `MessageRef`, it stops after the first error
+#[derive(Debug, Clone)]
+pub struct Questions<'a> {
+    message: &'a [u8],
+    position: usize,
+    remaining: u16,
+}
+
+impl<'a> Iterator for Questions<'a> {
+    type Item = Result<QuestionRef<'a>, DnsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        match read_question(self.message, self.position) {
+            Ok((question, position)) => {
+                self.remaining -= 1;
+                self.position = position;
+                Some(Ok(question))
+            }
+            Err(e) => {
+                self.remaining = 0;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Iterator over a section of records of a `MessageRef`, it stops after the first error
+///
+/// The sections before it are skipped when the first record is read
+#[derive(Debug, Clone)]
+pub struct Records<'a> {
+    message: &'a [u8],
+    position: usize,
+    skip_questions: u16,
+    skip_records: u32,
+    remaining: u16,
+}
+
+impl<'a> Records<'a> {
+    fn skip(&mut self) -> Result<(), DnsError> {
+        while self.skip_questions > 0 {
+            (_, self.position) = read_question(self.message, self.position)?;
+            self.skip_questions -= 1;
+        }
+
+        while self.skip_records > 0 {
+            (_, self.position) = read_record(self.message, self.position)?;
+            self.skip_records -= 1;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<RecordRef<'a>, DnsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let record = self
+            .skip()
+            .and_then(|_| read_record(self.message, self.position));
+
+        match record {
+            Ok((record, position)) => {
+                self.remaining -= 1;
+                self.position = position;
+                Some(Ok(record))
+            }
+            Err(e) => {
+                self.remaining = 0;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::MessageRef;
+    use crate::message::tests::example_message as ns_response;
+    use crate::{DnsError, Edns, Message, Name, Networkable, RecordType};
+
+    fn example_message() -> Message {
+        let mut message = ns_response();
+        message.edns = Some(Edns::new(1232));
+        message
+    }
+
+    #[test]
+    fn reads_sections_lazily() {
+        let bytes = example_message().to_bytes();
+        let message = MessageRef::new(&bytes).unwrap();
+
+        assert_eq!(message.header().id, 1);
+        assert_eq!(message.header().num_additionals, 2);
+
+        let questions: Vec<_> = message.questions().map(Result::unwrap).collect();
+        assert_eq!(questions.len(), 1);
+        assert_eq!(questions[0].name, Name::new("example.com"));
+        assert_eq!(questions[0].type_, RecordType::Ns);
+        assert_eq!(questions[0].to_string(), "example.com. IN NS");
+
+        let answer = message.answers().next().unwrap().unwrap();
+        assert_eq!(answer.ttl, 300);
+        // The name server is compressed on the wire
+        assert_eq!(answer.raw_data().len(), 6);
+        assert_eq!(answer.to_record().unwrap(), example_message().answers[0]);
+
+        assert_eq!(message.authorities().count(), 0);
+
+        let additionals: Vec<_> = message.additionals().map(Result::unwrap).collect();
+        assert_eq!(additionals.len(), 2);
+        assert_eq!(additionals[0].name, Name::new("ns1.example.com"));
+        assert_eq!(additionals[1].type_, RecordType::Opt);
+
+        assert_eq!(message.edns().unwrap(), Some(Edns::new(1232)));
+        assert_eq!(message.to_message().unwrap().to_bytes(), bytes);
+    }
+
+    #[test]
+    fn reports_errors_where_they_are() {
+        let mut bytes = example_message().to_bytes().to_vec();
+        // Cut the message off in the middle of the A record
+        bytes.truncate(bytes.len() - 15);
+        let message = MessageRef::new(&bytes).unwrap();
+
+        // Everything before the broken record can still be read
+        assert!(message.questions().all(|q| q.is_ok()));
+        assert!(message.answers().all(|r| r.is_ok()));
+
+        let additionals: Vec<_> = message.additionals().collect();
+        assert_eq!(additionals.len(), 1);
+        assert!(matches!(additionals[0], Err(DnsError::FormatError)));
+        assert!(message.edns().is_err());
+
+        assert!(MessageRef::new(&bytes[..11]).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn agrees_with_message(bytes in prop::collection::vec(any::<u8>(), 0..512)) {
+            let Ok(message) = MessageRef::new(&bytes) else {
+                return Ok(());
+            };
+
+            let questions: Result<Vec<_>, _> = message.questions().collect();
+            let records: Result<Vec<_>, _> = message
+                .answers()
+                .chain(message.authorities())
+                .chain(message.additionals())
+                .map(|record| record.and_then(|record| record.to_record()))
+                .collect();
+            let edns = message.edns();
+
+            match Message::from_bytes(&mut std::io::Cursor::new(&bytes)) {
+                Ok(owned) => {
+                    let questions = questions.unwrap();
+                    prop_assert_eq!(questions.len(), owned.questions.len());
+                    for (borrowed, owned) in questions.iter().zip(owned.questions.iter()) {
+                        prop_assert_eq!(borrowed.name, owned.name.clone());
+                    }
+                    let records: Vec<_> = records
+                        .unwrap()
+                        .into_iter()
+                        .filter(|record| record.type_ != RecordType::Opt)
+                        .collect();
+                    let mut owned_records = owned.answers.clone();
+                    owned_records.extend(owned.authorities.iter().cloned());
+                    owned_records.extend(owned.additionals.iter().cloned());
+                    prop_assert_eq!(records, owned_records);
+                    prop_assert_eq!(edns.unwrap(), owned.edns);
+                }
+                Err(_) => prop_assert!(questions.is_err() || records.is_err() || edns.is_err()),
+            }
+        }
+    }
+}
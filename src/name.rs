@@ -1,124 +1,302 @@
-use std::fmt::Display;
+use std::cmp::Ordering;
+use std::fmt::{Display, Write};
+use std::hash::{Hash, Hasher};
 use std::io::Cursor;
+use std::str::FromStr;
 
-use bytes::{Buf, Bytes, BytesMut, BufMut};
+use bytes::{Bytes, BytesMut};
 
 use super::Networkable;
+use crate::encode::Encoder;
 use crate::DnsError;
 
-// #[derive(Debug, Clone, Hash, PartialEq, Eq)]
-// pub struct Label(pub String);
+mod name_ref;
+pub use name_ref::{Labels, NameRef};
 
-// TODO: Need to implement hash, partialeq, and eq on my own
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
-pub struct Name {
-    /// This is the domain name
-    /// E.g. www.google.com
-    pub name: String,
+/// Maximum length of a name on the wire, including the length octets (RFC 1035 3.1)
+const MAX_NAME_LENGTH: usize = 255;
+
+/// Maximum length of a single label (RFC 1035 3.1)
+const MAX_LABEL_LENGTH: usize = 63;
 
-    /// This is a vector of all the indices where a label starts
-    /// E.g. www.google.com would have a split_indices of [0, 4, 11]
-    pub split_indices: Vec<usize>,
+/// A domain name, stored as its labels from left to right (the root label isn't stored)
+///
+/// Labels are arbitrary bytes. In text they use the master file escapes (RFC 1035 5.1),
+/// so `\.` is a dot inside a label and `\DDD` is the byte with decimal value DDD.
+///
+/// Comparison, hashing and ordering ignore ASCII case (RFC 4343),
+/// but the original case is kept for output
+#[derive(Debug, Clone, Default)]
+pub struct Name {
+    labels: Vec<Vec<u8>>,
 }
 
 impl Name {
-    // TODO: Checking on the length
+    /// Create a name from its text form, e.g. `www.google.com` or `a\.b.example`
+    ///
+    /// # Panics
+    /// If the name isn't valid, use `str::parse` to handle that case
     pub fn new(name: &str) -> Self {
-        let mut split_indices = vec![0];
-        split_indices.extend(name.match_indices('.').filter_map(|(i, _)| {
-            if i == name.len() - 1 {
-                None
-            } else {
-                Some(i + 1)
+        name.parse().expect("invalid domain name")
+    }
+
+    /// Create a name from its labels, checking the length limits
+    pub fn from_labels(labels: Vec<Vec<u8>>) -> Result<Self, DnsError> {
+        let mut wire_len = 1;
+
+        for label in labels.iter() {
+            if label.is_empty() || label.len() > MAX_LABEL_LENGTH {
+                return Err(DnsError::FormatError);
             }
-        }));
 
-        Self {
-            name: name.to_owned(),
-            split_indices,
+            wire_len += label.len() + 1;
+        }
+
+        if wire_len > MAX_NAME_LENGTH {
+            return Err(DnsError::FormatError);
         }
+
+        Ok(Self { labels })
+    }
+
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    /// The labels of the name, from left to right
+    /// E.g. www.google.com has the labels [www, google, com]
+    pub fn labels(&self) -> &[Vec<u8>] {
+        &self.labels
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.labels.is_empty()
     }
 
     // TODO: Rename this
     /// Get larger and larger subdomains
     /// Eg www.google.com -> [com, google.com, www.google.com]
     pub fn iter_subdomains(&self) -> impl Iterator<Item = String> + '_ {
-        self.split_indices
-            .iter()
+        (0..self.labels.len())
             .rev()
-            .map(|i| self.name[*i..].to_owned())
+            .map(|i| self.suffix(i).to_string())
     }
 
+    /// The number of labels, counting from the right, that both names share
     pub fn matching_level(&self, other: &Name) -> usize {
-        let a = self.iter_subdomains();
-        let b = other.iter_subdomains();
+        self.labels
+            .iter()
+            .rev()
+            .zip(other.labels.iter().rev())
+            .take_while(|(a, b)| a.eq_ignore_ascii_case(b))
+            .count()
+    }
+
+    /// Whether the name is `other` or below it, e.g. www.google.com is in google.com
+    pub fn is_subdomain_of(&self, other: &Name) -> bool {
+        self.matching_level(other) == other.labels.len()
+    }
+
+    /// The name without its first label, the root has no parent
+    pub fn parent(&self) -> Option<Name> {
+        (!self.is_root()).then(|| self.suffix(1))
+    }
 
-        a.zip(b)
-            .enumerate()
-            .map_while(|(i, (a, b))| if a == b { Some(i + 1) } else { None })
-            .last()
-            .unwrap_or(0)
+    /// The name with its ASCII letters in lowercase, which is how names are signed
+    /// and hashed in DNSSEC (RFC 4034 6.2)
+    pub fn to_lowercase(&self) -> Name {
+        Self {
+            labels: self
+                .labels
+                .iter()
+                .map(|label| label.to_ascii_lowercase())
+                .collect(),
+        }
+    }
+
+    /// The name made of the labels starting at `start`
+    /// E.g. a suffix of 1 for www.google.com is google.com
+    pub(crate) fn suffix(&self, start: usize) -> Name {
+        Self {
+            labels: self.labels[start..].to_vec(),
+        }
     }
 }
 
-impl Display for Name {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(&self.name)
+impl PartialEq for Name {
+    fn eq(&self, other: &Self) -> bool {
+        self.labels.len() == other.labels.len()
+            && self
+                .labels
+                .iter()
+                .zip(other.labels.iter())
+                .all(|(a, b)| a.eq_ignore_ascii_case(b))
     }
 }
 
-impl Networkable for Name {
-    fn to_bytes(&self) -> Bytes {
-        let mut ret = BytesMut::new();
+impl Eq for Name {}
 
-        for section in self.name.split('.') {
-            ret.put_u8(section.len() as u8);
-            ret.extend_from_slice(section.as_bytes());
+impl Hash for Name {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.labels.len());
+        for label in self.labels.iter() {
+            state.write_usize(label.len());
+            for byte in label.iter() {
+                state.write_u8(byte.to_ascii_lowercase());
+            }
         }
+    }
+}
 
-        ret.put_u8(0);
+impl Ord for Name {
+    /// Canonical DNS name order (RFC 4034 6.1),
+    /// labels are compared from the right as lowercase byte strings
+    fn cmp(&self, other: &Self) -> Ordering {
+        for (a, b) in self.labels.iter().rev().zip(other.labels.iter().rev()) {
+            let a = a.iter().map(|b| b.to_ascii_lowercase());
+            let b = b.iter().map(|b| b.to_ascii_lowercase());
 
-        ret.into()
+            match a.cmp(b) {
+                Ordering::Equal => continue,
+                ordering => return ordering,
+            }
+        }
+
+        self.labels.len().cmp(&other.labels.len())
     }
+}
 
-    fn from_bytes(bytes: &mut Cursor<&[u8]>) -> Result<Self, DnsError> {
-        let mut parts: Vec<String> = Vec::new();
-        loop {
-            let len = bytes.get_u8() as usize;
-            if len == 0 {
-                break;
+impl PartialOrd for Name {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl FromStr for Name {
+    type Err = DnsError;
+
+    /// Parse the text form of a name. The trailing dot is optional,
+    /// and both `""` and `"."` are the root name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || s == "." {
+            return Ok(Self::root());
+        }
+
+        let mut labels = parse_labels(s)?;
+
+        // A trailing dot leaves an empty label at the end
+        if labels.last().is_some_and(|label| label.is_empty()) {
+            labels.pop();
+        }
+
+        Self::from_labels(labels)
+    }
+}
+
+impl TryFrom<&str> for Name {
+    type Error = DnsError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Display for Name {
+    /// The name without the trailing dot, `{:#}` writes it fully qualified as in zone files
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_root() {
+            return f.write_char('.');
+        }
+
+        for (i, label) in self.labels.iter().enumerate() {
+            if i != 0 {
+                f.write_char('.')?;
             }
 
-            if (len & 0b1100_0000) >> 6 == 0b11 {
-                // Compressed
-                let pointer = (((len & 0b0011_1111) as u16) << 8) | (bytes.get_u8() as u16);
-                let position = bytes.position();
-                bytes.set_position(pointer as u64);
-                let n = Self::from_bytes(bytes)?;
-                bytes.set_position(position);
-                parts.push(n.name);
-                break;
-            } else {
-                // Uncompressed
-                if bytes.remaining() < len {
-                    return Err(DnsError::FormatError);
-                }
+            write_label(f, label)?;
+        }
+
+        if f.alternate() {
+            f.write_char('.')?;
+        }
 
-                let chars = bytes.copy_to_bytes(len);
-                let s = std::str::from_utf8(&chars).or(Err(DnsError::FormatError))?;
-                parts.push(s.to_owned());
+        Ok(())
+    }
+}
+
+/// Write a label in text form, escaping the bytes that have a meaning in zone files
+fn write_label(f: &mut std::fmt::Formatter<'_>, label: &[u8]) -> std::fmt::Result {
+    for byte in label.iter() {
+        match byte {
+            b'.' | b'\\' | b'"' | b'(' | b')' | b';' | b'@' | b'$' => {
+                write!(f, "\\{}", *byte as char)?
             }
+            0x21..=0x7E => f.write_char(*byte as char)?,
+            _ => write!(f, "\\{:03}", byte)?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Split the text form of a name into labels, resolving escape sequences
+fn parse_labels(name: &str) -> Result<Vec<Vec<u8>>, DnsError> {
+    let mut labels = Vec::new();
+    let mut label = Vec::new();
+
+    let mut bytes = name.bytes();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'.' => labels.push(std::mem::take(&mut label)),
+            b'\\' => match bytes.next() {
+                Some(digit @ b'0'..=b'9') => {
+                    let mut value = (digit - b'0') as u16;
+                    for _ in 0..2 {
+                        match bytes.next() {
+                            Some(digit @ b'0'..=b'9') => value = value * 10 + (digit - b'0') as u16,
+                            _ => return Err(DnsError::FormatError),
+                        }
+                    }
+
+                    label.push(u8::try_from(value).or(Err(DnsError::FormatError))?);
+                }
+                Some(escaped) => label.push(escaped),
+                None => return Err(DnsError::FormatError),
+            },
+            _ => label.push(byte),
         }
+    }
+
+    labels.push(label);
+
+    Ok(labels)
+}
 
-        let name = parts.join(".");
+impl Networkable for Name {
+    fn to_bytes(&self) -> Bytes {
+        let mut ret = BytesMut::new();
+        Encoder::uncompressed(&mut ret).put_name_uncompressed(self);
+        ret.into()
+    }
 
-        Ok(Self::new(&name))
+    fn from_bytes(bytes: &mut Cursor<&[u8]>) -> Result<Self, DnsError> {
+        let (name, end) = NameRef::parse(bytes.get_ref(), bytes.position() as usize)?;
+        bytes.set_position(end as u64);
+
+        Ok(name.to_name())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::Name;
+    use std::collections::HashSet;
+    use std::io::Cursor;
+
+    use bytes::BytesMut;
+
+    use super::parse_labels;
+    use crate::encode::Encoder;
+    use crate::{DnsError, Name, Networkable};
 
     #[test]
     fn generates_subdomain_iter() {
@@ -134,4 +312,256 @@ mod tests {
 
         assert_eq!(2, name1.matching_level(&name2));
     }
+
+    #[test]
+    fn compares_ignoring_case() {
+        let lower = Name::new("www.google.com");
+        let mixed = Name::new("WWW.Google.com");
+
+        assert_eq!(lower, mixed);
+        assert_eq!(mixed.to_string(), "WWW.Google.com");
+        assert_ne!(lower, Name::new("www.google.co"));
+        assert_ne!(lower, Name::new("google.com"));
+
+        let set: HashSet<Name> = [lower, mixed].into_iter().collect();
+        assert_eq!(set.len(), 1);
+
+        assert_eq!(
+            3,
+            Name::new("a.GOOGLE.com").matching_level(&Name::new("a.google.COM"))
+        );
+    }
+
+    #[test]
+    fn parses_escapes() {
+        let name = Name::new("a\\.b.\\000\\255\\\\.com");
+
+        let labels: &[&[u8]] = &[b"a.b", &[0, 255, b'\\'], b"com"];
+        assert_eq!(name.labels(), labels);
+        assert_eq!(name.to_string(), "a\\.b.\\000\\255\\\\.com");
+
+        assert_eq!(
+            Name::new("\\w\\w\\w.google.com").to_string(),
+            "www.google.com"
+        );
+        assert_eq!(Name::new("a\\ b\\;c").to_string(), "a\\032b\\;c");
+        assert!(parse_labels("a\\").is_err());
+        assert!(parse_labels("a\\25").is_err());
+        assert!(parse_labels("a\\256").is_err());
+    }
+
+    #[test]
+    fn parses_root_and_trailing_dots() {
+        assert!(Name::new("").is_root());
+        assert!(Name::new(".").is_root());
+        assert_eq!(Name::root().to_string(), ".");
+        assert_eq!(format!("{:#}", Name::root()), ".");
+        assert_eq!(format!("{:#}", Name::new("google.com")), "google.com.");
+
+        assert_eq!(Name::new("google.com."), Name::new("google.com"));
+        assert_eq!(Name::new("google.com.").labels().len(), 2);
+        assert_eq!(Name::new("a\\.").labels(), [b"a.".to_vec()]);
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        for name in ["a..b", ".a", "..", "a.b..", "a\\"] {
+            assert!(name.parse::<Name>().is_err(), "{name}");
+        }
+
+        let label = "a".repeat(63);
+        assert!(Name::try_from(label.as_str()).is_ok());
+        assert!(Name::try_from(format!("{label}a").as_str()).is_err());
+        assert!(Name::from_labels(vec![Vec::new()]).is_err());
+
+        // 4 labels of 63 bytes is 257 bytes on the wire, 255 is the limit
+        let name = [label.as_str(); 4].join(".");
+        assert!(name.parse::<Name>().is_err());
+        let name = format!("{0}.{0}.{0}.{1}", label, "a".repeat(61));
+        assert_eq!(name.parse::<Name>().unwrap().to_bytes().len(), 255);
+    }
+
+    #[test]
+    fn round_trips_binary_labels() {
+        let packet: &[u8] = &[4, b'a', b'.', 0, b' ', 3, 0xFF, b'\\', b'"', 0];
+        let name = parse(packet, 0).unwrap();
+        assert_eq!(name.to_string(), "a\\.\\000\\032.\\255\\\\\\\"");
+
+        let reparsed = Name::new(&name.to_string());
+        assert_eq!(reparsed.labels(), name.labels());
+        assert_eq!(&reparsed.to_bytes()[..], packet);
+    }
+
+    #[test]
+    fn orders_canonically() {
+        // The example from RFC 4034 6.1
+        let ordered = [
+            "example",
+            "a.example",
+            "yljkjljk.a.example",
+            "Z.a.example",
+            "zABC.a.EXAMPLE",
+            "z.example",
+            "*.z.example",
+            "\\200.z.example",
+        ];
+
+        let mut names: Vec<Name> = ordered.iter().rev().map(|n| Name::new(n)).collect();
+        names.sort();
+
+        let sorted: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+        assert_eq!(sorted, ordered);
+        assert!(Name::new("") < Name::new("com"));
+    }
+
+    #[test]
+    fn compresses_repeated_suffixes() {
+        let names = [
+            Name::new("www.google.com"),
+            Name::new("mail.google.com"),
+            Name::new("google.com"),
+        ];
+
+        let mut buf = BytesMut::new();
+        let mut encoder = Encoder::new(&mut buf);
+        for name in names.iter() {
+            encoder.put_name(name);
+        }
+
+        // www.google.com in full, then mail + a pointer to google.com, then just the pointer
+        let expected: &[u8] = &[
+            3, b'w', b'w', b'w', 6, b'g', b'o', b'o', b'g', b'l', b'e', 3, b'c', b'o', b'm', 0, 4,
+            b'm', b'a', b'i', b'l', 0xC0, 4, 0xC0, 4,
+        ];
+        assert_eq!(&buf[..], expected);
+
+        let mut cursor = Cursor::new(&buf[..]);
+        cursor.set_position(16);
+        assert_eq!(
+            Name::from_bytes(&mut cursor).unwrap(),
+            Name::new("mail.google.com")
+        );
+        assert_eq!(
+            Name::from_bytes(&mut cursor).unwrap(),
+            Name::new("google.com")
+        );
+    }
+
+    fn parse(packet: &[u8], position: u64) -> Result<Name, DnsError> {
+        let mut cursor = Cursor::new(packet);
+        cursor.set_position(position);
+        Name::from_bytes(&mut cursor)
+    }
+
+    #[test]
+    fn follows_backward_pointers() {
+        // www.google.com, then mail -> google.com, then a pointer to the mail name
+        let packet: &[u8] = &[
+            3, b'w', b'w', b'w', 6, b'g', b'o', b'o', b'g', b'l', b'e', 3, b'c', b'o', b'm', 0, 4,
+            b'm', b'a', b'i', b'l', 0xC0, 4, 0xC0, 16,
+        ];
+
+        let mut cursor = Cursor::new(packet);
+        cursor.set_position(16);
+        assert_eq!(
+            Name::from_bytes(&mut cursor).unwrap(),
+            Name::new("mail.google.com")
+        );
+        assert_eq!(cursor.position(), 23);
+
+        assert_eq!(parse(packet, 23).unwrap(), Name::new("mail.google.com"));
+    }
+
+    #[test]
+    fn rejects_pointer_to_itself() {
+        let packet: &[u8] = &[0xC0, 0];
+        assert!(matches!(parse(packet, 0), Err(DnsError::FormatError)));
+    }
+
+    #[test]
+    fn rejects_pointer_loop() {
+        // a -> pointer to b, b -> pointer to a
+        let packet: &[u8] = &[1, b'a', 0xC0, 4, 1, b'b', 0xC0, 0];
+        assert!(matches!(parse(packet, 4), Err(DnsError::FormatError)));
+        assert!(matches!(parse(packet, 0), Err(DnsError::FormatError)));
+    }
+
+    #[test]
+    fn rejects_pointer_into_own_labels() {
+        // The pointer jumps back to the start of the same name
+        let packet: &[u8] = &[0, 0, 1, b'a', 1, b'b', 0xC0, 4];
+        assert!(matches!(parse(packet, 2), Err(DnsError::FormatError)));
+    }
+
+    #[test]
+    fn rejects_forward_pointer() {
+        let packet: &[u8] = &[0xC0, 2, 1, b'a', 0];
+        assert!(matches!(parse(packet, 0), Err(DnsError::FormatError)));
+    }
+
+    #[test]
+    fn rejects_pointer_past_buffer() {
+        let packet: &[u8] = &[1, b'a', 0xFF, 0xFF];
+        assert!(matches!(parse(packet, 0), Err(DnsError::FormatError)));
+    }
+
+    #[test]
+    fn rejects_truncated_names() {
+        assert!(matches!(parse(&[], 0), Err(DnsError::FormatError)));
+        assert!(matches!(
+            parse(&[3, b'w', b'w'], 0),
+            Err(DnsError::FormatError)
+        ));
+        assert!(matches!(parse(&[1, b'a'], 0), Err(DnsError::FormatError)));
+        assert!(matches!(parse(&[0, 0xC0], 1), Err(DnsError::FormatError)));
+    }
+
+    #[test]
+    fn rejects_reserved_label_types() {
+        assert!(matches!(
+            parse(&[0b0100_0000, 0], 0),
+            Err(DnsError::FormatError)
+        ));
+        assert!(matches!(
+            parse(&[0b1000_0000, 0], 0),
+            Err(DnsError::FormatError)
+        ));
+    }
+
+    #[test]
+    fn rejects_long_names() {
+        // 4 labels of 63 bytes is 257 bytes with the length octets and root label
+        let mut packet = Vec::new();
+        for _ in 0..4 {
+            packet.push(63);
+            packet.extend_from_slice(&[b'a'; 63]);
+        }
+        packet.push(0);
+        assert!(matches!(parse(&packet, 0), Err(DnsError::FormatError)));
+
+        // Shrinking the last label to 61 bytes brings it to 255, which is allowed
+        let mut packet = packet[..64 * 3].to_vec();
+        packet.push(61);
+        packet.extend_from_slice(&[b'a'; 61]);
+        packet.push(0);
+        assert!(parse(&packet, 0).is_ok());
+    }
+
+    #[test]
+    fn rejects_long_names_built_from_pointers() {
+        // Each name adds a 63 byte label in front of a pointer to the previous one
+        let mut packet = vec![0];
+        let mut previous = 0;
+        for _ in 0..5 {
+            let start = packet.len();
+            packet.push(63);
+            packet.extend_from_slice(&[b'a'; 63]);
+            packet.extend_from_slice(&[0xC0, previous]);
+            previous = start as u8;
+        }
+        assert!(matches!(
+            parse(&packet, previous as u64),
+            Err(DnsError::FormatError)
+        ));
+    }
 }
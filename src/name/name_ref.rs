@@ -0,0 +1,233 @@
+use std::fmt::{Display, Write};
+
+use super::{write_label, Name, MAX_NAME_LENGTH};
+use crate::DnsError;
+
+/// A name borrowed from a message, its labels are read from the message bytes
+/// as they're needed and compression pointers are followed on the way
+///
+/// The name is checked when it's parsed, so reading it afterwards can't fail
+#[derive(Debug, Clone, Copy)]
+pub struct NameRef<'a> {
+    message: &'a [u8],
+    start: usize,
+}
+
+impl<'a> NameRef<'a> {
+    /// Read the name at `position` in `message`,
+    /// returning it and the position of the first byte after it
+    pub(crate) fn parse(message: &'a [u8], position: usize) -> Result<(Self, usize), DnsError> {
+        let read = |position: usize| message.get(position).copied().ok_or(DnsError::FormatError);
+
+        // Length of the uncompressed name on the wire, starting with the root label
+        let mut wire_len = 1;
+
+        // Where the name ends in the message, set by the first pointer
+        let mut end_position = None;
+
+        // Pointers have to point before the labels that contain them,
+        // which means they strictly decrease and can't form a loop
+        let mut pointer_limit = position;
+
+        let mut current = position;
+        loop {
+            let len = read(current)? as usize;
+            if len == 0 {
+                current += 1;
+                break;
+            }
+
+            match (len & 0b1100_0000) >> 6 {
+                0b11 => {
+                    // Compressed
+                    let pointer = ((len & 0b0011_1111) << 8) | read(current + 1)? as usize;
+                    if pointer >= pointer_limit {
+                        return Err(DnsError::FormatError);
+                    }
+
+                    end_position.get_or_insert(current + 2);
+                    pointer_limit = pointer;
+                    current = pointer;
+                }
+                0b00 => {
+                    // Uncompressed
+                    wire_len += len + 1;
+                    if wire_len > MAX_NAME_LENGTH || current + 1 + len > message.len() {
+                        return Err(DnsError::FormatError);
+                    }
+
+                    current += 1 + len;
+                }
+                // 0b01 and 0b10 are reserved label types
+                _ => return Err(DnsError::FormatError),
+            }
+        }
+
+        let name = Self {
+            message,
+            start: position,
+        };
+        Ok((name, end_position.unwrap_or(current)))
+    }
+
+    /// The labels of the name, from left to right
+    pub fn labels(&self) -> Labels<'a> {
+        Labels {
+            message: self.message,
+            position: self.start,
+        }
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.labels().next().is_none()
+    }
+
+    /// Copy the name out of the message
+    pub fn to_name(&self) -> Name {
+        Name {
+            labels: self.labels().map(<[u8]>::to_vec).collect(),
+        }
+    }
+}
+
+impl PartialEq for NameRef<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        labels_eq(self.labels(), other.labels())
+    }
+}
+
+impl Eq for NameRef<'_> {}
+
+impl PartialEq<Name> for NameRef<'_> {
+    fn eq(&self, other: &Name) -> bool {
+        labels_eq(self.labels(), other.labels.iter().map(Vec::as_slice))
+    }
+}
+
+impl PartialEq<NameRef<'_>> for Name {
+    fn eq(&self, other: &NameRef<'_>) -> bool {
+        other == self
+    }
+}
+
+/// Compare labels ignoring ASCII case, like `Name` does
+fn labels_eq<'a, 'b>(
+    mut a: impl Iterator<Item = &'a [u8]>,
+    mut b: impl Iterator<Item = &'b [u8]>,
+) -> bool {
+    loop {
+        match (a.next(), b.next()) {
+            (None, None) => return true,
+            (Some(a), Some(b)) if a.eq_ignore_ascii_case(b) => continue,
+            _ => return false,
+        }
+    }
+}
+
+impl Display for NameRef<'_> {
+    /// The same text as the `Name` it would be copied to
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_root() {
+            return f.write_char('.');
+        }
+
+        for (i, label) in self.labels().enumerate() {
+            if i != 0 {
+                f.write_char('.')?;
+            }
+
+            write_label(f, label)?;
+        }
+
+        if f.alternate() {
+            f.write_char('.')?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Iterator over the labels of a `NameRef`
+#[derive(Debug, Clone)]
+pub struct Labels<'a> {
+    message: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Iterator for Labels<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The name was checked when it was parsed, so the reads are in bounds
+        loop {
+            let len = self.message[self.position] as usize;
+            match len {
+                0 => return None,
+                len if len & 0b1100_0000 == 0b1100_0000 => {
+                    let low = self.message[self.position + 1] as usize;
+                    self.position = ((len & 0b0011_1111) << 8) | low;
+                }
+                len => {
+                    let label = &self.message[self.position + 1..self.position + 1 + len];
+                    self.position += 1 + len;
+                    return Some(label);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NameRef;
+    use crate::{DnsError, Name, Networkable};
+
+    #[test]
+    fn follows_compression_pointers() {
+        // google.com at 0, then www and a pointer to it at 12
+        let mut message = Name::new("google.com").to_bytes().to_vec();
+        message.extend_from_slice(&[3, b'w', b'w', b'w', 0xC0, 0]);
+
+        let (name, end) = NameRef::parse(&message, 12).unwrap();
+        assert_eq!(end, message.len());
+        assert_eq!(
+            name.labels().collect::<Vec<_>>(),
+            [&b"www"[..], b"google", b"com"]
+        );
+        assert_eq!(name, Name::new("WWW.google.com"));
+        assert_eq!(name.to_name(), Name::new("www.google.com"));
+        assert_eq!(format!("{name:#}"), "www.google.com.");
+
+        let (google, end) = NameRef::parse(&message, 0).unwrap();
+        assert_eq!(end, 12);
+        assert_ne!(name, google);
+        assert_eq!(google.to_string(), "google.com");
+
+        let (root, _) = NameRef::parse(&[0], 0).unwrap();
+        assert!(root.is_root());
+        assert_eq!(root.to_string(), ".");
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        let invalid: [&[u8]; 5] = [
+            // A pointer to itself
+            &[0xC0, 0],
+            // A label that runs past the end
+            &[3, b'a', b'b'],
+            // No root label
+            &[1, b'a'],
+            // A reserved label type
+            &[0b0100_0001, 0],
+            // A truncated pointer
+            &[0xC0],
+        ];
+
+        for message in invalid {
+            assert!(matches!(
+                NameRef::parse(message, 0),
+                Err(DnsError::FormatError)
+            ));
+        }
+    }
+}
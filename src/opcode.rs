@@ -0,0 +1,70 @@
+use std::fmt::Display;
+
+use crate::UnknownValue;
+
+/// The kind of query a message is
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum Opcode {
+    #[default]
+    Query,
+    /// Inverse query, obsolete (RFC 3425)
+    IQuery,
+    Status,
+    Notify,
+    Update,
+    /// DNS Stateful Operations (RFC 8490)
+    Dso,
+    Unknown(UnknownValue<u8>),
+}
+
+impl Opcode {
+    pub fn to_int(&self) -> u8 {
+        match self {
+            Self::Query => 0,
+            Self::IQuery => 1,
+            Self::Status => 2,
+            Self::Notify => 4,
+            Self::Update => 5,
+            Self::Dso => 6,
+            Self::Unknown(v) => v.get(),
+        }
+    }
+
+    pub fn from_int(v: u8) -> Self {
+        match v {
+            0 => Self::Query,
+            1 => Self::IQuery,
+            2 => Self::Status,
+            4 => Self::Notify,
+            5 => Self::Update,
+            6 => Self::Dso,
+            v => Self::Unknown(UnknownValue::new(v)),
+        }
+    }
+}
+
+impl From<u8> for Opcode {
+    fn from(value: u8) -> Self {
+        Self::from_int(value)
+    }
+}
+
+impl From<Opcode> for u8 {
+    fn from(value: Opcode) -> Self {
+        value.to_int()
+    }
+}
+
+impl Display for Opcode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Query => f.write_str("QUERY"),
+            Self::IQuery => f.write_str("IQUERY"),
+            Self::Status => f.write_str("STATUS"),
+            Self::Notify => f.write_str("NOTIFY"),
+            Self::Update => f.write_str("UPDATE"),
+            Self::Dso => f.write_str("DSO"),
+            Self::Unknown(v) => write!(f, "OPCODE{v}"),
+        }
+    }
+}
@@ -1,17 +1,19 @@
 use bytes::{Bytes, BytesMut};
+use std::fmt::Display;
 use std::io::Cursor;
 
-use bytes::Buf;
 use tracing::instrument;
 
 use super::{Name, Networkable};
-use crate::{DnsError, RecordType};
+use crate::encode::{Encoder, Sink};
+use crate::read::CheckedBuf;
+use crate::{DnsClass, DnsError, RecordType};
 
 #[derive(Debug, Clone)]
 pub struct Question {
     pub name: Name,
     pub type_: RecordType,
-    pub class: u16,
+    pub class: DnsClass,
 }
 
 impl Question {
@@ -19,31 +21,39 @@ impl Question {
         Self {
             name,
             type_,
-            class: 1,
+            class: DnsClass::In,
         }
     }
+
+    pub(crate) fn encode<'m>(&'m self, encoder: &mut Encoder<'m, impl Sink>) {
+        encoder.put_name(&self.name);
+        encoder.put_u16(self.type_.to_int());
+        encoder.put_u16(self.class.to_int());
+    }
+}
+
+impl Display for Question {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#} {} {}", self.name, self.class, self.type_)
+    }
 }
 
 impl Networkable for Question {
     #[instrument(level = "trace", skip_all)]
     fn to_bytes(&self) -> Bytes {
         let mut ret = BytesMut::new();
-
-        ret.extend_from_slice(&self.name.to_bytes());
-        ret.extend_from_slice(&(self.type_ as u16).to_be_bytes());
-        ret.extend_from_slice(&self.class.to_be_bytes());
-
+        self.encode(&mut Encoder::uncompressed(&mut ret));
         ret.into()
     }
 
     #[instrument(level = "trace", skip_all)]
     fn from_bytes(bytes: &mut Cursor<&[u8]>) -> Result<Self, DnsError> {
-        let name = Name::from_bytes(bytes).unwrap();
+        let name = Name::from_bytes(bytes)?;
 
-        let type_ = bytes.get_u16();
-        let type_ = RecordType::from_int(type_).ok_or(DnsError::FormatError)?;
+        let type_ = bytes.read_u16()?;
+        let type_ = RecordType::from_int(type_);
 
-        let class = bytes.get_u16();
+        let class = DnsClass::from_int(bytes.read_u16()?);
 
         Ok(Self { name, type_, class })
     }
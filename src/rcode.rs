@@ -0,0 +1,191 @@
+use std::fmt::Display;
+
+use crate::{DnsError, UnknownValue};
+
+/// The response code of a message
+///
+/// It's 12 bits, the lower 4 are in the header and the upper 8 in the OPT record (RFC 6891 6.1.3)
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum Rcode {
+    #[default]
+    NoError,
+    FormErr,
+    ServFail,
+    NxDomain,
+    NotImp,
+    Refused,
+    YxDomain,
+    YxRrset,
+    NxRrset,
+    NotAuth,
+    NotZone,
+    DsoTypeNi,
+    BadVers,
+    BadKey,
+    BadTime,
+    BadMode,
+    BadName,
+    BadAlg,
+    BadTrunc,
+    BadCookie,
+    Unknown(UnknownValue<u16>),
+}
+
+impl Rcode {
+    pub fn to_int(&self) -> u16 {
+        match self {
+            Self::NoError => 0,
+            Self::FormErr => 1,
+            Self::ServFail => 2,
+            Self::NxDomain => 3,
+            Self::NotImp => 4,
+            Self::Refused => 5,
+            Self::YxDomain => 6,
+            Self::YxRrset => 7,
+            Self::NxRrset => 8,
+            Self::NotAuth => 9,
+            Self::NotZone => 10,
+            Self::DsoTypeNi => 11,
+            Self::BadVers => 16,
+            Self::BadKey => 17,
+            Self::BadTime => 18,
+            Self::BadMode => 19,
+            Self::BadName => 20,
+            Self::BadAlg => 21,
+            Self::BadTrunc => 22,
+            Self::BadCookie => 23,
+            Self::Unknown(v) => v.get(),
+        }
+    }
+
+    pub fn from_int(v: u16) -> Self {
+        match v {
+            0 => Self::NoError,
+            1 => Self::FormErr,
+            2 => Self::ServFail,
+            3 => Self::NxDomain,
+            4 => Self::NotImp,
+            5 => Self::Refused,
+            6 => Self::YxDomain,
+            7 => Self::YxRrset,
+            8 => Self::NxRrset,
+            9 => Self::NotAuth,
+            10 => Self::NotZone,
+            11 => Self::DsoTypeNi,
+            16 => Self::BadVers,
+            17 => Self::BadKey,
+            18 => Self::BadTime,
+            19 => Self::BadMode,
+            20 => Self::BadName,
+            21 => Self::BadAlg,
+            22 => Self::BadTrunc,
+            23 => Self::BadCookie,
+            v => Self::Unknown(UnknownValue::new(v)),
+        }
+    }
+
+    /// Combine the 4 bits from the header with the 8 bits from the OPT record
+    pub fn from_parts(header: u8, extended: u8) -> Self {
+        Self::from_int((extended as u16) << 4 | (header & 0xF) as u16)
+    }
+
+    /// The lower 4 bits, which go in the header
+    pub fn header_bits(&self) -> u8 {
+        (self.to_int() & 0xF) as u8
+    }
+
+    /// The upper 8 bits, which go in the OPT record
+    pub fn extended_bits(&self) -> u8 {
+        (self.to_int() >> 4) as u8
+    }
+
+    /// Whether the code can only be sent in a message with an OPT record
+    pub fn is_extended(&self) -> bool {
+        self.extended_bits() != 0
+    }
+}
+
+impl From<u16> for Rcode {
+    fn from(value: u16) -> Self {
+        Self::from_int(value)
+    }
+}
+
+impl From<Rcode> for u16 {
+    fn from(value: Rcode) -> Self {
+        value.to_int()
+    }
+}
+
+impl From<&DnsError> for Rcode {
+    fn from(value: &DnsError) -> Self {
+        match value {
+            DnsError::FormatError => Self::FormErr,
+            DnsError::ServerFailure(_) => Self::ServFail,
+            DnsError::NameError => Self::NxDomain,
+            DnsError::NotImplemented => Self::NotImp,
+            DnsError::Refused => Self::Refused,
+        }
+    }
+}
+
+impl Display for Rcode {
+    /// The mnemonic dig uses, or `RCODE123` for codes without one
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mnemonic = match self {
+            Self::NoError => "NOERROR",
+            Self::FormErr => "FORMERR",
+            Self::ServFail => "SERVFAIL",
+            Self::NxDomain => "NXDOMAIN",
+            Self::NotImp => "NOTIMP",
+            Self::Refused => "REFUSED",
+            Self::YxDomain => "YXDOMAIN",
+            Self::YxRrset => "YXRRSET",
+            Self::NxRrset => "NXRRSET",
+            Self::NotAuth => "NOTAUTH",
+            Self::NotZone => "NOTZONE",
+            Self::DsoTypeNi => "DSOTYPENI",
+            Self::BadVers => "BADVERS",
+            Self::BadKey => "BADKEY",
+            Self::BadTime => "BADTIME",
+            Self::BadMode => "BADMODE",
+            Self::BadName => "BADNAME",
+            Self::BadAlg => "BADALG",
+            Self::BadTrunc => "BADTRUNC",
+            Self::BadCookie => "BADCOOKIE",
+            Self::Unknown(v) => return write!(f, "RCODE{v}"),
+        };
+
+        f.write_str(mnemonic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DnsError, Rcode};
+
+    #[test]
+    fn splits_extended_rcodes() {
+        assert_eq!(Rcode::BadCookie.to_int(), 23);
+        assert_eq!(Rcode::BadCookie.header_bits(), 7);
+        assert_eq!(Rcode::BadCookie.extended_bits(), 1);
+        assert_eq!(Rcode::from_parts(7, 1), Rcode::BadCookie);
+        assert_eq!(Rcode::from_parts(7, 0), Rcode::YxRrset);
+
+        assert_eq!(Rcode::BadVers.header_bits(), 0);
+        assert!(Rcode::BadVers.is_extended());
+        assert!(!Rcode::Refused.is_extended());
+
+        assert_eq!(Rcode::from_parts(0xF, 0xFF).to_int(), 4095);
+        assert!(matches!(Rcode::from_int(12), Rcode::Unknown(v) if v.get() == 12));
+        assert_eq!(Rcode::from_int(12).to_string(), "RCODE12");
+        assert_eq!(Rcode::NxDomain.to_string(), "NXDOMAIN");
+    }
+
+    #[test]
+    fn converts_errors() {
+        assert_eq!(Rcode::from(&DnsError::FormatError), Rcode::FormErr);
+        assert_eq!(Rcode::from(&DnsError::NameError), Rcode::NxDomain);
+        assert_eq!(Rcode::from(&DnsError::Refused), Rcode::Refused);
+    }
+}
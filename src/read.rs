@@ -0,0 +1,42 @@
+use bytes::{Buf, Bytes};
+
+use crate::DnsError;
+
+/// Reads that return `DnsError::FormatError` when the packet is too short,
+/// instead of panicking like the `Buf::get_*` methods
+pub(crate) trait CheckedBuf: Buf {
+    fn ensure_remaining(&self, len: usize) -> Result<(), DnsError> {
+        if self.remaining() < len {
+            return Err(DnsError::FormatError);
+        }
+
+        Ok(())
+    }
+
+    fn read_u8(&mut self) -> Result<u8, DnsError> {
+        self.ensure_remaining(1)?;
+        Ok(self.get_u8())
+    }
+
+    fn read_u16(&mut self) -> Result<u16, DnsError> {
+        self.ensure_remaining(2)?;
+        Ok(self.get_u16())
+    }
+
+    fn read_u32(&mut self) -> Result<u32, DnsError> {
+        self.ensure_remaining(4)?;
+        Ok(self.get_u32())
+    }
+
+    fn read_u128(&mut self) -> Result<u128, DnsError> {
+        self.ensure_remaining(16)?;
+        Ok(self.get_u128())
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<Bytes, DnsError> {
+        self.ensure_remaining(len)?;
+        Ok(self.copy_to_bytes(len))
+    }
+}
+
+impl<T: Buf> CheckedBuf for T {}
@@ -1,51 +1,156 @@
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-pub enum RecordType {
-    A = 1,
-    Ns = 2,
-    Md = 3,
-    Mf = 4,
-    Cname = 5,
-    Soa = 6,
-    Mb = 7,
-    Mg = 8,
-    Mr = 9,
-    Null = 10,
-    Wks = 11,
-    Ptr = 12,
-    Hinfo = 13,
-    Minfo = 14,
-    Mx = 15,
-    Txt = 16,
-    Aaaa = 28,
-    Opt = 41,
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::{DnsError, UnknownValue};
+
+macro_rules! record_types {
+    ($($variant:ident = $value:literal => $mnemonic:literal,)*) => {
+        /// The type of a resource record or question
+        ///
+        /// Types that dnrs doesn't know about are kept as `Unknown` (RFC 3597)
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+        pub enum RecordType {
+            $($variant,)*
+            Unknown(UnknownValue<u16>),
+        }
+
+        impl RecordType {
+            pub fn to_int(&self) -> u16 {
+                match self {
+                    $(Self::$variant => $value,)*
+                    Self::Unknown(v) => v.get(),
+                }
+            }
+
+            pub fn from_int(v: u16) -> Self {
+                match v {
+                    $($value => Self::$variant,)*
+                    v => Self::Unknown(UnknownValue::new(v)),
+                }
+            }
+
+            fn mnemonic(&self) -> Option<&'static str> {
+                match self {
+                    $(Self::$variant => Some($mnemonic),)*
+                    Self::Unknown(_) => None,
+                }
+            }
+
+            fn from_mnemonic(s: &str) -> Option<Self> {
+                $(
+                    if s.eq_ignore_ascii_case($mnemonic) {
+                        return Some(Self::$variant);
+                    }
+                )*
+
+                None
+            }
+        }
+    };
+}
+
+record_types! {
+    A = 1 => "A",
+    Ns = 2 => "NS",
+    Md = 3 => "MD",
+    Mf = 4 => "MF",
+    Cname = 5 => "CNAME",
+    Soa = 6 => "SOA",
+    Mb = 7 => "MB",
+    Mg = 8 => "MG",
+    Mr = 9 => "MR",
+    Null = 10 => "NULL",
+    Wks = 11 => "WKS",
+    Ptr = 12 => "PTR",
+    Hinfo = 13 => "HINFO",
+    Minfo = 14 => "MINFO",
+    Mx = 15 => "MX",
+    Txt = 16 => "TXT",
+    Rp = 17 => "RP",
+    Afsdb = 18 => "AFSDB",
+    Rt = 21 => "RT",
+    Px = 26 => "PX",
+    Aaaa = 28 => "AAAA",
+    Srv = 33 => "SRV",
+    Naptr = 35 => "NAPTR",
+    Kx = 36 => "KX",
+    Dname = 39 => "DNAME",
+    Opt = 41 => "OPT",
+    Ds = 43 => "DS",
+    Rrsig = 46 => "RRSIG",
+    Nsec = 47 => "NSEC",
+    Dnskey = 48 => "DNSKEY",
+    Nsec3 = 50 => "NSEC3",
+    Nsec3param = 51 => "NSEC3PARAM",
+    Svcb = 64 => "SVCB",
+    Https = 65 => "HTTPS",
+    Caa = 257 => "CAA",
 }
 
-impl RecordType {
-    pub fn to_int(&self) -> u16 {
-        *self as u16
+impl From<u16> for RecordType {
+    fn from(value: u16) -> Self {
+        Self::from_int(value)
     }
+}
+
+impl From<RecordType> for u16 {
+    fn from(value: RecordType) -> Self {
+        value.to_int()
+    }
+}
 
-    pub fn from_int(v: u16) -> Option<Self> {
-        match v {
-            1 => Some(Self::A),
-            2 => Some(Self::Ns),
-            3 => Some(Self::Md),
-            4 => Some(Self::Mf),
-            5 => Some(Self::Cname),
-            6 => Some(Self::Soa),
-            7 => Some(Self::Mb),
-            8 => Some(Self::Mg),
-            9 => Some(Self::Mr),
-            10 => Some(Self::Null),
-            11 => Some(Self::Wks),
-            12 => Some(Self::Ptr),
-            13 => Some(Self::Hinfo),
-            14 => Some(Self::Minfo),
-            15 => Some(Self::Mx),
-            16 => Some(Self::Txt),
-            28 => Some(Self::Aaaa),
-            41 => Some(Self::Opt),
-            _ => None,
+impl Display for RecordType {
+    /// The mnemonic of the type, or `TYPE123` for types without one (RFC 3597 5)
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.mnemonic() {
+            Some(mnemonic) => f.write_str(mnemonic),
+            None => write!(f, "TYPE{}", self.to_int()),
         }
     }
 }
+
+impl FromStr for RecordType {
+    type Err = DnsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(type_) = Self::from_mnemonic(s) {
+            return Ok(type_);
+        }
+
+        match s.get(..4) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("TYPE") => s[4..]
+                .parse::<u16>()
+                .map(Self::from_int)
+                .or(Err(DnsError::FormatError)),
+            _ => Err(DnsError::FormatError),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::RecordType;
+
+    #[test]
+    fn converts_unknown_types() {
+        assert_eq!(RecordType::from_int(1), RecordType::A);
+        assert!(matches!(RecordType::from_int(123), RecordType::Unknown(v) if v.get() == 123));
+        assert_eq!(RecordType::from_int(123).to_int(), 123);
+    }
+
+    #[test]
+    fn parses_and_displays_text() {
+        assert_eq!(RecordType::Cname.to_string(), "CNAME");
+        assert_eq!(RecordType::from_int(123).to_string(), "TYPE123");
+
+        assert_eq!("cname".parse::<RecordType>().unwrap(), RecordType::Cname);
+        assert_eq!(
+            "TYPE123".parse::<RecordType>().unwrap(),
+            RecordType::from_int(123)
+        );
+        assert_eq!("type1".parse::<RecordType>().unwrap(), RecordType::A);
+        assert!("TYPE".parse::<RecordType>().is_err());
+        assert!("TYPE65536".parse::<RecordType>().is_err());
+        assert!("BOGUS".parse::<RecordType>().is_err());
+    }
+}
@@ -1,22 +1,40 @@
 use std::io::Cursor;
 use std::net::{IpAddr, Ipv4Addr};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use async_recursion::async_recursion;
 use dnrs::{
-    DnsError, Flags, Header, Message, Name, Networkable, Question, RecordData, RecordType,
-    ResourceRecord,
+    ClientSubnet, DnsClass, DnsError, Edns, EdnsOption, FailureReason, Message, MessageBuilder,
+    MessageRef, Name, Networkable, Opcode, Question, Rcode, RecordData, RecordType, ResourceRecord,
+    MIN_UDP_PAYLOAD_SIZE,
 };
 use itertools::{Either, Itertools};
 use rand::seq::SliceRandom;
-use tokio::net::UdpSocket;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
 use tracing::{debug, info, instrument, trace, warn};
 
-use crate::util::set_response_flags;
+use crate::util::{error_response, response_edns};
 
 mod cache;
 use cache::Cache;
 
+mod cookies;
+pub use cookies::CookiePolicy;
+use cookies::{ClientCookies, ServerCookies};
+
+mod validator;
+pub use validator::TrustAnchor;
+use validator::{denial_records, signs, Security, Validator};
+
+/// The largest udp payload that's sent or accepted
+/// http://www.dnsflagday.net/2020/
+const EDNS_UDP_PAYLOAD_SIZE: u16 = 1232;
+
+/// How long to wait for a nameserver to respond
+const QUERY_TIMEOUT: Duration = Duration::from_secs(3);
+
 // TODO: Make this a list of hosts?
 const ROOT_NAMESERVERS: [IpAddr; 13] = [
     IpAddr::V4(Ipv4Addr::new(198, 41, 0, 4)),
@@ -34,7 +52,70 @@ const ROOT_NAMESERVERS: [IpAddr; 13] = [
     IpAddr::V4(Ipv4Addr::new(202, 12, 27, 33)),
 ];
 
-pub async fn run(ip: &str, port: u16) {
+/// Settings for the resolver
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// How many bits of an ipv4 client's address are sent upstream in the client subnet option,
+    /// 0 stops the option from being sent. It's off by default since it tells nameservers
+    /// about the clients, RFC 7871 11.1 recommends 24 when it's used
+    pub ecs_ipv4_prefix: u8,
+    /// The same as `ecs_ipv4_prefix` for ipv6 clients, where 56 is recommended
+    pub ecs_ipv6_prefix: u8,
+    pub cookie_policy: CookiePolicy,
+    /// How often the secret that server cookies are signed with is replaced
+    pub cookie_secret_lifetime: Duration,
+    /// The root DS records that DNSSEC validation starts from, None turns validation off
+    pub trust_anchor: Option<Arc<TrustAnchor>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            ecs_ipv4_prefix: 0,
+            ecs_ipv6_prefix: 0,
+            cookie_policy: CookiePolicy::Enabled,
+            cookie_secret_lifetime: Duration::from_secs(24 * 60 * 60),
+            trust_anchor: Some(Arc::new(TrustAnchor::default())),
+        }
+    }
+}
+
+impl Config {
+    /// The subnet to send upstream for a client, if any
+    fn client_subnet(&self, client: IpAddr, request: Option<&Edns>) -> Option<ClientSubnet> {
+        // A client that sent its own subnet decides what's revealed, up to our limit
+        let (address, source_prefix) = match request.and_then(Edns::client_subnet) {
+            Some(subnet) => (subnet.address, subnet.source_prefix),
+            None => (client, u8::MAX),
+        };
+
+        let max_prefix = match address {
+            IpAddr::V4(_) => self.ecs_ipv4_prefix,
+            IpAddr::V6(_) => self.ecs_ipv6_prefix,
+        };
+
+        let subnet = ClientSubnet::new(address, source_prefix.min(max_prefix));
+        (subnet.source_prefix != 0).then_some(subnet)
+    }
+}
+
+/// The records that answer a question
+#[derive(Debug, Default)]
+pub struct Answer {
+    /// NXDOMAIN when the name doesn't exist, otherwise NOERROR
+    pub rcode: Rcode,
+    /// The answer's RRsets with their RRSIGs, empty for a negative answer
+    pub records: Vec<ResourceRecord>,
+    /// The SOA of a negative answer and the NSEC and NSEC3 records that prove
+    /// what doesn't exist, with their RRSIGs
+    pub authorities: Vec<ResourceRecord>,
+    /// What DNSSEC validation found out about the answer
+    pub security: Security,
+    /// The client subnet that the nameserver scoped the answer to
+    pub scope: Option<ClientSubnet>,
+}
+
+pub async fn run(ip: &str, port: u16, config: Config) {
     info!("Starting udp server");
 
     // TODO: Spawn 2 tasks for tcp and udp
@@ -43,12 +124,16 @@ pub async fn run(ip: &str, port: u16) {
         .expect("Couldn't run server");
 
     let cache = Arc::new(Mutex::new(Cache::new()));
+    let server_cookies = Arc::new(Mutex::new(ServerCookies::new(
+        config.cookie_secret_lifetime,
+    )));
+    let client_cookies = Arc::new(Mutex::new(ClientCookies::new()));
 
     let sock = Arc::new(sock);
+    let config = Arc::new(config);
 
     loop {
-        // http://www.dnsflagday.net/2020/
-        let mut buf = [0; 1232];
+        let mut buf = [0; EDNS_UDP_PAYLOAD_SIZE as usize];
 
         // TODO: Handle if this errors
         let (len, addr) = sock.recv_from(&mut buf).await.unwrap();
@@ -56,83 +141,296 @@ pub async fn run(ip: &str, port: u16) {
 
         let sock = Arc::clone(&sock);
         let cache = Arc::clone(&cache);
+        let server_cookies = Arc::clone(&server_cookies);
+        let client_cookies = Arc::clone(&client_cookies);
+        let config = Arc::clone(&config);
 
         tokio::spawn(async move {
-            if let Some(response) = handle_request(&buf[0..len], cache).await {
-                sock.send_to(&response.to_bytes(), addr).await.ok();
+            let response = handle_request(
+                &buf[0..len],
+                addr.ip(),
+                cache,
+                server_cookies,
+                client_cookies,
+                &config,
+            )
+            .await;
+
+            if let Some((response, max_size)) = response {
+                let mut buf = Vec::with_capacity(max_size);
+                match response.encode_limited(&mut buf, max_size) {
+                    Ok(()) => {
+                        sock.send_to(&buf, addr).await.ok();
+                    }
+                    Err(e) => warn!(error = ?e, "failed to encode response"),
+                }
             }
         });
     }
 }
 
 #[instrument(skip_all)]
-async fn handle_request(data: &[u8], cache: Arc<Mutex<Cache>>) -> Option<Message> {
-    let mut request = Message::from_bytes(&mut Cursor::new(data)).unwrap();
-    debug!(?request, "parsed request");
-
-    if request.header.flags.qr() {
-        warn!(?request, "discarding request");
+async fn handle_request(
+    data: &[u8],
+    client: IpAddr,
+    cache: Arc<Mutex<Cache>>,
+    server_cookies: Arc<Mutex<ServerCookies>>,
+    client_cookies: Arc<Mutex<ClientCookies>>,
+    config: &Config,
+) -> Option<(Message, usize)> {
+    // Without a header there's nothing to respond to
+    let request = MessageRef::new(data).ok()?;
+    let header = request.header();
+
+    if header.flags.qr() {
+        warn!(?header, "discarding request");
         return None;
     }
 
-    if request.header.num_questions != 1 || request.header.flags.opcode() != 0 {
-        warn!(?request, "unimplemented request");
-        let mut flags = set_response_flags(request.header.flags);
-        flags.set_rcode(4);
+    // Reading the OPT record only walks the framing of the sections, so every
+    // record's data is parsed as well to reject malformed requests
+    let checked = request
+        .answers()
+        .chain(request.authorities())
+        .chain(request.additionals())
+        .try_for_each(|record| record?.data().map(drop));
+    let request_edns = match checked.and_then(|_| request.edns()) {
+        Ok(edns) => edns,
+        Err(e) => {
+            warn!(error = ?e, "failed to parse request");
+            let response = error_response(header, Rcode::from(&e), None);
+            return Some((response, MIN_UDP_PAYLOAD_SIZE as usize));
+        }
+    };
+
+    // Responses have to fit in what the client can receive (RFC 6891 6.2.5),
+    // but aren't made larger than what we advertise so they don't get fragmented
+    let max_size = request_edns
+        .as_ref()
+        .map_or(MIN_UDP_PAYLOAD_SIZE, Edns::max_payload_size)
+        .min(EDNS_UDP_PAYLOAD_SIZE) as usize;
+
+    let response = respond(
+        &request,
+        request_edns,
+        client,
+        cache,
+        server_cookies,
+        client_cookies,
+        config,
+    )
+    .await?;
+
+    Some((response, max_size))
+}
 
-        let header = Header::new(request.header.id, flags);
-        return Some(Message::new(header));
+/// The response to a request that was parsed
+async fn respond(
+    request: &MessageRef<'_>,
+    request_edns: Option<Edns>,
+    client: IpAddr,
+    cache: Arc<Mutex<Cache>>,
+    server_cookies: Arc<Mutex<ServerCookies>>,
+    client_cookies: Arc<Mutex<ClientCookies>>,
+    config: &Config,
+) -> Option<Message> {
+    let header = request.header();
+    let questions = || request.questions().filter_map(Result::ok);
+    debug!(?header, questions = %questions().format(", "), "parsed request");
+
+    let mut edns = response_edns(request_edns.as_ref(), EDNS_UDP_PAYLOAD_SIZE);
+
+    if let Some(request_edns) = &request_edns {
+        if request_edns.version != 0 {
+            warn!(version = request_edns.version, "unsupported edns version");
+            let edns = Edns::new(EDNS_UDP_PAYLOAD_SIZE);
+            return Some(error_response(header, Rcode::BadVers, Some(edns)));
+        }
     }
 
-    let question = request.questions.remove(0);
-
-    // TODO: If rd is false check cache, otherwise resolve
-
-    let result = resolve(question.clone(), Arc::clone(&cache)).await;
+    // Clients that sent a cookie get a new server cookie in every response (RFC 7873 5.2)
+    let request_cookie = request_edns.as_ref().and_then(Edns::cookie);
+    let valid_cookie = match (request_cookie, edns.as_mut()) {
+        (Some(cookie), Some(edns)) if config.cookie_policy != CookiePolicy::Disabled => {
+            let (valid, cookie) = server_cookies.lock().unwrap().respond(cookie, client);
+            edns.options.push(EdnsOption::Cookie(cookie));
+            valid
+        }
+        _ => false,
+    };
 
-    if let Ok(records) = result {
-        let flags = set_response_flags(request.header.flags);
-        let header = Header::new(request.header.id, flags);
+    if config.cookie_policy == CookiePolicy::Required && !valid_cookie {
+        warn!("request without a valid server cookie");
+        let rcode = if request_cookie.is_some() {
+            Rcode::BadCookie
+        } else {
+            Rcode::Refused
+        };
 
-        let mut response = Message::new(header);
-        response.add_question(question);
-        for record in records {
-            response.add_answer(record);
+        let mut response = error_response(header, rcode, edns);
+        for question in questions() {
+            response.add_question(question.to_question());
         }
+        return Some(response);
+    }
 
-        Some(response)
-    } else {
-        warn!("responding with error");
-        let mut flags = set_response_flags(request.header.flags);
-        flags.set_rcode(2);
+    if header.num_questions != 1 || header.flags.opcode() != Opcode::Query {
+        warn!(?header, "unimplemented request");
+        let rcode = Rcode::from(&DnsError::NotImplemented);
+        return Some(error_response(header, rcode, edns));
+    }
 
-        let header = Header::new(request.header.id, flags);
+    let question = questions().next()?.to_question();
 
-        let mut response = Message::new(header);
+    // Only the internet class is resolved, the root servers don't serve anything else
+    if question.class != DnsClass::In {
+        warn!(class = %question.class, "refusing non-internet query");
+        let rcode = Rcode::from(&DnsError::Refused);
+        let mut response = error_response(header, rcode, edns);
         response.add_question(question);
+        return Some(response);
+    }
 
-        Some(response)
+    // TODO: If rd is false check cache, otherwise resolve
+
+    let client_subnet = config.client_subnet(client, request_edns.as_ref());
+    let result = resolve(
+        question.clone(),
+        Arc::clone(&cache),
+        client_cookies,
+        client_subnet,
+        config.trust_anchor.clone(),
+    )
+    .await;
+
+    // Answers that failed validation are only given to clients that check the signatures themselves
+    let checking_disabled = header.flags.cd();
+    let result = result.and_then(|answer| match answer.security {
+        Security::Bogus(reason) | Security::Indeterminate(reason) if !checking_disabled => {
+            Err(DnsError::ServerFailure(reason))
+        }
+        _ => Ok(answer),
+    });
+
+    let dnssec_ok = request_edns
+        .as_ref()
+        .is_some_and(|edns| edns.flags.dnssec_ok());
+
+    match result {
+        Ok(answer) => {
+            // A client that sent a subnet gets it back with the scope of the answer (RFC 7871 7.2.2)
+            let request_subnet = request_edns.as_ref().and_then(Edns::client_subnet);
+            let edns = edns.map(|mut edns| {
+                if let Some(subnet) = request_subnet {
+                    let mut subnet = *subnet;
+                    subnet.scope_prefix = answer.scope.map_or(0, |scope| scope.scope_prefix);
+                    edns.options.push(EdnsOption::ClientSubnet(subnet));
+                }
+                edns
+            });
+
+            // Only clients that set DO get the signatures and proofs (RFC 4035 3.2.1),
+            // and AD is only set for clients that show they understand it (RFC 6840 5.7)
+            let (records, authorities) = if dnssec_ok {
+                (answer.records, answer.authorities)
+            } else {
+                let records = answer
+                    .records
+                    .into_iter()
+                    .filter(|rr| {
+                        rr.type_ != RecordType::Rrsig || question.type_ == RecordType::Rrsig
+                    })
+                    .collect();
+                let authorities = answer
+                    .authorities
+                    .into_iter()
+                    .filter(|rr| rr.type_ == RecordType::Soa)
+                    .collect();
+                (records, authorities)
+            };
+            let authentic = answer.security == Security::Secure && (dnssec_ok || header.flags.ad());
+
+            let response = MessageBuilder::response_to_header(header)
+                .rcode(answer.rcode)
+                .authentic_data(authentic)
+                .question(question)
+                .answers(records)
+                .authorities(authorities)
+                .edns(edns)
+                .build();
+            Some(response)
+        }
+        Err(e) => {
+            warn!(error = ?e, "responding with error");
+            let edns = edns.map(|mut edns| {
+                if let Some(error) = e.extended_error() {
+                    edns.options.push(EdnsOption::ExtendedError(error));
+                }
+                edns
+            });
+
+            let mut response = error_response(header, Rcode::from(&e), edns);
+            response.add_question(question);
+            Some(response)
+        }
     }
 }
 
-#[instrument(skip(cache), ret, err(Debug))]
+/// Resolve a question, using the cache when it has an answer for the client's subnet
+#[instrument(skip(cache, cookies, anchor), ret, err(Debug))]
 #[async_recursion]
 pub async fn resolve(
     question: Question,
     cache: Arc<Mutex<Cache>>,
-) -> Result<Vec<ResourceRecord>, DnsError> {
-    let flags = Flags::default();
+    cookies: Arc<Mutex<ClientCookies>>,
+    client_subnet: Option<ClientSubnet>,
+    anchor: Option<Arc<TrustAnchor>>,
+) -> Result<Answer, DnsError> {
+    let client = client_subnet.map(|subnet| subnet.address);
+    if let Some(answer) = cache.lock().unwrap().get(&question, client) {
+        debug!("answering from cache");
+        return Ok(answer);
+    }
+
+    let answer = query_nameservers(
+        question.clone(),
+        Arc::clone(&cache),
+        cookies,
+        client_subnet,
+        anchor,
+    )
+    .await?;
+    cache.lock().unwrap().insert(&question, &answer);
+
+    Ok(answer)
+}
+
+async fn query_nameservers(
+    question: Question,
+    cache: Arc<Mutex<Cache>>,
+    cookies: Arc<Mutex<ClientCookies>>,
+    client_subnet: Option<ClientSubnet>,
+    anchor: Option<Arc<TrustAnchor>>,
+) -> Result<Answer, DnsError> {
     let id = rand::random::<u16>();
-    let header = Header::new(id, flags);
-    let mut query = Message::new(header);
-    query.add_question(question.clone());
+    let mut query = MessageBuilder::query(id, question.clone()).build();
 
-    let mut response = Vec::new();
+    let mut response = Answer::default();
 
-    let mut buf = [0; 1024];
+    // A server that rejects our cookie gets asked again once with the cookie it sent back
+    let mut retried_cookie = false;
+
+    let validator = anchor.as_ref().map(|anchor| Validator {
+        anchor: Arc::clone(anchor),
+        cache: Arc::clone(&cache),
+        cookies: Arc::clone(&cookies),
+    });
+
+    // The zone that the nameserver was delegated, which has to sign what it answers
+    let mut zone = Name::root();
 
     let mut nameserver = (
-        Name::new(""),
+        Name::root(),
         *ROOT_NAMESERVERS.choose(&mut rand::thread_rng()).unwrap(),
     );
 
@@ -140,55 +438,140 @@ pub async fn resolve(
 
     // TODO: Some kind of work-limiting mechanism
     loop {
-        let (ns_name, ns_ip) = nameserver;
+        let (ns_name, ns_ip) = nameserver.clone();
+
+        // Advertise a payload size so that larger answers don't need to be retried over tcp
+        let mut edns = Edns::new(EDNS_UDP_PAYLOAD_SIZE);
+        edns.flags.set_dnssec_ok(validator.is_some());
+        // Root and TLD servers don't tailor their answers to clients, so they
+        // aren't told about them (RFC 7871 12)
+        let sent_subnet = client_subnet.filter(|_| zone.labels().len() > 1);
+        if let Some(subnet) = sent_subnet {
+            edns.options.push(EdnsOption::ClientSubnet(subnet));
+        }
+        edns.options
+            .push(EdnsOption::Cookie(cookies.lock().unwrap().cookie(ns_ip)));
+        query.edns = Some(edns);
 
         debug!(?ns_name, ?ns_ip, "querying nameserver");
         sock.send_to(&query.to_bytes(), (ns_ip, 53)).await.unwrap();
 
-        // TODO: Need to do validation of this message
-        let response_len = sock.recv(&mut buf).await?;
-        let mut cursor = Cursor::new(&buf[..response_len]);
-        let mut message = Message::from_bytes(&mut cursor).unwrap();
+        let timed_out = |_| DnsError::ServerFailure(FailureReason::Timeout(ns_ip));
+        let mut message =
+            tokio::time::timeout(QUERY_TIMEOUT, receive_response(&sock, ns_ip, id, &cookies))
+                .await
+                .map_err(timed_out)??;
+
+        // A truncated response is missing records, possibly the RRSIGs or proofs
+        // that validation needs, so the whole response is asked for over tcp
+        if message.header.flags.tc() {
+            debug!("response was truncated, retrying over tcp");
+            message = tokio::time::timeout(QUERY_TIMEOUT, query_tcp(&query, ns_ip, &cookies))
+                .await
+                .map_err(timed_out)??;
+        }
 
         debug!("received response from nameserver");
-        trace!(?message);
+        trace!(%message);
 
-        if message.header.num_answers != 0 {
-            debug!(?message.answers, "received answers from nameserver");
-            if let Some(idx) = message
-                .answers
-                .iter()
-                .position(|rr| rr.type_ == question.type_)
-            {
-                response.push(message.answers.remove(idx));
-                return Ok(response);
+        if message.rcode() == Rcode::BadCookie {
+            if retried_cookie {
+                return Err(DnsError::ServerFailure(FailureReason::Other(
+                    "nameserver rejected our cookie".to_owned(),
+                )));
             }
 
-            // If we don't have an answer that matches the question
-            if let Some(rr) = message
-                .answers
-                .iter()
-                .find(|rr| rr.type_ == RecordType::Cname)
-            {
-                response.push(rr.clone());
-
-                let RecordData::Cname(name) = &rr.data else {
-                    unreachable!();
-                };
-
-                info!("received cname from nameserver, re-starting resolution process");
-                let answer = resolve(
-                    Question::new(name.clone(), question.type_),
-                    Arc::clone(&cache),
+            info!("nameserver rejected our cookie, retrying");
+            retried_cookie = true;
+            continue;
+        }
+
+        // Only a scope for the subnet that was sent can be trusted, and it can't be
+        // more specific than what was sent (RFC 7871 7.3)
+        let scope = message
+            .edns
+            .as_ref()
+            .and_then(Edns::client_subnet)
+            .filter(|scope| {
+                sent_subnet.is_some_and(|sent| {
+                    sent.address == scope.address && sent.source_prefix == scope.source_prefix
+                })
+            })
+            .map(|scope| ClientSubnet {
+                scope_prefix: scope.scope_prefix.min(scope.source_prefix),
+                ..*scope
+            });
+        response.scope = narrowest_scope(response.scope, scope);
+
+        if message.header.num_answers != 0 {
+            debug!(?message.answers, "received answers from nameserver");
+            let answers = |type_: RecordType| {
+                let question = &question;
+                message.answers.iter().filter(move |rr| {
+                    rr.name == question.name
+                        && rr.type_ == type_
+                        && question.class.matches(rr.class)
+                })
+            };
+
+            // A CNAME is only followed when the name has no records of the type asked for
+            let type_ = if answers(question.type_).next().is_some() {
+                question.type_
+            } else if answers(RecordType::Cname).next().is_some() {
+                RecordType::Cname
+            } else {
+                return Err(DnsError::ServerFailure(FailureReason::InvalidResponse(
+                    "answer doesn't match the question".to_owned(),
+                )));
+            };
+
+            // The whole RRset is kept with the RRSIGs over it
+            let records: Vec<_> = answers(type_)
+                .chain(
+                    message
+                        .answers
+                        .iter()
+                        .filter(|rr| signs(rr, &question.name, type_)),
                 )
-                .await?;
+                .cloned()
+                .collect();
+            let mut proof = denial_records(&message.authorities);
+
+            if let Some(validator) = &validator {
+                response.security = validator
+                    .validate(&question, &zone, &records, &mut proof, false)
+                    .await;
+            }
 
-                response.extend(answer);
+            let target = records.iter().find_map(|rr| match &rr.data {
+                RecordData::Cname(name) if type_ != question.type_ => Some(name.clone()),
+                _ => None,
+            });
+            response.records.extend(records);
+            response.authorities.extend(proof);
 
+            let Some(target) = target else {
                 return Ok(response);
-            }
+            };
+
+            info!("received cname from nameserver, re-starting resolution process");
+            let answer = resolve(
+                Question::new(target, question.type_),
+                Arc::clone(&cache),
+                Arc::clone(&cookies),
+                client_subnet,
+                anchor.clone(),
+            )
+            .await?;
+
+            // The target decides whether the name exists (RFC 6604 2)
+            response.rcode = answer.rcode;
+            response.records.extend(answer.records);
+            response.authorities.extend(answer.authorities);
+            response.security = response.security.and(answer.security);
+            response.scope = narrowest_scope(response.scope, answer.scope);
 
-            panic!();
+            return Ok(response);
         }
 
         let (mut resolved, mut unresolved): (Vec<_>, Vec<_>) = {
@@ -212,8 +595,8 @@ pub async fn resolve(
                     }
 
                     // If the ip is in the cache
-                    if let Some(cached_rrs) = cache.get_record_set(name) {
-                        if let Some(ip) = find_ip(name, &cached_rrs.iter().cloned().collect_vec()) {
+                    if let Some(cached_rrs) = cache.get_record_set(name, RecordType::A) {
+                        if let Some(ip) = find_ip(name, &cached_rrs) {
                             return Either::Left((name.clone(), ip));
                         }
                     }
@@ -222,18 +605,34 @@ pub async fn resolve(
                 })
         };
 
+        // Referrals further down the tree change the zone that answers are signed by
+        if let Some(cut) = message
+            .authorities
+            .iter()
+            .find(|rr| rr.type_ == RecordType::Ns)
+            .map(|rr| &rr.name)
+        {
+            if question.name.is_subdomain_of(cut) && cut.is_subdomain_of(&zone) {
+                zone = cut.clone();
+            }
+        }
+
         if let Some(host) = resolved.pop() {
             nameserver = host;
             continue;
         } else if let Some(name) = unresolved.pop() {
+            // Nameserver addresses don't depend on the client, so no subnet is sent
             let answer = resolve(
                 Question::new(name.clone(), RecordType::A),
                 Arc::clone(&cache),
+                Arc::clone(&cookies),
+                None,
+                anchor.clone(),
             )
             .await?;
 
-            let ip = find_ip(&name, &answer).ok_or(DnsError::ServerFailure(
-                "failed to resolve next nameserver".to_owned(),
+            let ip = find_ip(&name, &answer.records).ok_or(DnsError::ServerFailure(
+                FailureReason::Other(format!("failed to resolve nameserver {name}")),
             ))?;
 
             nameserver = (name, ip);
@@ -246,41 +645,147 @@ pub async fn resolve(
             .find(|rr| rr.type_ == RecordType::Soa);
 
         if let Some(soa_record) = soa {
-            response.push(soa_record.clone());
+            let records: Vec<_> = message
+                .authorities
+                .iter()
+                .filter(|rr| {
+                    (rr.type_ == RecordType::Soa && rr.name == soa_record.name)
+                        || signs(rr, &soa_record.name, RecordType::Soa)
+                })
+                .cloned()
+                .collect();
+            let mut proof = denial_records(&message.authorities);
+
+            let name_error = message.rcode() == Rcode::NxDomain;
+            if let Some(validator) = &validator {
+                response.security = validator
+                    .validate(&question, &zone, &records, &mut proof, name_error)
+                    .await;
+            }
+
+            // A negative answer has the SOA in the authority section (RFC 2308 3)
+            if name_error {
+                response.rcode = Rcode::NxDomain;
+            }
+            response.authorities.extend(records);
+            response.authorities.extend(proof);
             return Ok(response);
         }
 
-        panic!()
+        // Without an answer, a referral or an SOA the nameserver doesn't serve the zone,
+        // unless it says that the name doesn't exist
+        if message.rcode() == Rcode::NxDomain {
+            return Err(DnsError::NameError);
+        }
+
+        return Err(DnsError::ServerFailure(FailureReason::LameDelegation(
+            ns_name,
+        )));
     }
 }
 
-fn find_iter<'a, I>(
-    name: Name,
-    records: I,
-    t: Option<RecordType>,
-) -> impl Iterator<Item = &'a ResourceRecord>
-where
-    I: IntoIterator<Item = &'a ResourceRecord>,
-{
-    records.into_iter().filter(move |rr| {
-        if let Some(type_filter) = t {
-            rr.name == name && rr.type_ == type_filter
-        } else {
-            rr.name == name
+/// Wait for the response to a query, ignoring anything that could be spoofed:
+/// packets from other addresses, malformed packets, other ids and the wrong client cookie
+async fn receive_response(
+    sock: &UdpSocket,
+    nameserver: IpAddr,
+    id: u16,
+    cookies: &Mutex<ClientCookies>,
+) -> Result<Message, DnsError> {
+    let mut buf = [0; EDNS_UDP_PAYLOAD_SIZE as usize];
+
+    loop {
+        let (len, from) = sock.recv_from(&mut buf).await?;
+        if from.ip() != nameserver {
+            warn!(?from, "discarding response from unexpected address");
+            continue;
+        }
+
+        if let Some(message) = read_response(&buf[..len], nameserver, id, cookies) {
+            return Ok(message);
         }
-    })
+    }
+}
+
+/// Send a query over tcp, for a response that was truncated over udp (RFC 7766 5)
+async fn query_tcp(
+    query: &Message,
+    nameserver: IpAddr,
+    cookies: &Mutex<ClientCookies>,
+) -> Result<Message, DnsError> {
+    let mut stream = TcpStream::connect((nameserver, 53)).await?;
+
+    // Messages over tcp are prefixed with their length (RFC 1035 4.2.2)
+    let bytes = query.to_bytes();
+    let mut framed = (bytes.len() as u16).to_be_bytes().to_vec();
+    framed.extend_from_slice(&bytes);
+    stream.write_all(&framed).await?;
+
+    let len = stream.read_u16().await?;
+    let mut buf = vec![0; len as usize];
+    stream.read_exact(&mut buf).await?;
+
+    // Nothing else can arrive on the connection, so a response that doesn't check out is an error
+    let message = read_response(&buf, nameserver, query.header.id, cookies).ok_or_else(|| {
+        DnsError::ServerFailure(FailureReason::InvalidResponse(format!(
+            "invalid tcp response from {nameserver}"
+        )))
+    })?;
+    if message.header.flags.tc() {
+        return Err(DnsError::ServerFailure(FailureReason::InvalidResponse(
+            format!("truncated tcp response from {nameserver}"),
+        )));
+    }
+
+    Ok(message)
+}
+
+/// Parse a response, None if it's malformed or has the wrong id or client cookie
+fn read_response(
+    bytes: &[u8],
+    nameserver: IpAddr,
+    id: u16,
+    cookies: &Mutex<ClientCookies>,
+) -> Option<Message> {
+    let Ok(message) = Message::from_bytes(&mut Cursor::new(bytes)) else {
+        warn!("discarding malformed response");
+        return None;
+    };
+    if message.header.id != id {
+        warn!(
+            id = message.header.id,
+            "discarding response with unexpected id"
+        );
+        return None;
+    }
+
+    let cookie = message.edns.as_ref().and_then(Edns::cookie);
+    if !cookies.lock().unwrap().check_response(nameserver, cookie) {
+        warn!(?cookie, "discarding response with invalid cookie");
+        return None;
+    }
+
+    Some(message)
+}
+
+/// The more specific of two scopes, an answer built from both is only valid for it
+fn narrowest_scope(a: Option<ClientSubnet>, b: Option<ClientSubnet>) -> Option<ClientSubnet> {
+    match (a, b) {
+        (Some(a), Some(b)) if b.scope_prefix > a.scope_prefix => Some(b),
+        (Some(a), _) => Some(a),
+        (None, b) => b,
+    }
 }
 
 fn find_ip(name: &Name, rr_set: &[ResourceRecord]) -> Option<IpAddr> {
     for rr in rr_set {
-        if &rr.name != name {
+        if &rr.name != name || rr.class != DnsClass::In {
             continue;
         }
 
-        match rr.data {
-            RecordData::A(ip) => return Some(IpAddr::V4(ip)),
-            // RecordData::Aaaa(ip) => return Some(IpAddr::V6(ip)),
-            _ => {}
+        // TODO: Handle RecordData::Aaaa
+        if let RecordData::A(ip) = rr.data {
+            return Some(IpAddr::V4(ip));
         }
     }
 
@@ -1,45 +1,283 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
 
-use dnrs::{Name, RecordType, ResourceRecord};
+use dnrs::{ClientSubnet, DnsClass, Name, Question, Rcode, RecordData, RecordType, ResourceRecord};
 
-pub struct Cache(HashMap<Name, HashSet<ResourceRecord>>);
+use super::validator::Security;
+use super::Answer;
+
+/// How long a bogus answer is kept, so that it's checked again soon (RFC 4035 4.7)
+const BOGUS_TTL: u32 = 60;
+
+/// Answers to questions, kept until their shortest ttl runs out
+pub struct Cache(HashMap<Name, Vec<CacheEntry>>);
+
+struct CacheEntry {
+    type_: RecordType,
+    class: DnsClass,
+    rcode: Rcode,
+    records: Vec<ResourceRecord>,
+    authorities: Vec<ResourceRecord>,
+    security: Security,
+    /// The subnet the answer was scoped to (RFC 7871 7.3.1),
+    /// answers without one are given to every client
+    scope: Option<ClientSubnet>,
+    inserted: Instant,
+    expires: Instant,
+}
+
+impl CacheEntry {
+    fn matches(&self, question: &Question, client: Option<IpAddr>) -> bool {
+        if self.type_ != question.type_ || self.class != question.class {
+            return false;
+        }
+
+        match (self.scope, client) {
+            (None, _) => true,
+            (Some(scope), Some(client)) => scope.scope_contains(client),
+            (Some(_), None) => false,
+        }
+    }
+
+    fn same_scope(&self, scope: Option<&ClientSubnet>) -> bool {
+        match (self.scope.as_ref(), scope) {
+            (None, None) => true,
+            (Some(a), Some(b)) => {
+                a.scope_prefix == b.scope_prefix && a.contains(b.address, a.scope_prefix)
+            }
+            _ => false,
+        }
+    }
+}
 
 impl Cache {
     pub fn new() -> Self {
         Self(HashMap::new())
     }
 
-    pub fn insert_record(&mut self, name: Name, record: ResourceRecord) {
-        self.0
-            .entry(name)
-            .or_insert_with(HashSet::new)
-            .replace(record);
-    }
+    /// Store the answer to a question, replacing a previous answer for the same scope.
+    /// Answers that couldn't be validated aren't stored, and bogus ones only briefly
+    pub fn insert(&mut self, question: &Question, answer: &Answer) {
+        let Some(mut ttl) = answer
+            .records
+            .iter()
+            .chain(&answer.authorities)
+            .map(|rr| rr.ttl)
+            .min()
+        else {
+            return;
+        };
 
-    pub fn insert_records(&mut self, name: Name, records: Vec<ResourceRecord>) {
-        if !(self.0.contains_key(&name)) {
-            self.0.insert(name.clone(), HashSet::new());
+        // A negative answer is kept for the SOA's minimum at most (RFC 2308 5)
+        let minimum = answer.authorities.iter().find_map(|rr| match rr.data {
+            RecordData::Soa { minimum, .. } => Some(minimum),
+            _ => None,
+        });
+        if let Some(minimum) = minimum {
+            ttl = ttl.min(minimum);
         }
 
-        for record in records {
-            self.0.get_mut(&name).unwrap().replace(record);
+        match answer.security {
+            Security::Indeterminate(_) => return,
+            Security::Bogus(_) => ttl = ttl.min(BOGUS_TTL),
+            Security::Secure | Security::Insecure => {}
         }
-    }
 
-    pub fn get_record_set(&self, name: &Name) -> Option<&HashSet<ResourceRecord>> {
-        self.0.get(name)
+        // A scope of 0 means the answer is the same for every client
+        let scope = answer.scope.filter(|scope| scope.scope_prefix != 0);
+
+        let now = Instant::now();
+        let entries = self.0.entry(question.name.clone()).or_default();
+        entries.retain(|entry| {
+            entry.expires > now
+                && !(entry.type_ == question.type_
+                    && entry.class == question.class
+                    && entry.same_scope(scope.as_ref()))
+        });
+
+        entries.push(CacheEntry {
+            type_: question.type_,
+            class: question.class,
+            rcode: answer.rcode,
+            records: answer.records.clone(),
+            authorities: answer.authorities.clone(),
+            security: answer.security.clone(),
+            scope,
+            inserted: now,
+            expires: now + Duration::from_secs(ttl as u64),
+        });
     }
 
-    pub fn get_records_by_type(
-        &mut self,
-        name: &Name,
-        t: RecordType,
-    ) -> impl Iterator<Item = ResourceRecord> + '_ {
-        self.0
-            .entry(name.to_owned())
-            .or_insert_with(HashSet::new)
+    /// Get the answer to a question for a client in the subnet of `client`,
+    /// with the ttls reduced by the time the answer has been cached for
+    pub fn get(&self, question: &Question, client: Option<IpAddr>) -> Option<Answer> {
+        let now = Instant::now();
+
+        let entry = self
+            .0
+            .get(&question.name)?
             .iter()
-            .filter(move |rr| rr.type_ == t)
-            .cloned()
+            .filter(|entry| entry.expires > now && entry.matches(question, client))
+            // The most specific answer wins
+            .max_by_key(|entry| entry.scope.map(|scope| scope.scope_prefix))?;
+
+        let elapsed = (now - entry.inserted).as_secs() as u32;
+        let age = |records: &[ResourceRecord]| {
+            records
+                .iter()
+                .cloned()
+                .map(|mut rr| {
+                    rr.ttl = rr.ttl.saturating_sub(elapsed);
+                    rr
+                })
+                .collect()
+        };
+
+        Some(Answer {
+            rcode: entry.rcode,
+            records: age(&entry.records),
+            authorities: age(&entry.authorities),
+            security: entry.security.clone(),
+            scope: entry.scope,
+        })
+    }
+
+    /// Get the records of an unscoped answer for `name`, e.g. the address of a nameserver
+    pub fn get_record_set(&self, name: &Name, type_: RecordType) -> Option<Vec<ResourceRecord>> {
+        self.get(&Question::new(name.clone(), type_), None)
+            .map(|answer| answer.records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use dnrs::{
+        ClientSubnet, DnsClass, FailureReason, Name, Question, Rcode, RecordData, RecordType,
+        ResourceRecord,
+    };
+
+    use super::{Cache, BOGUS_TTL};
+    use crate::resolver::validator::Security;
+    use crate::resolver::Answer;
+
+    fn answer(ip: [u8; 4], scope: Option<ClientSubnet>) -> Answer {
+        Answer {
+            records: vec![ResourceRecord {
+                name: Name::new("cdn.example.com"),
+                type_: RecordType::A,
+                class: DnsClass::In,
+                ttl: 300,
+                data: RecordData::A(ip.into()),
+            }],
+            scope,
+            ..Answer::default()
+        }
+    }
+
+    fn scope(address: &str, source_prefix: u8, scope_prefix: u8) -> Option<ClientSubnet> {
+        let mut subnet = ClientSubnet::new(address.parse().unwrap(), source_prefix);
+        subnet.scope_prefix = scope_prefix;
+        Some(subnet)
+    }
+
+    fn get(cache: &Cache, question: &Question, client: Option<&str>) -> Option<RecordData> {
+        cache
+            .get(question, client.map(|c| c.parse().unwrap()))
+            .map(|answer| answer.records[0].data.clone())
+    }
+
+    #[test]
+    fn keeps_answers_to_their_scope() {
+        let question = Question::new(Name::new("cdn.example.com"), RecordType::A);
+        let mut cache = Cache::new();
+
+        cache.insert(&question, &answer([1, 1, 1, 1], scope("10.1.0.0", 24, 16)));
+        cache.insert(&question, &answer([2, 2, 2, 2], scope("10.2.0.0", 24, 16)));
+
+        let first = Some(RecordData::A([1, 1, 1, 1].into()));
+        let second = Some(RecordData::A([2, 2, 2, 2].into()));
+        assert_eq!(get(&cache, &question, Some("10.1.5.5")), first);
+        assert_eq!(get(&cache, &question, Some("10.2.5.5")), second);
+        assert_eq!(get(&cache, &question, Some("10.3.5.5")), None);
+        assert_eq!(get(&cache, &question, None), None);
+
+        // An answer for every client is used when there isn't a more specific one
+        cache.insert(&question, &answer([3, 3, 3, 3], scope("10.3.0.0", 24, 0)));
+        let global = Some(RecordData::A([3, 3, 3, 3].into()));
+        assert_eq!(get(&cache, &question, Some("10.3.5.5")), global);
+        assert_eq!(get(&cache, &question, Some("10.1.5.5")), first);
+        assert_eq!(get(&cache, &question, None), global);
+    }
+
+    #[test]
+    fn replaces_answers_for_the_same_scope() {
+        let question = Question::new(Name::new("cdn.example.com"), RecordType::A);
+        let mut cache = Cache::new();
+
+        cache.insert(&question, &answer([1, 1, 1, 1], None));
+        cache.insert(&question, &answer([2, 2, 2, 2], None));
+
+        assert_eq!(cache.0[&question.name].len(), 1);
+        assert_eq!(
+            cache.get_record_set(&question.name, RecordType::A).unwrap()[0].data,
+            RecordData::A([2, 2, 2, 2].into())
+        );
+        assert!(cache
+            .get_record_set(&question.name, RecordType::Aaaa)
+            .is_none());
+    }
+
+    #[test]
+    fn keeps_negative_answers_for_the_soa_minimum() {
+        let question = Question::new(Name::new("missing.example.com"), RecordType::A);
+        let mut cache = Cache::new();
+
+        let negative = Answer {
+            rcode: Rcode::NxDomain,
+            authorities: vec![ResourceRecord {
+                name: Name::new("example.com"),
+                type_: RecordType::Soa,
+                class: DnsClass::In,
+                ttl: 3600,
+                data: RecordData::Soa {
+                    mname: Name::new("ns1.example.com"),
+                    rname: Name::new("hostmaster.example.com"),
+                    serial: 1,
+                    refresh: 7200,
+                    retry: 900,
+                    expire: 86400,
+                    minimum: 300,
+                },
+            }],
+            ..Answer::default()
+        };
+        cache.insert(&question, &negative);
+
+        let entry = &cache.0[&question.name][0];
+        assert_eq!(entry.expires - entry.inserted, Duration::from_secs(300));
+        assert_eq!(cache.get(&question, None).unwrap().rcode, Rcode::NxDomain);
+    }
+
+    #[test]
+    fn keeps_failed_validations_briefly() {
+        let question = Question::new(Name::new("cdn.example.com"), RecordType::A);
+        let mut cache = Cache::new();
+
+        let mut indeterminate = answer([1, 1, 1, 1], None);
+        indeterminate.security = Security::Indeterminate(FailureReason::Network(String::new()));
+        cache.insert(&question, &indeterminate);
+        assert!(!cache.0.contains_key(&question.name));
+
+        let mut bogus = answer([1, 1, 1, 1], None);
+        bogus.security = Security::Bogus(FailureReason::Network(String::new()));
+        cache.insert(&question, &bogus);
+        let entry = &cache.0[&question.name][0];
+        assert_eq!(
+            entry.expires - entry.inserted,
+            Duration::from_secs(BOGUS_TTL as u64)
+        );
     }
 }
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::hash::Hasher;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use dnrs::{Cookie, CLIENT_COOKIE_LENGTH};
+use siphasher::sip::SipHasher24;
+
+use crate::util::unix_time;
+
+/// The server cookie format of RFC 9018
+const SERVER_COOKIE_VERSION: u8 = 1;
+/// Server cookies older than this aren't accepted (RFC 9018 4.3)
+const MAX_COOKIE_AGE: u32 = 60 * 60;
+/// How far in the future a server cookie can be, for servers with slightly different clocks
+const MAX_CLOCK_SKEW: u32 = 5 * 60;
+
+type Secret = [u8; 16];
+
+/// How the server treats cookies in requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CookiePolicy {
+    /// Cookies are ignored
+    Disabled,
+    /// Cookies are answered, but requests without a valid server cookie are resolved anyway
+    Enabled,
+    /// Only requests with a valid server cookie are resolved,
+    /// others get BADCOOKIE or REFUSED if they didn't send a cookie at all
+    Required,
+}
+
+/// Creates and checks the server cookies given to clients (RFC 9018 4)
+pub struct ServerCookies {
+    current: Secret,
+    /// Cookies made before the last rotation are still accepted until they expire
+    previous: Option<Secret>,
+    rotated: Instant,
+    rotation_interval: Duration,
+}
+
+impl ServerCookies {
+    pub fn new(rotation_interval: Duration) -> Self {
+        Self {
+            current: rand::random(),
+            previous: None,
+            rotated: Instant::now(),
+            rotation_interval,
+        }
+    }
+
+    /// Check the cookie of a request, returning whether its server cookie is valid
+    /// and the cookie to respond with
+    pub fn respond(&mut self, request: &Cookie, client: IpAddr) -> (bool, Cookie) {
+        if self.rotated.elapsed() >= self.rotation_interval {
+            self.rotate();
+        }
+
+        self.respond_at(request, client, unix_time())
+    }
+
+    fn respond_at(&self, request: &Cookie, client: IpAddr, now: u32) -> (bool, Cookie) {
+        let valid = request
+            .server
+            .as_ref()
+            .is_some_and(|server| self.is_valid(&request.client, server, client, now));
+
+        // Always give out a fresh cookie, so it doesn't expire while the client is using it
+        let server = server_cookie(&self.current, &request.client, client, now);
+        (valid, Cookie::new(request.client, Some(server)))
+    }
+
+    fn is_valid(
+        &self,
+        client_cookie: &[u8; CLIENT_COOKIE_LENGTH],
+        cookie: &[u8],
+        client: IpAddr,
+        now: u32,
+    ) -> bool {
+        if cookie.len() != 16 || cookie[0] != SERVER_COOKIE_VERSION {
+            return false;
+        }
+
+        // The timestamp is compared with serial number arithmetic (RFC 9018 4.3)
+        let timestamp = u32::from_be_bytes(cookie[4..8].try_into().unwrap());
+        let age = now.wrapping_sub(timestamp) as i32;
+        if age > MAX_COOKIE_AGE as i32 || age < -(MAX_CLOCK_SKEW as i32) {
+            return false;
+        }
+
+        [Some(self.current), self.previous]
+            .into_iter()
+            .flatten()
+            .any(|secret| cookie == server_cookie(&secret, client_cookie, client, timestamp))
+    }
+
+    fn rotate(&mut self) {
+        self.previous = Some(self.current);
+        self.current = rand::random();
+        self.rotated = Instant::now();
+    }
+}
+
+/// The cookies used with upstream nameservers (RFC 7873 5.1)
+pub struct ClientCookies {
+    secret: Secret,
+    /// The server cookie learned from each nameserver
+    servers: HashMap<IpAddr, Vec<u8>>,
+}
+
+impl ClientCookies {
+    pub fn new() -> Self {
+        Self {
+            secret: rand::random(),
+            servers: HashMap::new(),
+        }
+    }
+
+    /// The cookie to send to a nameserver
+    pub fn cookie(&self, server: IpAddr) -> Cookie {
+        Cookie::new(
+            self.client_cookie(server),
+            self.servers.get(&server).cloned(),
+        )
+    }
+
+    /// Check the cookie in a response from a nameserver and remember its server cookie,
+    /// returning false if the response should be discarded (RFC 7873 5.3)
+    pub fn check_response(&mut self, server: IpAddr, response: Option<&Cookie>) -> bool {
+        let Some(cookie) = response else {
+            // A server that has sent a cookie before is expected to keep sending them
+            return !self.servers.contains_key(&server);
+        };
+
+        if cookie.client != self.client_cookie(server) {
+            return false;
+        }
+
+        let Some(server_cookie) = &cookie.server else {
+            return false;
+        };
+
+        self.servers.insert(server, server_cookie.clone());
+        true
+    }
+
+    /// A different client cookie for every server, so servers can't track the resolver
+    fn client_cookie(&self, server: IpAddr) -> [u8; CLIENT_COOKIE_LENGTH] {
+        let mut hasher = SipHasher24::new_with_key(&self.secret);
+        write_ip(&mut hasher, server);
+        hasher.finish().to_le_bytes()
+    }
+}
+
+/// A server cookie: version, 3 reserved bytes, timestamp and hash (RFC 9018 4.2)
+fn server_cookie(
+    secret: &Secret,
+    client_cookie: &[u8; CLIENT_COOKIE_LENGTH],
+    client: IpAddr,
+    timestamp: u32,
+) -> Vec<u8> {
+    let mut cookie = vec![SERVER_COOKIE_VERSION, 0, 0, 0];
+    cookie.extend_from_slice(&timestamp.to_be_bytes());
+
+    let mut hasher = SipHasher24::new_with_key(secret);
+    hasher.write(client_cookie);
+    hasher.write(&cookie);
+    write_ip(&mut hasher, client);
+
+    cookie.extend_from_slice(&hasher.finish().to_le_bytes());
+    cookie
+}
+
+fn write_ip(hasher: &mut SipHasher24, ip: IpAddr) {
+    match ip {
+        IpAddr::V4(ip) => hasher.write(&ip.octets()),
+        IpAddr::V6(ip) => hasher.write(&ip.octets()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+    use std::time::Duration;
+
+    use dnrs::Cookie;
+
+    use super::{ClientCookies, ServerCookies};
+
+    const NOW: u32 = 1559731985;
+
+    fn decode_hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn server(secret: &str) -> ServerCookies {
+        let mut server = ServerCookies::new(Duration::from_secs(3600));
+        server.current = decode_hex(secret).try_into().unwrap();
+        server
+    }
+
+    #[test]
+    fn matches_rfc_9018_test_vector() {
+        // RFC 9018 A.2
+        let server = server("e5e973e5a6b2a43f48e7dc849e37bfcf");
+        let client: IpAddr = "198.51.100.100".parse().unwrap();
+        let request = Cookie::new(decode_hex("2464c4abcf10c957").try_into().unwrap(), None);
+
+        let (valid, response) = server.respond_at(&request, client, NOW);
+        assert!(!valid);
+        assert_eq!(
+            response.server,
+            Some(decode_hex("010000005cf79f111f8130c3eee29480"))
+        );
+
+        assert!(server.respond_at(&response, client, NOW + 10).0);
+    }
+
+    #[test]
+    fn rejects_invalid_server_cookies() {
+        let server = server("e5e973e5a6b2a43f48e7dc849e37bfcf");
+        let client: IpAddr = "198.51.100.100".parse().unwrap();
+        let (_, cookie) = server.respond_at(&Cookie::new([1; 8], None), client, NOW);
+
+        // Another client or a cookie that's too old or too far in the future
+        assert!(
+            !server
+                .respond_at(&cookie, "198.51.100.101".parse().unwrap(), NOW)
+                .0
+        );
+        assert!(!server.respond_at(&cookie, client, NOW + 3601).0);
+        assert!(!server.respond_at(&cookie, client, NOW - 301).0);
+
+        let mut tampered = cookie.clone();
+        tampered.server.as_mut().unwrap()[15] ^= 1;
+        assert!(!server.respond_at(&tampered, client, NOW).0);
+
+        let mut other_client_cookie = cookie.clone();
+        other_client_cookie.client = [2; 8];
+        assert!(!server.respond_at(&other_client_cookie, client, NOW).0);
+    }
+
+    #[test]
+    fn accepts_cookies_from_the_previous_secret() {
+        let mut server = server("e5e973e5a6b2a43f48e7dc849e37bfcf");
+        let client: IpAddr = "192.0.2.1".parse().unwrap();
+        let (_, cookie) = server.respond_at(&Cookie::new([1; 8], None), client, NOW);
+
+        server.rotate();
+        let (valid, fresh) = server.respond_at(&cookie, client, NOW);
+        assert!(valid);
+        assert_ne!(fresh, cookie);
+
+        server.rotate();
+        assert!(!server.respond_at(&cookie, client, NOW).0);
+    }
+
+    #[test]
+    fn checks_responses_from_nameservers() {
+        let mut cookies = ClientCookies::new();
+        let server: IpAddr = "192.0.2.53".parse().unwrap();
+        let other: IpAddr = "192.0.2.54".parse().unwrap();
+
+        let sent = cookies.cookie(server);
+        assert_eq!(sent.server, None);
+        assert_ne!(sent.client, cookies.cookie(other).client);
+
+        // Servers that don't support cookies are fine until they've sent one
+        assert!(cookies.check_response(server, None));
+
+        let mut response = Cookie::new(sent.client, None);
+        assert!(!cookies.check_response(server, Some(&response)));
+
+        response.server = Some(vec![3; 16]);
+        assert!(!cookies.check_response(other, Some(&response)));
+        assert!(cookies.check_response(server, Some(&response)));
+        assert_eq!(cookies.cookie(server).server, Some(vec![3; 16]));
+
+        assert!(!cookies.check_response(server, None));
+        assert!(cookies.check_response(other, None));
+    }
+}
@@ -0,0 +1,480 @@
+use std::sync::{Arc, Mutex};
+
+use dnrs::dnssec::{self, Denial};
+use dnrs::{
+    parse_zone, ExtendedErrorCode, FailureReason, Name, Question, RecordData, RecordType,
+    ResourceRecord, ZoneError,
+};
+use tracing::debug;
+
+use super::cache::Cache;
+use super::cookies::ClientCookies;
+use super::resolve;
+use crate::util::unix_time;
+
+/// The DS records of the root zone's key signing keys
+/// https://data.iana.org/root-anchors/root-anchors.xml
+const ROOT_ANCHORS: &str = "
+. 0 IN DS 20326 8 2 E06D44B80B8F1D39A95C0B0D7C65D08458E880409BBC683457104237C7F8EC8D
+. 0 IN DS 38696 8 2 683D2D0ACB8C9B712A1948B27F741219298D0A450D612C483AF444A4C0FB2B16
+";
+
+/// The DS records of the root zone that validation starts from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrustAnchor(Vec<RecordData>);
+
+impl TrustAnchor {
+    /// Read the DS records of the root from a zone file's text, other records are ignored
+    pub fn from_zone(input: &str) -> Result<Self, ZoneError> {
+        let ds = parse_zone(input, &Name::root())?
+            .into_iter()
+            .filter(|rr| rr.name.is_root() && rr.type_ == RecordType::Ds)
+            .map(|rr| rr.data)
+            .collect();
+
+        Ok(Self(ds))
+    }
+}
+
+impl Default for TrustAnchor {
+    fn default() -> Self {
+        Self::from_zone(ROOT_ANCHORS).expect("the root anchors are valid")
+    }
+}
+
+/// What validation found out about an answer (RFC 4035 4.3)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Security {
+    /// A chain of signatures from the trust anchor covers the whole answer
+    Secure,
+    /// Part of the answer is from an unsigned zone, or it wasn't validated
+    #[default]
+    Insecure,
+    /// The answer should be signed but its signatures don't check out
+    Bogus(FailureReason),
+    /// The keys or DS records needed to check the answer couldn't be looked up
+    Indeterminate(FailureReason),
+}
+
+impl Security {
+    /// The security of an answer made of two parts, e.g. a CNAME and its target
+    pub fn and(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Bogus(reason), _) | (_, Self::Bogus(reason)) => Self::Bogus(reason),
+            (Self::Indeterminate(reason), _) | (_, Self::Indeterminate(reason)) => {
+                Self::Indeterminate(reason)
+            }
+            (Self::Insecure, _) | (_, Self::Insecure) => Self::Insecure,
+            (Self::Secure, Self::Secure) => Self::Secure,
+        }
+    }
+}
+
+/// Checks the signatures of responses, looking up the keys and DS records it needs on the way
+pub struct Validator {
+    pub anchor: Arc<TrustAnchor>,
+    pub cache: Arc<Mutex<Cache>>,
+    pub cookies: Arc<Mutex<ClientCookies>>,
+}
+
+impl Validator {
+    /// Validate what a nameserver for `zone` answered `question` with (RFC 4035 5).
+    /// `records` are the RRset that answers it, or the SOA of a negative response,
+    /// with their RRSIGs. NSEC and NSEC3 records in `proof` that can't be verified are removed
+    pub async fn validate(
+        &self,
+        question: &Question,
+        zone: &Name,
+        records: &[ResourceRecord],
+        proof: &mut Vec<ResourceRecord>,
+        name_error: bool,
+    ) -> Security {
+        let rrsets = split_rrsets(records);
+        let Some(first) = rrsets.first() else {
+            return Security::Insecure;
+        };
+
+        let signer = signer(zone, first, records);
+        debug!(%signer, "validating response");
+
+        // The parent has the DS records of a zone, the zone itself can't vouch for them
+        if question.type_ == RecordType::Ds && question.name == signer && !signer.is_root() {
+            return Security::Bogus(bogus(
+                ExtendedErrorCode::DnssecBogus,
+                format!("the DS records of {signer} are signed by the zone itself"),
+            ));
+        }
+
+        let keys = if question.type_ == RecordType::Dnskey && question.name == signer {
+            self.trusted_keys(&signer, records).await
+        } else {
+            self.zone_keys(&signer).await
+        };
+        let keys = match keys {
+            Ok(Some(keys)) => keys,
+            Ok(None) => return Security::Insecure,
+            Err(security) => return security,
+        };
+
+        let now = unix_time();
+        let mut wildcard = None;
+        for rrset in &rrsets {
+            match verify(rrset, records, &keys, now) {
+                Ok(expanded) => wildcard = wildcard.or(expanded),
+                Err(reason) => return Security::Bogus(reason),
+            }
+        }
+
+        let verified: Vec<_> = split_rrsets(proof)
+            .into_iter()
+            .filter(|rrset| verify(rrset, proof, &keys, now).is_ok())
+            .flatten()
+            .collect();
+        proof.retain(|rr| {
+            let signs_verified = matches!(&rr.data, RecordData::Rrsig { type_covered, .. }
+                if verified.iter().any(|v| v.name == rr.name && v.type_ == *type_covered));
+            signs_verified || verified.contains(rr)
+        });
+
+        let answered = first[0].name == question.name
+            && (first[0].type_ == question.type_ || first[0].type_ == RecordType::Cname);
+        let denial = if let Some(wildcard) = wildcard {
+            dnssec::prove_wildcard_expansion(&first[0].name, &wildcard, proof)
+        } else if name_error {
+            dnssec::prove_nonexistence(&question.name, proof)
+        } else if !answered {
+            dnssec::prove_no_data(&question.name, question.type_, proof)
+        } else {
+            Ok(Denial::Proven)
+        };
+
+        match denial {
+            Ok(Denial::Proven) => Security::Secure,
+            Ok(Denial::Insecure) => Security::Insecure,
+            Err(reason) => Security::Bogus(reason),
+        }
+    }
+
+    /// The keys of a zone, None if it's unsigned. Failures are Bogus or Indeterminate
+    async fn zone_keys(&self, zone: &Name) -> Result<Option<Vec<ResourceRecord>>, Security> {
+        let answer = self.resolve(zone, RecordType::Dnskey).await?;
+        match answer.security {
+            Security::Secure => {
+                let keys: Vec<_> = answer
+                    .records
+                    .into_iter()
+                    .filter(|rr| rr.type_ == RecordType::Dnskey && rr.name == *zone)
+                    .collect();
+
+                if keys.is_empty() {
+                    return Err(Security::Bogus(bogus(
+                        ExtendedErrorCode::DnskeyMissing,
+                        format!("{zone} has no keys"),
+                    )));
+                }
+                Ok(Some(keys))
+            }
+            Security::Insecure => Ok(None),
+            security => Err(security),
+        }
+    }
+
+    /// The keys of a zone from its DNSKEY RRset, which are trusted when one of them
+    /// matches a DS record of the zone and signs the RRset (RFC 4035 5.2)
+    async fn trusted_keys(
+        &self,
+        zone: &Name,
+        records: &[ResourceRecord],
+    ) -> Result<Option<Vec<ResourceRecord>>, Security> {
+        let Some(ds) = self.zone_ds(zone).await? else {
+            return Ok(None);
+        };
+
+        // A zone whose DS records can't be checked is treated as unsigned
+        let ds: Vec<_> = ds
+            .into_iter()
+            .filter(|ds| {
+                matches!(ds, RecordData::Ds { algorithm, digest_type, .. }
+                    if dnssec::is_supported_algorithm(*algorithm)
+                        && dnssec::is_supported_digest(*digest_type))
+            })
+            .collect();
+        if ds.is_empty() {
+            debug!(%zone, "no supported DS records, the zone is insecure");
+            return Ok(None);
+        }
+
+        let keys: Vec<_> = records
+            .iter()
+            .filter(|rr| rr.type_ == RecordType::Dnskey && rr.name == *zone)
+            .cloned()
+            .collect();
+        let entry_keys: Vec<_> = keys
+            .iter()
+            .filter(|key| ds.iter().any(|ds| dnssec::ds_matches(ds, key)))
+            .cloned()
+            .collect();
+        if entry_keys.is_empty() {
+            return Err(Security::Bogus(bogus(
+                ExtendedErrorCode::DnskeyMissing,
+                format!("no key of {zone} matches its DS records"),
+            )));
+        }
+
+        verify(&keys, records, &entry_keys, unix_time()).map_err(Security::Bogus)?;
+        Ok(Some(keys))
+    }
+
+    /// The DS records of a zone, None if it's an unsigned delegation
+    async fn zone_ds(&self, zone: &Name) -> Result<Option<Vec<RecordData>>, Security> {
+        if zone.is_root() {
+            return Ok(Some(self.anchor.0.clone()));
+        }
+
+        let answer = self.resolve(zone, RecordType::Ds).await?;
+        match answer.security {
+            Security::Secure => {
+                let ds: Vec<_> = answer
+                    .records
+                    .iter()
+                    .filter(|rr| rr.type_ == RecordType::Ds && rr.name == *zone)
+                    .map(|rr| rr.data.clone())
+                    .collect();
+                if !ds.is_empty() {
+                    return Ok(Some(ds));
+                }
+
+                // Without DS records the parent has to show that the zone is an unsigned delegation
+                dnssec::prove_unsigned_delegation(zone, &answer.authorities)
+                    .map_err(Security::Bogus)?;
+                Ok(None)
+            }
+            Security::Insecure => Ok(None),
+            security => Err(security),
+        }
+    }
+
+    /// Look up records that validation needs, not getting them leaves it indeterminate
+    async fn resolve(&self, name: &Name, type_: RecordType) -> Result<super::Answer, Security> {
+        resolve(
+            Question::new(name.clone(), type_),
+            Arc::clone(&self.cache),
+            Arc::clone(&self.cookies),
+            None,
+            Some(Arc::clone(&self.anchor)),
+        )
+        .await
+        .map_err(|e| {
+            Security::Indeterminate(bogus(
+                ExtendedErrorCode::DnssecIndeterminate,
+                format!("couldn't get the {type_} records of {name}: {e:?}"),
+            ))
+        })
+    }
+}
+
+/// Whether the record is an RRSIG over the RRset of `type_` at `name`
+pub fn signs(rr: &ResourceRecord, name: &Name, type_: RecordType) -> bool {
+    matches!(&rr.data, RecordData::Rrsig { type_covered, .. } if *type_covered == type_)
+        && rr.name == *name
+}
+
+/// The NSEC and NSEC3 records in a section, with their RRSIGs
+pub fn denial_records(records: &[ResourceRecord]) -> Vec<ResourceRecord> {
+    records
+        .iter()
+        .filter(|rr| match &rr.data {
+            RecordData::Nsec { .. } | RecordData::Nsec3 { .. } => true,
+            RecordData::Rrsig { type_covered, .. } => {
+                matches!(type_covered, RecordType::Nsec | RecordType::Nsec3)
+            }
+            _ => false,
+        })
+        .cloned()
+        .collect()
+}
+
+fn bogus(code: ExtendedErrorCode, text: String) -> FailureReason {
+    FailureReason::Bogus(code, text)
+}
+
+/// The RRsets in some records, leaving out the RRSIGs
+fn split_rrsets(records: &[ResourceRecord]) -> Vec<Vec<ResourceRecord>> {
+    let mut rrsets: Vec<Vec<ResourceRecord>> = Vec::new();
+    for rr in records.iter().filter(|rr| rr.type_ != RecordType::Rrsig) {
+        match rrsets.iter_mut().find(|rrset| {
+            rrset[0].name == rr.name && rrset[0].type_ == rr.type_ && rrset[0].class == rr.class
+        }) {
+            Some(rrset) => rrset.push(rr.clone()),
+            None => rrsets.push(vec![rr.clone()]),
+        }
+    }
+
+    rrsets
+}
+
+/// The zone that signed an RRset. It's below `zone` when the nameservers of `zone` serve
+/// the zone below it too, which the RRSIGs or the owner of an SOA show
+fn signer(zone: &Name, rrset: &[ResourceRecord], records: &[ResourceRecord]) -> Name {
+    let owner = &rrset[0].name;
+    let within = |name: &Name| name.is_subdomain_of(zone) && owner.is_subdomain_of(name);
+
+    let signed_by = records
+        .iter()
+        .filter(|rr| signs(rr, owner, rrset[0].type_))
+        .find_map(|rr| match &rr.data {
+            RecordData::Rrsig { signer, .. } if within(signer) => Some(signer.clone()),
+            _ => None,
+        });
+
+    signed_by.unwrap_or_else(|| {
+        if rrset[0].type_ == RecordType::Soa && within(owner) {
+            owner.clone()
+        } else {
+            zone.clone()
+        }
+    })
+}
+
+/// Check that one of `keys` signed the RRset with an RRSIG in `records`,
+/// returning the wildcard the RRset was expanded from, if any
+fn verify(
+    rrset: &[ResourceRecord],
+    records: &[ResourceRecord],
+    keys: &[ResourceRecord],
+    now: u32,
+) -> Result<Option<Name>, FailureReason> {
+    let (owner, type_) = (&rrset[0].name, rrset[0].type_);
+    let mut failure = bogus(
+        ExtendedErrorCode::RrsigsMissing,
+        format!("no RRSIG over {owner} {type_}"),
+    );
+
+    for rrsig in records.iter().filter(|rr| signs(rr, owner, type_)) {
+        let RecordData::Rrsig {
+            key_tag, labels, ..
+        } = &rrsig.data
+        else {
+            continue;
+        };
+
+        let mut signing_keys = keys
+            .iter()
+            .filter(|key| key.data.key_tag() == Some(*key_tag))
+            .peekable();
+        if signing_keys.peek().is_none() {
+            failure = bogus(
+                ExtendedErrorCode::DnskeyMissing,
+                format!("no key with tag {key_tag} for {owner} {type_}"),
+            );
+        }
+
+        for key in signing_keys {
+            match dnssec::verify_rrset(rrset, rrsig, key, now) {
+                Ok(()) => return Ok(dnssec::expanded_wildcard(owner, *labels)),
+                Err(reason) => failure = reason,
+            }
+        }
+    }
+
+    Err(failure)
+}
+
+#[cfg(test)]
+mod tests {
+    use dnrs::{parse_zone, ExtendedErrorCode, FailureReason, Name, RecordData, ResourceRecord};
+
+    use super::{signer, verify, Security, TrustAnchor};
+
+    // The example of RFC 8080 6.1
+    const SIGNED_ZONE: &str = r#"
+example.com. 3600 IN DNSKEY 257 3 15 l02Woi0iS8Aa25FQkUd9RMzZHJpBoRQwAQEX1SxZJA4=
+example.com. 3600 IN MX 10 mail.example.com.
+example.com. 3600 IN RRSIG MX 15 2 3600 1440021600 1438207200 3613 example.com. (
+    oL9krJun7xfBOIWcGHi7mag5/hdZrKWw15jPGrHpjQeRAvTdszaPD+QLs3fx8A4M3e23mRZ9VrbpMngwcrqNAg== )
+"#;
+
+    fn bogus(code: ExtendedErrorCode) -> Security {
+        Security::Bogus(FailureReason::Bogus(code, String::new()))
+    }
+
+    fn failure_code(result: Result<Option<Name>, FailureReason>) -> Option<ExtendedErrorCode> {
+        match result {
+            Err(FailureReason::Bogus(code, _)) => Some(code),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn combines_security() {
+        let bogus = bogus(ExtendedErrorCode::DnssecBogus);
+
+        assert_eq!(Security::Secure.and(Security::Secure), Security::Secure);
+        assert_eq!(Security::Secure.and(Security::Insecure), Security::Insecure);
+        assert_eq!(Security::Insecure.and(bogus.clone()), bogus);
+        assert_eq!(bogus.clone().and(Security::Secure), bogus);
+
+        let indeterminate = Security::Indeterminate(FailureReason::Network(String::new()));
+        assert_eq!(Security::Insecure.and(indeterminate.clone()), indeterminate);
+        assert_eq!(indeterminate.clone().and(bogus.clone()), bogus);
+    }
+
+    #[test]
+    fn reads_trust_anchors() {
+        let anchor = TrustAnchor::default();
+        let key_tags: Vec<_> = anchor
+            .0
+            .iter()
+            .map(|ds| match ds {
+                RecordData::Ds { key_tag, .. } => *key_tag,
+                _ => panic!("not a DS record"),
+            })
+            .collect();
+        assert_eq!(key_tags, [20326, 38696]);
+
+        // Only the root's DS records are anchors
+        let anchor = TrustAnchor::from_zone(
+            r#"
+. 0 IN DS 20326 8 2 E06D44B80B8F1D39A95C0B0D7C65D08458E880409BBC683457104237C7F8EC8D
+com. 0 IN DS 19718 13 2 8ACBB0CD28F41250A80A491389424D341522D946B0DA0C0291F2D3D771D7805A
+. 0 IN NS a.root-servers.net.
+"#,
+        )
+        .unwrap();
+        assert_eq!(anchor.0.len(), 1);
+    }
+
+    #[test]
+    fn checks_rrsets_against_zone_keys() {
+        let records = parse_zone(SIGNED_ZONE, &Name::root()).unwrap();
+        let (keys, answer) = (&records[..1], &records[1..]);
+        let now = 1439000000;
+
+        // A nameserver for com that also serves example.com answers for example.com
+        assert_eq!(
+            signer(&Name::new("com"), &answer[..1], answer),
+            Name::new("example.com")
+        );
+        // But it can't claim a zone it wasn't delegated
+        assert_eq!(
+            signer(&Name::new("org"), &answer[..1], answer),
+            Name::new("org")
+        );
+
+        assert_eq!(verify(&answer[..1], answer, keys, now), Ok(None));
+        assert_eq!(
+            failure_code(verify(&answer[..1], &answer[..1], keys, now)),
+            Some(ExtendedErrorCode::RrsigsMissing)
+        );
+
+        let other_key: Vec<ResourceRecord> = parse_zone(
+            "example.com. 3600 IN DNSKEY 256 3 15 l02Woi0iS8Aa25FQkUd9RMzZHJpBoRQwAQEX1SxZJA4=",
+            &Name::root(),
+        )
+        .unwrap();
+        assert_eq!(
+            failure_code(verify(&answer[..1], answer, &other_key, now)),
+            Some(ExtendedErrorCode::DnskeyMissing)
+        );
+    }
+}
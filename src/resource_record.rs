@@ -1,53 +1,85 @@
-use bytes::{BufMut, Bytes, BytesMut};
+use bytes::{Bytes, BytesMut};
+use std::fmt::Display;
 use std::io::Cursor;
 
-use bytes::Buf;
 use derivative::Derivative;
 use tracing::instrument;
 
 use super::{Name, Networkable};
-use crate::{DnsError, RecordType};
+use crate::encode::{Encoder, Sink};
+use crate::read::CheckedBuf;
+use crate::{DnsClass, DnsError, RecordType};
+
+mod character_string;
+pub use character_string::CharacterString;
 
 mod record_data;
+pub(crate) use record_data::is_caa_tag;
 pub use record_data::RecordData;
 
+mod type_bitmap;
+pub use type_bitmap::TypeBitmap;
+
+mod svcb;
+pub use svcb::{ServiceBinding, SvcParam, SvcParamKey};
+
 #[derive(Derivative)]
 #[derivative(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct ResourceRecord {
     pub name: Name,
     pub type_: RecordType,
-    pub class: u16,
+    pub class: DnsClass,
     #[derivative(Hash = "ignore", PartialEq = "ignore")]
     pub ttl: u32,
     pub data: RecordData,
 }
 
+impl ResourceRecord {
+    pub(crate) fn encode<'m>(&'m self, encoder: &mut Encoder<'m, impl Sink>) {
+        encoder.put_name(&self.name);
+        encoder.put_u16(self.type_.to_int());
+        encoder.put_u16(self.class.to_int());
+        encoder.put_u32(self.ttl);
+        encoder.put_record_data(&self.data);
+    }
+}
+
+impl Display for ResourceRecord {
+    /// The record as a line of a zone file, e.g. `www.example.com. 300 IN A 1.2.3.4`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:#} {} {} {} {}",
+            self.name, self.ttl, self.class, self.type_, self.data
+        )
+    }
+}
+
 impl Networkable for ResourceRecord {
     #[instrument(level = "trace", skip_all)]
     fn to_bytes(&self) -> Bytes {
         let mut ret = BytesMut::new();
-        ret.extend_from_slice(&self.name.to_bytes());
-        ret.put_u16(self.type_.to_int());
-        ret.extend_from_slice(&self.class.to_be_bytes());
-        ret.extend_from_slice(&self.ttl.to_be_bytes());
-        let data = self.data.to_bytes();
-        let rd_length = data.len() as u16;
-        ret.extend_from_slice(&rd_length.to_be_bytes());
-        ret.extend_from_slice(&data);
-
+        self.encode(&mut Encoder::uncompressed(&mut ret));
         ret.into()
     }
 
     #[instrument(level = "trace", skip_all)]
     fn from_bytes(bytes: &mut Cursor<&[u8]>) -> Result<Self, DnsError> {
-        let name = Name::from_bytes(bytes).unwrap();
-        let type_ = bytes.get_u16();
-        let type_ = RecordType::from_int(type_).ok_or(DnsError::FormatError)?;
-        let class = bytes.get_u16();
-        let ttl = bytes.get_u32();
-        let data_length = bytes.get_u16();
-
-        let data = RecordData::from_bytes(type_, data_length, bytes).unwrap();
+        let name = Name::from_bytes(bytes)?;
+        let type_ = bytes.read_u16()?;
+        let type_ = RecordType::from_int(type_);
+        let class = DnsClass::from_int(bytes.read_u16()?);
+        let ttl = bytes.read_u32()?;
+        let data_length = bytes.read_u16()?;
+        bytes.ensure_remaining(data_length as usize)?;
+
+        let data_end = bytes.position() + data_length as u64;
+        let data = RecordData::from_bytes(type_, data_length, bytes)?;
+
+        // The data has to use exactly the length that the record declared
+        if bytes.position() != data_end {
+            return Err(DnsError::FormatError);
+        }
 
         Ok(Self {
             name,
@@ -0,0 +1,78 @@
+use std::fmt::Display;
+use std::io::Cursor;
+
+use crate::encode::Sink;
+use crate::read::CheckedBuf;
+use crate::DnsError;
+
+/// Maximum length of a character-string, the length has to fit in one byte (RFC 1035 3.3)
+const MAX_LENGTH: usize = 255;
+
+/// A length-prefixed string of up to 255 arbitrary bytes, as used by TXT and other records
+#[derive(Debug, Clone, Default, Hash, PartialEq, Eq)]
+pub struct CharacterString(Vec<u8>);
+
+impl CharacterString {
+    pub fn new(data: impl Into<Vec<u8>>) -> Result<Self, DnsError> {
+        let data = data.into();
+        if data.len() > MAX_LENGTH {
+            return Err(DnsError::FormatError);
+        }
+
+        Ok(Self(data))
+    }
+
+    /// Split `data` into as many character-strings as needed to hold it,
+    /// e.g. for TXT records that are longer than 255 bytes
+    pub fn split(data: &[u8]) -> Vec<Self> {
+        if data.is_empty() {
+            return vec![Self::default()];
+        }
+
+        data.chunks(MAX_LENGTH)
+            .map(|chunk| Self(chunk.to_vec()))
+            .collect()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub(crate) fn from_bytes(bytes: &mut Cursor<&[u8]>) -> Result<Self, DnsError> {
+        let len = bytes.read_u8()?;
+        Ok(Self(bytes.read_bytes(len as usize)?.to_vec()))
+    }
+
+    pub(crate) fn write(&self, buf: &mut impl Sink) {
+        buf.put_u8(self.0.len() as u8);
+        buf.put_slice(&self.0);
+    }
+}
+
+impl Display for CharacterString {
+    /// The quoted text form, with `"` and `\` escaped and other special bytes as `\DDD`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_quoted(f, &self.0)
+    }
+}
+
+/// Write bytes as a quoted string, like a character-string but of any length
+pub(crate) fn write_quoted(f: &mut std::fmt::Formatter<'_>, bytes: &[u8]) -> std::fmt::Result {
+    f.write_str("\"")?;
+    for byte in bytes.iter() {
+        match byte {
+            b'"' | b'\\' => write!(f, "\\{}", *byte as char)?,
+            0x20..=0x7E => write!(f, "{}", *byte as char)?,
+            _ => write!(f, "\\{:03}", byte)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+impl TryFrom<&str> for CharacterString {
+    type Error = DnsError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
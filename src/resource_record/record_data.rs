@@ -1,10 +1,16 @@
+use std::fmt::Display;
 use std::io::Cursor;
 use std::net::{Ipv4Addr, Ipv6Addr};
 
 use bytes::Buf;
-use tracing::warn;
+use tracing::debug;
 
-use crate::{DnsError, Name, Networkable, RecordType};
+use super::character_string::write_quoted;
+use super::{ServiceBinding, TypeBitmap};
+use crate::encode::{Encoder, Sink};
+use crate::read::CheckedBuf;
+use crate::text::{decode_hex, encode_base32hex, encode_base64, encode_hex, format_timestamp};
+use crate::{CharacterString, DnsError, Name, Networkable, RecordType};
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub enum RecordData {
@@ -21,12 +27,90 @@ pub enum RecordData {
         minimum: u32,
     },
     Mx {
-        priority: u16,
-        exchange: String,
+        preference: u16,
+        exchange: Name,
     },
-    Txt(String),
+    Ptr(Name),
+    Txt(Vec<CharacterString>),
     Aaaa(Ipv6Addr),
-    Other,
+    /// The location of a service (RFC 2782)
+    Srv {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: Name,
+    },
+    /// A rule that rewrites a string into a domain name or URI (RFC 3403)
+    Naptr {
+        order: u16,
+        preference: u16,
+        flags: CharacterString,
+        services: CharacterString,
+        regexp: CharacterString,
+        replacement: Name,
+    },
+    /// A certification authority that may issue certificates for the domain (RFC 8659)
+    Caa {
+        flags: u8,
+        /// The property, e.g. `issue`, which is ASCII letters and digits
+        tag: String,
+        value: Vec<u8>,
+    },
+    /// The digest of a DNSKEY in the child zone, which delegates trust to it (RFC 4034 5)
+    Ds {
+        key_tag: u16,
+        algorithm: u8,
+        digest_type: u8,
+        digest: Vec<u8>,
+    },
+    /// A signature over the RRset of `type_covered` at the owner name (RFC 4034 3)
+    Rrsig {
+        type_covered: RecordType,
+        algorithm: u8,
+        /// The number of labels in the owner name, not counting a leading `*`
+        labels: u8,
+        original_ttl: u32,
+        /// Seconds since the epoch, modulo 2^32
+        expiration: u32,
+        inception: u32,
+        key_tag: u16,
+        signer: Name,
+        signature: Vec<u8>,
+    },
+    /// The next owner name in the zone and the types at this one (RFC 4034 4)
+    Nsec {
+        next: Name,
+        types: TypeBitmap,
+    },
+    /// A public key that signs the zone's RRsets (RFC 4034 2)
+    Dnskey {
+        flags: u16,
+        protocol: u8,
+        algorithm: u8,
+        public_key: Vec<u8>,
+    },
+    /// Like NSEC, but with hashed owner names (RFC 5155 3)
+    Nsec3 {
+        hash_algorithm: u8,
+        flags: u8,
+        iterations: u16,
+        salt: Vec<u8>,
+        next_hashed: Vec<u8>,
+        types: TypeBitmap,
+    },
+    /// The parameters that the zone's NSEC3 names are hashed with (RFC 5155 4)
+    Nsec3param {
+        hash_algorithm: u8,
+        flags: u8,
+        iterations: u16,
+        salt: Vec<u8>,
+    },
+    /// Where and how to reach a service (RFC 9460)
+    Svcb(ServiceBinding),
+    /// A service binding for HTTPS, which has the same data as SVCB (RFC 9460 9)
+    Https(ServiceBinding),
+    /// The raw data of a type that dnrs doesn't parse (RFC 3597)
+    Unknown(Vec<u8>),
 }
 
 impl RecordData {
@@ -36,35 +120,521 @@ impl RecordData {
         bytes: &mut Cursor<&[u8]>,
     ) -> Result<Self, DnsError> {
         match type_ {
-            RecordType::A => Ok(Self::A(bytes.get_u32().to_be_bytes().into())),
+            RecordType::A => {
+                expect_length(rd_length, 4)?;
+                Ok(Self::A(bytes.read_u32()?.into()))
+            }
             RecordType::Ns => Ok(Self::Ns(Name::from_bytes(bytes)?)),
             RecordType::Cname => Ok(Self::Cname(Name::from_bytes(bytes)?)),
             RecordType::Soa => Ok(Self::Soa {
                 mname: Name::from_bytes(bytes)?,
                 rname: Name::from_bytes(bytes)?,
-                serial: bytes.get_u32(),
-                refresh: bytes.get_u32(),
-                retry: bytes.get_u32(),
-                expire: bytes.get_u32(),
-                minimum: bytes.get_u32(),
+                serial: bytes.read_u32()?,
+                refresh: bytes.read_u32()?,
+                retry: bytes.read_u32()?,
+                expire: bytes.read_u32()?,
+                minimum: bytes.read_u32()?,
+            }),
+            RecordType::Mx => Ok(Self::Mx {
+                preference: bytes.read_u16()?,
+                exchange: Name::from_bytes(bytes)?,
             }),
-            RecordType::Mx => Err(DnsError::NotImplemented),
-            RecordType::Txt => Err(DnsError::NotImplemented),
-            RecordType::Aaaa => Ok(Self::Aaaa(bytes.get_u128().to_be_bytes().into())),
+            RecordType::Ptr => Ok(Self::Ptr(Name::from_bytes(bytes)?)),
+            RecordType::Txt => {
+                let end = bytes.position() + rd_length as u64;
+
+                let mut strings = Vec::new();
+                while bytes.position() < end {
+                    strings.push(CharacterString::from_bytes(bytes)?);
+                }
+
+                Ok(Self::Txt(strings))
+            }
+            RecordType::Aaaa => {
+                expect_length(rd_length, 16)?;
+                Ok(Self::Aaaa(bytes.read_u128()?.into()))
+            }
+            RecordType::Srv => Ok(Self::Srv {
+                priority: bytes.read_u16()?,
+                weight: bytes.read_u16()?,
+                port: bytes.read_u16()?,
+                target: Name::from_bytes(bytes)?,
+            }),
+            RecordType::Naptr => Ok(Self::Naptr {
+                order: bytes.read_u16()?,
+                preference: bytes.read_u16()?,
+                flags: CharacterString::from_bytes(bytes)?,
+                services: CharacterString::from_bytes(bytes)?,
+                regexp: CharacterString::from_bytes(bytes)?,
+                replacement: Name::from_bytes(bytes)?,
+            }),
+            RecordType::Caa => {
+                let flags = bytes.read_u8()?;
+                let tag_length = bytes.read_u8()? as u16;
+                let value_length = rd_length
+                    .checked_sub(2 + tag_length)
+                    .ok_or(DnsError::FormatError)?;
+
+                let tag = bytes.read_bytes(tag_length as usize)?;
+                if !is_caa_tag(&tag) {
+                    return Err(DnsError::FormatError);
+                }
+
+                Ok(Self::Caa {
+                    flags,
+                    tag: String::from_utf8_lossy(&tag).into_owned(),
+                    value: bytes.read_bytes(value_length as usize)?.to_vec(),
+                })
+            }
+            RecordType::Ds => {
+                let start = bytes.position();
+                Ok(Self::Ds {
+                    key_tag: bytes.read_u16()?,
+                    algorithm: bytes.read_u8()?,
+                    digest_type: bytes.read_u8()?,
+                    digest: read_rest(start, rd_length, bytes)?,
+                })
+            }
+            RecordType::Rrsig => {
+                let start = bytes.position();
+                Ok(Self::Rrsig {
+                    type_covered: RecordType::from_int(bytes.read_u16()?),
+                    algorithm: bytes.read_u8()?,
+                    labels: bytes.read_u8()?,
+                    original_ttl: bytes.read_u32()?,
+                    expiration: bytes.read_u32()?,
+                    inception: bytes.read_u32()?,
+                    key_tag: bytes.read_u16()?,
+                    signer: Name::from_bytes(bytes)?,
+                    signature: read_rest(start, rd_length, bytes)?,
+                })
+            }
+            RecordType::Nsec => {
+                let start = bytes.position();
+                let next = Name::from_bytes(bytes)?;
+                let len = remaining(start, rd_length, bytes)?;
+
+                Ok(Self::Nsec {
+                    next,
+                    types: TypeBitmap::from_bytes(len, bytes)?,
+                })
+            }
+            RecordType::Dnskey => {
+                let start = bytes.position();
+                Ok(Self::Dnskey {
+                    flags: bytes.read_u16()?,
+                    protocol: bytes.read_u8()?,
+                    algorithm: bytes.read_u8()?,
+                    public_key: read_rest(start, rd_length, bytes)?,
+                })
+            }
+            RecordType::Nsec3 => {
+                let start = bytes.position();
+                let hash_algorithm = bytes.read_u8()?;
+                let flags = bytes.read_u8()?;
+                let iterations = bytes.read_u16()?;
+                let salt_length = bytes.read_u8()?;
+                let salt = bytes.read_bytes(salt_length as usize)?.to_vec();
+
+                let hash_length = bytes.read_u8()?;
+                if hash_length == 0 {
+                    return Err(DnsError::FormatError);
+                }
+                let next_hashed = bytes.read_bytes(hash_length as usize)?.to_vec();
+
+                let len = remaining(start, rd_length, bytes)?;
+                Ok(Self::Nsec3 {
+                    hash_algorithm,
+                    flags,
+                    iterations,
+                    salt,
+                    next_hashed,
+                    types: TypeBitmap::from_bytes(len, bytes)?,
+                })
+            }
+            RecordType::Nsec3param => {
+                let hash_algorithm = bytes.read_u8()?;
+                let flags = bytes.read_u8()?;
+                let iterations = bytes.read_u16()?;
+                let salt_length = bytes.read_u8()?;
+                expect_length(rd_length, 5 + salt_length as u16)?;
+
+                Ok(Self::Nsec3param {
+                    hash_algorithm,
+                    flags,
+                    iterations,
+                    salt: bytes.read_bytes(salt_length as usize)?.to_vec(),
+                })
+            }
+            RecordType::Svcb => Ok(Self::Svcb(ServiceBinding::from_bytes(rd_length, bytes)?)),
+            RecordType::Https => Ok(Self::Https(ServiceBinding::from_bytes(rd_length, bytes)?)),
 
             record_type => {
-                warn!(?record_type, "received unimplemented record data");
-                bytes.advance(rd_length as usize);
-                Ok(Self::Other)
+                // Older types may have compressed names, which wouldn't point at the
+                // same place in another message, so they're kept decompressed (RFC 3597 4)
+                if let Some((fixed, names)) = embedded_names(record_type) {
+                    let mut data = bytes.read_bytes(fixed)?.to_vec();
+                    for _ in 0..names {
+                        data.extend_from_slice(&Name::from_bytes(bytes)?.to_bytes());
+                    }
+                    return Ok(Self::Unknown(data));
+                }
+
+                debug!(?record_type, "keeping unparsed record data");
+                Ok(Self::Unknown(
+                    bytes.read_bytes(rd_length as usize)?.to_vec(),
+                ))
             }
         }
     }
 
+    /// The type of record the data belongs to, unknown data could be any type
+    pub fn record_type(&self) -> Option<RecordType> {
+        match self {
+            Self::A(_) => Some(RecordType::A),
+            Self::Ns(_) => Some(RecordType::Ns),
+            Self::Cname(_) => Some(RecordType::Cname),
+            Self::Soa { .. } => Some(RecordType::Soa),
+            Self::Mx { .. } => Some(RecordType::Mx),
+            Self::Ptr(_) => Some(RecordType::Ptr),
+            Self::Txt(_) => Some(RecordType::Txt),
+            Self::Aaaa(_) => Some(RecordType::Aaaa),
+            Self::Srv { .. } => Some(RecordType::Srv),
+            Self::Naptr { .. } => Some(RecordType::Naptr),
+            Self::Caa { .. } => Some(RecordType::Caa),
+            Self::Ds { .. } => Some(RecordType::Ds),
+            Self::Rrsig { .. } => Some(RecordType::Rrsig),
+            Self::Nsec { .. } => Some(RecordType::Nsec),
+            Self::Dnskey { .. } => Some(RecordType::Dnskey),
+            Self::Nsec3 { .. } => Some(RecordType::Nsec3),
+            Self::Nsec3param { .. } => Some(RecordType::Nsec3param),
+            Self::Svcb(_) => Some(RecordType::Svcb),
+            Self::Https(_) => Some(RecordType::Https),
+            Self::Unknown(_) => None,
+        }
+    }
+
+    /// The key tag of DNSKEY data, which RRSIG and DS records use to refer to the key
+    /// (RFC 4034 B). Other data doesn't have one
+    pub fn key_tag(&self) -> Option<u16> {
+        let Self::Dnskey {
+            algorithm,
+            public_key,
+            ..
+        } = self
+        else {
+            return None;
+        };
+
+        // RSA/MD5 keys use bits of their modulus instead (RFC 4034 B.1)
+        if *algorithm == 1 {
+            let len = public_key.len();
+            return (len >= 3)
+                .then(|| u16::from_be_bytes([public_key[len - 3], public_key[len - 2]]));
+        }
+
+        let mut sum: u32 = 0;
+        for (i, byte) in self.to_bytes().iter().enumerate() {
+            sum += if i % 2 == 0 {
+                (*byte as u32) << 8
+            } else {
+                *byte as u32
+            };
+        }
+        sum += (sum >> 16) & 0xFFFF;
+
+        Some(sum as u16)
+    }
+
+    /// Parse the generic text form of record data, e.g. `\# 4 0A000001` (RFC 3597 5)
+    pub fn from_generic(type_: RecordType, s: &str) -> Result<Self, DnsError> {
+        let mut parts = s.trim().splitn(3, char::is_whitespace);
+        if parts.next() != Some(r"\#") {
+            return Err(DnsError::FormatError);
+        }
+
+        let length: u16 = parts
+            .next()
+            .and_then(|length| length.parse().ok())
+            .ok_or(DnsError::FormatError)?;
+
+        let data = decode_hex(parts.next().unwrap_or_default())?;
+        if data.len() != length as usize {
+            return Err(DnsError::FormatError);
+        }
+
+        let mut cursor = Cursor::new(&data[..]);
+        let ret = Self::from_bytes(type_, length, &mut cursor)?;
+        if cursor.has_remaining() {
+            return Err(DnsError::FormatError);
+        }
+
+        Ok(ret)
+    }
+
+    /// The generic text form of the data, which works for any type (RFC 3597 5)
+    pub fn to_generic(&self) -> String {
+        let data = self.to_bytes();
+        if data.is_empty() {
+            return r"\# 0".to_owned();
+        }
+
+        format!(r"\# {} {}", data.len(), encode_hex(&data))
+    }
+
+    /// Write the data, the names of the types that RFC 1035 defines may be compressed.
+    /// PTR is one of them, but its target is left uncompressed like SRV's (RFC 2782),
+    /// SVCB's (RFC 9460) and the names in DNSSEC records (RFC 4034 3.1.7)
+    pub(crate) fn encode<'m>(&'m self, encoder: &mut Encoder<'m, impl Sink>) {
+        match self {
+            Self::A(data) => encoder.put_u32((*data).into()),
+            Self::Ns(data) => encoder.put_name(data),
+            Self::Cname(data) => encoder.put_name(data),
+            Self::Soa {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => {
+                encoder.put_name(mname);
+                encoder.put_name(rname);
+                encoder.put_u32(*serial);
+                encoder.put_u32(*refresh);
+                encoder.put_u32(*retry);
+                encoder.put_u32(*expire);
+                encoder.put_u32(*minimum);
+            }
+            Self::Mx {
+                preference,
+                exchange,
+            } => {
+                encoder.put_u16(*preference);
+                encoder.put_name(exchange);
+            }
+            Self::Ptr(data) => encoder.put_name_uncompressed(data),
+            Self::Txt(strings) => {
+                for string in strings.iter() {
+                    string.write(encoder);
+                }
+            }
+            Self::Aaaa(data) => encoder.put_u128((*data).into()),
+            Self::Srv {
+                priority,
+                weight,
+                port,
+                target,
+            } => {
+                encoder.put_u16(*priority);
+                encoder.put_u16(*weight);
+                encoder.put_u16(*port);
+                encoder.put_name_uncompressed(target);
+            }
+            Self::Naptr {
+                order,
+                preference,
+                flags,
+                services,
+                regexp,
+                replacement,
+            } => {
+                encoder.put_u16(*order);
+                encoder.put_u16(*preference);
+                flags.write(encoder);
+                services.write(encoder);
+                regexp.write(encoder);
+                encoder.put_name_uncompressed(replacement);
+            }
+            Self::Caa { flags, tag, value } => {
+                encoder.put_u8(*flags);
+                encoder.put_u8(tag.len() as u8);
+                encoder.put_slice(tag.as_bytes());
+                encoder.put_slice(value);
+            }
+            Self::Ds {
+                key_tag,
+                algorithm,
+                digest_type,
+                digest,
+            } => {
+                encoder.put_u16(*key_tag);
+                encoder.put_u8(*algorithm);
+                encoder.put_u8(*digest_type);
+                encoder.put_slice(digest);
+            }
+            Self::Rrsig {
+                type_covered,
+                algorithm,
+                labels,
+                original_ttl,
+                expiration,
+                inception,
+                key_tag,
+                signer,
+                signature,
+            } => {
+                encoder.put_u16(type_covered.to_int());
+                encoder.put_u8(*algorithm);
+                encoder.put_u8(*labels);
+                encoder.put_u32(*original_ttl);
+                encoder.put_u32(*expiration);
+                encoder.put_u32(*inception);
+                encoder.put_u16(*key_tag);
+                encoder.put_name_uncompressed(signer);
+                encoder.put_slice(signature);
+            }
+            Self::Nsec { next, types } => {
+                encoder.put_name_uncompressed(next);
+                types.write(encoder);
+            }
+            Self::Dnskey {
+                flags,
+                protocol,
+                algorithm,
+                public_key,
+            } => {
+                encoder.put_u16(*flags);
+                encoder.put_u8(*protocol);
+                encoder.put_u8(*algorithm);
+                encoder.put_slice(public_key);
+            }
+            Self::Nsec3 {
+                hash_algorithm,
+                flags,
+                iterations,
+                salt,
+                next_hashed,
+                types,
+            } => {
+                encoder.put_u8(*hash_algorithm);
+                encoder.put_u8(*flags);
+                encoder.put_u16(*iterations);
+                encoder.put_u8(salt.len() as u8);
+                encoder.put_slice(salt);
+                encoder.put_u8(next_hashed.len() as u8);
+                encoder.put_slice(next_hashed);
+                types.write(encoder);
+            }
+            Self::Nsec3param {
+                hash_algorithm,
+                flags,
+                iterations,
+                salt,
+            } => {
+                encoder.put_u8(*hash_algorithm);
+                encoder.put_u8(*flags);
+                encoder.put_u16(*iterations);
+                encoder.put_u8(salt.len() as u8);
+                encoder.put_slice(salt);
+            }
+            Self::Svcb(binding) | Self::Https(binding) => binding.encode(encoder),
+            Self::Unknown(data) => encoder.put_slice(data),
+        }
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        self.encode(&mut Encoder::uncompressed(&mut ret));
+        ret
+    }
+
+    /// The data of a `type_` record as it's signed, with the names of the types that
+    /// RFC 4034 6.2 lists in lowercase. RFC 6840 5.1 took NSEC off that list,
+    /// so its next name keeps its case
+    pub(crate) fn to_canonical_bytes(&self, type_: RecordType) -> Vec<u8> {
+        let canonical = match self.clone() {
+            Self::Ns(name) => Self::Ns(name.to_lowercase()),
+            Self::Cname(name) => Self::Cname(name.to_lowercase()),
+            Self::Ptr(name) => Self::Ptr(name.to_lowercase()),
+            Self::Soa {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => Self::Soa {
+                mname: mname.to_lowercase(),
+                rname: rname.to_lowercase(),
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            },
+            Self::Mx {
+                preference,
+                exchange,
+            } => Self::Mx {
+                preference,
+                exchange: exchange.to_lowercase(),
+            },
+            Self::Srv {
+                priority,
+                weight,
+                port,
+                target,
+            } => Self::Srv {
+                priority,
+                weight,
+                port,
+                target: target.to_lowercase(),
+            },
+            Self::Naptr {
+                order,
+                preference,
+                flags,
+                services,
+                regexp,
+                replacement,
+            } => Self::Naptr {
+                order,
+                preference,
+                flags,
+                services,
+                regexp,
+                replacement: replacement.to_lowercase(),
+            },
+            Self::Rrsig {
+                type_covered,
+                algorithm,
+                labels,
+                original_ttl,
+                expiration,
+                inception,
+                key_tag,
+                signer,
+                signature,
+            } => Self::Rrsig {
+                type_covered,
+                algorithm,
+                labels,
+                original_ttl,
+                expiration,
+                inception,
+                key_tag,
+                signer: signer.to_lowercase(),
+                signature,
+            },
+            Self::Unknown(data) => {
+                return lowercase_embedded_names(type_, &data).unwrap_or(data);
+            }
+            data => data,
+        };
+
+        canonical.to_bytes()
+    }
+}
+
+impl Display for RecordData {
+    /// The data as it's written in zone files (RFC 1035 5.1)
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::A(data) => u32::from(*data).to_be_bytes().to_vec(),
-            Self::Ns(data) => data.to_bytes().to_vec(),
-            Self::Cname(data) => data.to_bytes().to_vec(),
+            Self::A(address) => write!(f, "{address}"),
+            Self::Ns(name) | Self::Cname(name) | Self::Ptr(name) => write!(f, "{name:#}"),
             Self::Soa {
                 mname,
                 rname,
@@ -73,21 +643,622 @@ impl RecordData {
                 retry,
                 expire,
                 minimum,
+            } => write!(
+                f,
+                "{mname:#} {rname:#} {serial} {refresh} {retry} {expire} {minimum}"
+            ),
+            Self::Mx {
+                preference,
+                exchange,
+            } => write!(f, "{preference} {exchange:#}"),
+            Self::Txt(strings) => {
+                for (i, string) in strings.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(" ")?;
+                    }
+                    write!(f, "{string}")?;
+                }
+                Ok(())
+            }
+            Self::Aaaa(address) => write!(f, "{address}"),
+            Self::Srv {
+                priority,
+                weight,
+                port,
+                target,
+            } => write!(f, "{priority} {weight} {port} {target:#}"),
+            Self::Naptr {
+                order,
+                preference,
+                flags,
+                services,
+                regexp,
+                replacement,
+            } => write!(
+                f,
+                "{order} {preference} {flags} {services} {regexp} {replacement:#}"
+            ),
+            Self::Caa { flags, tag, value } => {
+                write!(f, "{flags} {tag} ")?;
+                write_quoted(f, value)
+            }
+            Self::Ds {
+                key_tag,
+                algorithm,
+                digest_type,
+                digest,
+            } => write!(
+                f,
+                "{key_tag} {algorithm} {digest_type} {}",
+                encode_hex(digest)
+            ),
+            Self::Rrsig {
+                type_covered,
+                algorithm,
+                labels,
+                original_ttl,
+                expiration,
+                inception,
+                key_tag,
+                signer,
+                signature,
+            } => write!(
+                f,
+                "{type_covered} {algorithm} {labels} {original_ttl} {} {} {key_tag} {signer:#} {}",
+                format_timestamp(*expiration),
+                format_timestamp(*inception),
+                encode_base64(signature)
+            ),
+            Self::Nsec { next, types } => {
+                write!(f, "{next:#}")?;
+                write_types(f, types)
+            }
+            Self::Dnskey {
+                flags,
+                protocol,
+                algorithm,
+                public_key,
+            } => write!(
+                f,
+                "{flags} {protocol} {algorithm} {}",
+                encode_base64(public_key)
+            ),
+            Self::Nsec3 {
+                hash_algorithm,
+                flags,
+                iterations,
+                salt,
+                next_hashed,
+                types,
             } => {
-                let mut ret = Vec::new();
-                ret.extend_from_slice(&mname.to_bytes());
-                ret.extend_from_slice(&rname.to_bytes());
-                ret.extend_from_slice(&serial.to_be_bytes());
-                ret.extend_from_slice(&refresh.to_be_bytes());
-                ret.extend_from_slice(&retry.to_be_bytes());
-                ret.extend_from_slice(&expire.to_be_bytes());
-                ret.extend_from_slice(&minimum.to_be_bytes());
-                ret
-            }
-            Self::Mx { priority, exchange } => todo!(),
-            Self::Txt(data) => data.as_bytes().to_vec(),
-            Self::Aaaa(data) => u128::from(*data).to_be_bytes().to_vec(),
-            Self::Other => Vec::new(),
+                write!(
+                    f,
+                    "{hash_algorithm} {flags} {iterations} {} {}",
+                    salt_text(salt),
+                    encode_base32hex(next_hashed)
+                )?;
+                write_types(f, types)
+            }
+            Self::Nsec3param {
+                hash_algorithm,
+                flags,
+                iterations,
+                salt,
+            } => write!(
+                f,
+                "{hash_algorithm} {flags} {iterations} {}",
+                salt_text(salt)
+            ),
+            Self::Svcb(binding) | Self::Https(binding) => write!(f, "{binding}"),
+            Self::Unknown(_) => f.write_str(&self.to_generic()),
+        }
+    }
+}
+
+/// The layout of types that dnrs keeps unparsed but whose data has names:
+/// how many bytes of fixed fields come first, then how many names follow
+fn embedded_names(type_: RecordType) -> Option<(usize, usize)> {
+    match type_ {
+        RecordType::Md
+        | RecordType::Mf
+        | RecordType::Mb
+        | RecordType::Mg
+        | RecordType::Mr
+        | RecordType::Dname => Some((0, 1)),
+        RecordType::Minfo | RecordType::Rp => Some((0, 2)),
+        RecordType::Afsdb | RecordType::Rt | RecordType::Kx => Some((2, 1)),
+        RecordType::Px => Some((2, 2)),
+        _ => None,
+    }
+}
+
+/// The unparsed data of a type with names, with the names in lowercase.
+/// None if the type doesn't have names or the data doesn't have its layout
+fn lowercase_embedded_names(type_: RecordType, data: &[u8]) -> Option<Vec<u8>> {
+    let (fixed, names) = embedded_names(type_)?;
+    let mut bytes = Cursor::new(data);
+    let mut canonical = bytes.read_bytes(fixed).ok()?.to_vec();
+    for _ in 0..names {
+        let name = Name::from_bytes(&mut bytes).ok()?;
+        canonical.extend_from_slice(&name.to_lowercase().to_bytes());
+    }
+
+    (bytes.position() == data.len() as u64).then_some(canonical)
+}
+
+/// CAA tags are at least one ASCII letter or digit (RFC 8659 4.1)
+pub(crate) fn is_caa_tag(tag: &[u8]) -> bool {
+    !tag.is_empty() && tag.iter().all(u8::is_ascii_alphanumeric)
+}
+
+/// The types of an NSEC or NSEC3 record, each after a space
+fn write_types(f: &mut std::fmt::Formatter<'_>, types: &TypeBitmap) -> std::fmt::Result {
+    if types.is_empty() {
+        return Ok(());
+    }
+
+    write!(f, " {types}")
+}
+
+/// NSEC3 salts are hex, or `-` when there's no salt (RFC 5155 3.3)
+fn salt_text(salt: &[u8]) -> String {
+    if salt.is_empty() {
+        return "-".to_owned();
+    }
+
+    encode_hex(salt)
+}
+
+/// The number of bytes left of record data that started at `start`
+fn remaining(start: u64, rd_length: u16, bytes: &Cursor<&[u8]>) -> Result<usize, DnsError> {
+    (start + rd_length as u64)
+        .checked_sub(bytes.position())
+        .map(|len| len as usize)
+        .ok_or(DnsError::FormatError)
+}
+
+/// Read the rest of record data that started at `start`
+fn read_rest(start: u64, rd_length: u16, bytes: &mut Cursor<&[u8]>) -> Result<Vec<u8>, DnsError> {
+    let len = remaining(start, rd_length, bytes)?;
+    Ok(bytes.read_bytes(len)?.to_vec())
+}
+
+fn expect_length(rd_length: u16, expected: u16) -> Result<(), DnsError> {
+    if rd_length != expected {
+        return Err(DnsError::FormatError);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        CharacterString, DnsClass, DnsError, Header, Message, Name, Networkable, Question,
+        RecordData, RecordType, ResourceRecord, TypeBitmap,
+    };
+
+    fn round_trip(type_: RecordType, data: RecordData) -> Message {
+        let mut message = Message::new(Header::new(1, Default::default()));
+        message.add_question(Question::new(Name::new("example.com"), type_));
+        message.add_answer(ResourceRecord {
+            name: Name::new("example.com"),
+            type_,
+            class: DnsClass::In,
+            ttl: 300,
+            data: data.clone(),
+        });
+
+        let bytes = message.to_bytes();
+        let parsed = Message::from_bytes(&mut Cursor::new(&bytes)).unwrap();
+
+        assert_eq!(parsed.answers[0].type_, type_);
+        assert_eq!(parsed.answers[0].data, data);
+        assert_eq!(parsed.to_bytes(), bytes);
+
+        parsed
+    }
+
+    #[test]
+    fn round_trips_mx() {
+        let data = RecordData::Mx {
+            preference: 10,
+            exchange: Name::new("mail.example.com"),
+        };
+        let message = round_trip(RecordType::Mx, data.clone());
+
+        // The exchange is compressed against the owner name:
+        // header, question, owner pointer and fixed fields, preference, mail label and pointer
+        assert_eq!(message.to_bytes().len(), 12 + 17 + (2 + 10) + 2 + 5 + 2);
+
+        let bytes = data.to_bytes();
+        assert_eq!(&bytes[..2], [0, 10]);
+        assert_eq!(
+            RecordData::from_bytes(RecordType::Mx, bytes.len() as u16, &mut Cursor::new(&bytes))
+                .unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn never_compresses_ptr_and_srv_targets() {
+        let target = Name::new("sip.example.com");
+        let data = RecordData::Srv {
+            priority: 10,
+            weight: 60,
+            port: 5060,
+            target: target.clone(),
+        };
+        let message = round_trip(RecordType::Srv, data.clone());
+        assert_eq!(data.to_bytes()[..6], [0, 10, 0, 60, 0x13, 0xC4]);
+
+        // The target is written in full even though example.com was written before it
+        let bytes = message.to_bytes();
+        let target_bytes = target.to_bytes();
+        assert!(bytes.ends_with(&target_bytes));
+
+        let message = round_trip(RecordType::Ptr, RecordData::Ptr(target));
+        assert!(message.to_bytes().ends_with(&target_bytes));
+    }
+
+    #[test]
+    fn round_trips_naptr_and_caa() {
+        round_trip(
+            RecordType::Naptr,
+            RecordData::Naptr {
+                order: 100,
+                preference: 10,
+                flags: CharacterString::try_from("U").unwrap(),
+                services: CharacterString::try_from("E2U+sip").unwrap(),
+                regexp: CharacterString::try_from("!^.*$!sip:info@example.com!").unwrap(),
+                replacement: Name::root(),
+            },
+        );
+
+        let data = RecordData::Caa {
+            flags: 0,
+            tag: "issue".to_owned(),
+            value: b"ca.example.net".to_vec(),
+        };
+        round_trip(RecordType::Caa, data.clone());
+        assert_eq!(&data.to_bytes()[..7], b"\x00\x05issue");
+
+        // Tags have to be letters and digits, and fit in the data
+        for bytes in [&b"\x00\x00"[..], b"\x00\x02i-", b"\x00\x09issue"] {
+            let result = RecordData::from_bytes(
+                RecordType::Caa,
+                bytes.len() as u16,
+                &mut Cursor::new(bytes),
+            );
+            assert!(matches!(result, Err(DnsError::FormatError)));
+        }
+    }
+
+    #[test]
+    fn round_trips_dnssec_records() {
+        let types: TypeBitmap = [RecordType::A, RecordType::Rrsig, RecordType::Nsec]
+            .into_iter()
+            .collect();
+
+        let cases = [
+            RecordData::Dnskey {
+                flags: 257,
+                protocol: 3,
+                algorithm: 13,
+                public_key: vec![1; 64],
+            },
+            RecordData::Ds {
+                key_tag: 12345,
+                algorithm: 13,
+                digest_type: 2,
+                digest: vec![2; 32],
+            },
+            RecordData::Rrsig {
+                type_covered: RecordType::A,
+                algorithm: 13,
+                labels: 2,
+                original_ttl: 300,
+                expiration: 1700000000,
+                inception: 1690000000,
+                key_tag: 12345,
+                signer: Name::new("example.com"),
+                signature: vec![3; 64],
+            },
+            RecordData::Nsec {
+                next: Name::new("a.example.com"),
+                types: types.clone(),
+            },
+            RecordData::Nsec {
+                next: Name::new("example.com"),
+                types: TypeBitmap::new(),
+            },
+            RecordData::Nsec3 {
+                hash_algorithm: 1,
+                flags: 1,
+                iterations: 0,
+                salt: Vec::new(),
+                next_hashed: vec![4; 20],
+                types,
+            },
+            RecordData::Nsec3param {
+                hash_algorithm: 1,
+                flags: 0,
+                iterations: 10,
+                salt: vec![0xAB, 0xCD],
+            },
+        ];
+
+        for data in cases {
+            let type_ = data.record_type().unwrap();
+            let message = round_trip(type_, data.clone());
+
+            // Names in the data are written in full, so the data is the same as on its own
+            let bytes = message.to_bytes();
+            assert!(bytes.ends_with(&data.to_bytes()));
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_dnssec_records() {
+        let invalid: [(RecordType, &[u8]); 4] = [
+            // A DS shorter than its fixed fields
+            (RecordType::Ds, &[0, 1, 13]),
+            // An NSEC3 without a hash
+            (RecordType::Nsec3, &[1, 0, 0, 0, 0, 0]),
+            // An NSEC3PARAM with a salt longer than the data
+            (RecordType::Nsec3param, &[1, 0, 0, 0, 2, 0xAB]),
+            // An RRSIG whose signer runs past the data
+            (
+                RecordType::Rrsig,
+                &[0, 1, 13, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3],
+            ),
+        ];
+
+        for (type_, bytes) in invalid {
+            let result = RecordData::from_bytes(type_, bytes.len() as u16, &mut Cursor::new(bytes));
+            assert!(matches!(result, Err(DnsError::FormatError)), "{type_}");
+        }
+    }
+
+    #[test]
+    fn round_trips_txt() {
+        let data = RecordData::Txt(vec![
+            CharacterString::try_from("v=spf1 -all").unwrap(),
+            CharacterString::new(Vec::new()).unwrap(),
+            CharacterString::new(vec![0, 0xFF, b'"']).unwrap(),
+        ]);
+        round_trip(RecordType::Txt, data.clone());
+
+        assert_eq!(data.to_bytes(), b"\x0bv=spf1 -all\x00\x03\x00\xFF\x22");
+    }
+
+    #[test]
+    fn splits_long_txt_strings() {
+        let long = vec![b'a'; 300];
+        assert!(CharacterString::new(long.clone()).is_err());
+
+        let strings = CharacterString::split(&long);
+        assert_eq!(strings.len(), 2);
+        assert_eq!(strings[0].as_bytes().len(), 255);
+        assert_eq!(strings[1].as_bytes().len(), 45);
+
+        round_trip(RecordType::Txt, RecordData::Txt(strings));
+    }
+
+    #[test]
+    fn rejects_txt_overrunning_rd_length() {
+        let record = ResourceRecord {
+            name: Name::root(),
+            type_: RecordType::Txt,
+            class: DnsClass::In,
+            ttl: 300,
+            data: RecordData::Txt(vec![CharacterString::try_from("abc").unwrap()]),
+        };
+
+        // Make the string claim one more byte than the record data holds
+        let mut bytes = record.to_bytes().to_vec();
+        assert_eq!(bytes[11..], [3, b'a', b'b', b'c']);
+        bytes[11] = 4;
+        bytes.push(b'd');
+
+        let result = ResourceRecord::from_bytes(&mut Cursor::new(&bytes));
+        assert!(matches!(result, Err(DnsError::FormatError)));
+    }
+
+    #[test]
+    fn displays_zone_file_syntax() {
+        let record = |name: &str, type_, data| ResourceRecord {
+            name: Name::new(name),
+            type_,
+            class: DnsClass::In,
+            ttl: 300,
+            data,
+        };
+
+        let cases = [
+            (
+                record("www.example.com", RecordType::A, RecordData::A([1, 2, 3, 4].into())),
+                "www.example.com. 300 IN A 1.2.3.4",
+            ),
+            (
+                record("example.com", RecordType::Ns, RecordData::Ns(Name::new("ns1.example.com"))),
+                "example.com. 300 IN NS ns1.example.com.",
+            ),
+            (
+                record("www.example.com", RecordType::Cname, RecordData::Cname(Name::root())),
+                "www.example.com. 300 IN CNAME .",
+            ),
+            (
+                record(
+                    "example.com",
+                    RecordType::Soa,
+                    RecordData::Soa {
+                        mname: Name::new("ns1.example.com"),
+                        rname: Name::new("hostmaster.example.com"),
+                        serial: 2024010101,
+                        refresh: 7200,
+                        retry: 3600,
+                        expire: 1209600,
+                        minimum: 300,
+                    },
+                ),
+                "example.com. 300 IN SOA ns1.example.com. hostmaster.example.com. 2024010101 7200 3600 1209600 300",
+            ),
+            (
+                record(
+                    "example.com",
+                    RecordType::Mx,
+                    RecordData::Mx {
+                        preference: 10,
+                        exchange: Name::new("mail.example.com"),
+                    },
+                ),
+                "example.com. 300 IN MX 10 mail.example.com.",
+            ),
+            (
+                record(
+                    "example.com",
+                    RecordType::Txt,
+                    RecordData::Txt(vec![
+                        CharacterString::try_from("v=spf1 -all").unwrap(),
+                        CharacterString::new(vec![b'"', b'\\', 0, 0xFF]).unwrap(),
+                    ]),
+                ),
+                r#"example.com. 300 IN TXT "v=spf1 -all" "\"\\\000\255""#,
+            ),
+            (
+                record("example.com", RecordType::Aaaa, RecordData::Aaaa("2001:db8::1".parse().unwrap())),
+                "example.com. 300 IN AAAA 2001:db8::1",
+            ),
+            (
+                record("example.com", RecordType::from_int(65280), RecordData::Unknown(vec![0x0A, 0, 0, 1])),
+                r"example.com. 300 IN TYPE65280 \# 4 0A000001",
+            ),
+        ];
+
+        for (record, text) in cases {
+            assert_eq!(record.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn round_trips_unknown_types() {
+        let type_ = RecordType::from_int(65280);
+        let data = RecordData::Unknown(vec![0xC0, 0x0C, 0, 1, 2]);
+
+        let mut message = Message::new(Header::new(1, Default::default()));
+        message.add_question(Question::new(Name::new("example.com"), type_));
+        message.add_answer(ResourceRecord {
+            name: Name::new("example.com"),
+            type_,
+            class: DnsClass::In,
+            ttl: 300,
+            data: data.clone(),
+        });
+
+        let bytes = message.to_bytes();
+        let parsed = Message::from_bytes(&mut Cursor::new(&bytes)).unwrap();
+
+        assert_eq!(parsed.questions[0].type_, type_);
+        assert_eq!(parsed.answers[0].type_, type_);
+        assert_eq!(parsed.answers[0].data, data);
+        assert_eq!(parsed.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn decompresses_names_of_unparsed_types() {
+        let mailbox = |type_, data| {
+            let mut message = Message::new(Header::new(1, Default::default()));
+            message.add_question(Question::new(Name::new("example.com"), type_));
+            message.add_answer(ResourceRecord {
+                name: Name::new("example.com"),
+                type_,
+                class: DnsClass::In,
+                ttl: 300,
+                data: RecordData::Unknown(data),
+            });
+
+            let bytes = message.to_bytes();
+            Message::from_bytes(&mut Cursor::new(&bytes))
+                .unwrap()
+                .answers[0]
+                .data
+                .clone()
+        };
+        let name = Name::new("example.com").to_bytes().to_vec();
+
+        // Pointers to the question name
+        assert_eq!(
+            mailbox(RecordType::Mb, vec![0xC0, 0x0C]),
+            RecordData::Unknown(name.clone())
+        );
+        assert_eq!(
+            mailbox(RecordType::Minfo, vec![0xC0, 0x0C, 0xC0, 0x0C]),
+            RecordData::Unknown([name.clone(), name.clone()].concat())
+        );
+        // The preference comes before the name
+        assert_eq!(
+            mailbox(RecordType::Afsdb, vec![0, 1, 0xC0, 0x0C]),
+            RecordData::Unknown([vec![0, 1], name].concat())
+        );
+    }
+
+    #[test]
+    fn lowercases_names_in_canonical_form() {
+        let name = |name: &str| Name::new(name).to_bytes().to_vec();
+
+        let mx = RecordData::Mx {
+            preference: 10,
+            exchange: Name::new("Mail.Example.com"),
+        };
+        assert_eq!(
+            mx.to_canonical_bytes(RecordType::Mx),
+            [vec![0, 10], name("mail.example.com")].concat()
+        );
+
+        // Unparsed types with names are lowercased too
+        let px =
+            RecordData::Unknown([vec![0, 1], name("Map.Example"), name("X400.Example")].concat());
+        assert_eq!(
+            px.to_canonical_bytes(RecordType::Px),
+            [vec![0, 1], name("map.example"), name("x400.example")].concat()
+        );
+
+        // Data that doesn't have the type's layout is left alone
+        let dname = RecordData::Unknown(vec![1, b'A']);
+        assert_eq!(dname.to_canonical_bytes(RecordType::Dname), [1, b'A']);
+        let unknown = RecordData::Unknown(name("Example"));
+        assert_eq!(
+            unknown.to_canonical_bytes(RecordType::from_int(65280)),
+            name("Example")
+        );
+    }
+
+    #[test]
+    fn converts_generic_text() {
+        let data = RecordData::Unknown(vec![0x0A, 0, 0, 1]);
+        assert_eq!(data.to_generic(), r"\# 4 0A000001");
+        assert_eq!(
+            RecordData::from_generic(RecordType::from_int(123), r"\# 4 0a 00 0001").unwrap(),
+            data
+        );
+
+        // Known types can use the generic form too
+        assert_eq!(
+            RecordData::from_generic(RecordType::A, r"\# 4 0A000001").unwrap(),
+            RecordData::A([10, 0, 0, 1].into())
+        );
+
+        assert_eq!(RecordData::Unknown(Vec::new()).to_generic(), r"\# 0");
+        assert_eq!(
+            RecordData::from_generic(RecordType::from_int(123), r"\# 0").unwrap(),
+            RecordData::Unknown(Vec::new())
+        );
+
+        for invalid in [r"\# 3 0A000001", r"# 4 0A000001", r"\# 2 0A0", r"\# x"] {
+            assert!(RecordData::from_generic(RecordType::from_int(123), invalid).is_err());
         }
     }
 }
@@ -0,0 +1,578 @@
+use std::fmt::Display;
+use std::io::Cursor;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use super::character_string::write_quoted;
+use crate::encode::{Encoder, Sink, WireLength};
+use crate::read::CheckedBuf;
+use crate::text::{decode_base64, encode_base64};
+use crate::{CharacterString, DnsError, Name, Networkable};
+
+macro_rules! svc_param_keys {
+    ($($variant:ident = $value:literal => $name:literal,)*) => {
+        /// The key of a SvcParam (RFC 9460 14.3.2)
+        ///
+        /// Keys without a name are written as `keyNNNNN` in presentation format
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+        pub enum SvcParamKey {
+            $($variant,)*
+            Unknown(u16),
+        }
+
+        impl SvcParamKey {
+            pub fn to_int(&self) -> u16 {
+                match self {
+                    $(Self::$variant => $value,)*
+                    Self::Unknown(v) => *v,
+                }
+            }
+
+            pub fn from_int(v: u16) -> Self {
+                match v {
+                    $($value => Self::$variant,)*
+                    v => Self::Unknown(v),
+                }
+            }
+
+            fn name(&self) -> Option<&'static str> {
+                match self {
+                    $(Self::$variant => Some($name),)*
+                    Self::Unknown(_) => None,
+                }
+            }
+
+            fn from_name(s: &str) -> Option<Self> {
+                match s {
+                    $($name => Some(Self::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+svc_param_keys! {
+    Mandatory = 0 => "mandatory",
+    Alpn = 1 => "alpn",
+    NoDefaultAlpn = 2 => "no-default-alpn",
+    Port = 3 => "port",
+    Ipv4Hint = 4 => "ipv4hint",
+    Ech = 5 => "ech",
+    Ipv6Hint = 6 => "ipv6hint",
+}
+
+impl Display for SvcParamKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "key{}", self.to_int()),
+        }
+    }
+}
+
+impl FromStr for SvcParamKey {
+    type Err = DnsError;
+
+    /// Parse a key name or `keyNNNNN`, e.g. `key1` is the same as `alpn`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(key) = Self::from_name(s) {
+            return Ok(key);
+        }
+
+        s.strip_prefix("key")
+            .filter(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|digits| digits.parse().ok())
+            .map(Self::from_int)
+            .ok_or(DnsError::FormatError)
+    }
+}
+
+/// A parameter of a service binding (RFC 9460 7)
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub enum SvcParam {
+    /// Keys that a client has to understand to use the binding
+    Mandatory(Vec<SvcParamKey>),
+    /// The protocol ids (ALPN) that the service supports, e.g. `h2`
+    Alpn(Vec<CharacterString>),
+    /// The default protocol of the scheme isn't supported, only the ones in `Alpn`
+    NoDefaultAlpn,
+    Port(u16),
+    Ipv4Hint(Vec<Ipv4Addr>),
+    /// An ECHConfigList, which dnrs keeps as it is
+    Ech(Vec<u8>),
+    Ipv6Hint(Vec<Ipv6Addr>),
+    /// The raw value of a key that dnrs doesn't parse
+    Unknown(u16, Vec<u8>),
+}
+
+impl SvcParam {
+    pub fn key(&self) -> SvcParamKey {
+        match self {
+            Self::Mandatory(_) => SvcParamKey::Mandatory,
+            Self::Alpn(_) => SvcParamKey::Alpn,
+            Self::NoDefaultAlpn => SvcParamKey::NoDefaultAlpn,
+            Self::Port(_) => SvcParamKey::Port,
+            Self::Ipv4Hint(_) => SvcParamKey::Ipv4Hint,
+            Self::Ech(_) => SvcParamKey::Ech,
+            Self::Ipv6Hint(_) => SvcParamKey::Ipv6Hint,
+            Self::Unknown(key, _) => SvcParamKey::Unknown(*key),
+        }
+    }
+
+    /// Read the value of a parameter with `key`, which is all of `value`
+    fn from_value(key: SvcParamKey, value: &[u8]) -> Result<Self, DnsError> {
+        let mut bytes = Cursor::new(value);
+
+        let param = match key {
+            SvcParamKey::Mandatory => {
+                let keys = read_list(&mut bytes, |bytes| {
+                    Ok(SvcParamKey::from_int(bytes.read_u16()?))
+                })?;
+
+                // The keys are sorted without duplicates, like the parameters
+                if keys
+                    .windows(2)
+                    .any(|pair| pair[0].to_int() >= pair[1].to_int())
+                {
+                    return Err(DnsError::FormatError);
+                }
+
+                Self::Mandatory(keys)
+            }
+            SvcParamKey::Alpn => {
+                let ids = read_list(&mut bytes, CharacterString::from_bytes)?;
+                if ids.iter().any(|id| id.as_bytes().is_empty()) {
+                    return Err(DnsError::FormatError);
+                }
+
+                Self::Alpn(ids)
+            }
+            SvcParamKey::NoDefaultAlpn => Self::NoDefaultAlpn,
+            SvcParamKey::Port => Self::Port(bytes.read_u16()?),
+            SvcParamKey::Ipv4Hint => {
+                Self::Ipv4Hint(read_list(&mut bytes, |bytes| Ok(bytes.read_u32()?.into()))?)
+            }
+            SvcParamKey::Ech => Self::Ech(value.to_vec()),
+            SvcParamKey::Ipv6Hint => Self::Ipv6Hint(read_list(&mut bytes, |bytes| {
+                Ok(bytes.read_u128()?.into())
+            })?),
+            SvcParamKey::Unknown(key) => Self::Unknown(key, value.to_vec()),
+        };
+
+        // Fixed size values have to be exactly that size
+        if !matches!(param, Self::Ech(_) | Self::Unknown(..))
+            && bytes.position() as usize != value.len()
+        {
+            return Err(DnsError::FormatError);
+        }
+
+        Ok(param)
+    }
+
+    /// Parse a parameter from presentation format, `value` has had its
+    /// character-string escapes resolved and is `None` when there was no `=` (RFC 9460 2.1)
+    pub(crate) fn from_text(key: &str, value: Option<&[u8]>) -> Result<Self, DnsError> {
+        let key: SvcParamKey = key.parse()?;
+
+        let Some(value) = value else {
+            return match key {
+                SvcParamKey::NoDefaultAlpn => Ok(Self::NoDefaultAlpn),
+                SvcParamKey::Unknown(key) => Ok(Self::Unknown(key, Vec::new())),
+                _ => Err(DnsError::FormatError),
+            };
+        };
+
+        let text = || std::str::from_utf8(value).map_err(|_| DnsError::FormatError);
+        match key {
+            SvcParamKey::Mandatory => {
+                let mut keys: Vec<SvcParamKey> = text()?
+                    .split(',')
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()?;
+
+                // Keys can be in any order here, but not repeated
+                keys.sort_by_key(SvcParamKey::to_int);
+                if keys.windows(2).any(|pair| pair[0] == pair[1]) {
+                    return Err(DnsError::FormatError);
+                }
+
+                Ok(Self::Mandatory(keys))
+            }
+            SvcParamKey::Alpn => {
+                let ids = split_value_list(value)
+                    .into_iter()
+                    .map(|id| match id.is_empty() {
+                        true => Err(DnsError::FormatError),
+                        false => CharacterString::new(id),
+                    })
+                    .collect::<Result<_, _>>()?;
+
+                Ok(Self::Alpn(ids))
+            }
+            SvcParamKey::NoDefaultAlpn if value.is_empty() => Ok(Self::NoDefaultAlpn),
+            SvcParamKey::NoDefaultAlpn => Err(DnsError::FormatError),
+            SvcParamKey::Port => text()?
+                .parse()
+                .map(Self::Port)
+                .map_err(|_| DnsError::FormatError),
+            SvcParamKey::Ipv4Hint => Ok(Self::Ipv4Hint(parse_list(text()?)?)),
+            SvcParamKey::Ech => Ok(Self::Ech(decode_base64(text()?)?)),
+            SvcParamKey::Ipv6Hint => Ok(Self::Ipv6Hint(parse_list(text()?)?)),
+            SvcParamKey::Unknown(key) => Ok(Self::Unknown(key, value.to_vec())),
+        }
+    }
+
+    fn write_value(&self, buf: &mut impl Sink) {
+        match self {
+            Self::Mandatory(keys) => keys.iter().for_each(|key| buf.put_u16(key.to_int())),
+            Self::Alpn(ids) => ids.iter().for_each(|id| id.write(buf)),
+            Self::NoDefaultAlpn => {}
+            Self::Port(port) => buf.put_u16(*port),
+            Self::Ipv4Hint(addresses) => addresses
+                .iter()
+                .for_each(|address| buf.put_u32((*address).into())),
+            Self::Ech(value) | Self::Unknown(_, value) => buf.put_slice(value),
+            Self::Ipv6Hint(addresses) => addresses
+                .iter()
+                .for_each(|address| buf.put_u128((*address).into())),
+        }
+    }
+}
+
+impl Display for SvcParam {
+    /// The `key=value` form of the parameter
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.key())?;
+
+        match self {
+            Self::Mandatory(keys) => write_list(f, keys),
+            Self::Alpn(ids) => {
+                // Commas and backslashes inside an id are escaped for the list,
+                // then the whole list is escaped again as a character-string (RFC 9460 A.1)
+                let mut value = Vec::new();
+                for (i, id) in ids.iter().enumerate() {
+                    if i != 0 {
+                        value.push(b',');
+                    }
+                    for byte in id.as_bytes() {
+                        if matches!(byte, b',' | b'\\') {
+                            value.push(b'\\');
+                        }
+                        value.push(*byte);
+                    }
+                }
+
+                f.write_str("=")?;
+                write_quoted(f, &value)
+            }
+            Self::NoDefaultAlpn => Ok(()),
+            Self::Port(port) => write!(f, "={port}"),
+            Self::Ipv4Hint(addresses) => write_list(f, addresses),
+            Self::Ech(value) => write!(f, "={}", encode_base64(value)),
+            Self::Ipv6Hint(addresses) => write_list(f, addresses),
+            Self::Unknown(_, value) if value.is_empty() => Ok(()),
+            Self::Unknown(_, value) => {
+                f.write_str("=")?;
+                write_quoted(f, value)
+            }
+        }
+    }
+}
+
+/// The data of SVCB and HTTPS records, where a service can be reached and how (RFC 9460 2.2)
+///
+/// The parameters are kept sorted by key and are checked when the binding is made,
+/// so a binding always has a valid wire format
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct ServiceBinding {
+    priority: u16,
+    target: Name,
+    params: Vec<SvcParam>,
+}
+
+impl ServiceBinding {
+    /// A binding with `params` in any order. Fails if a key is repeated,
+    /// a key in `mandatory` is missing from the parameters,
+    /// or there's `no-default-alpn` without `alpn` (RFC 9460 7.1.1)
+    pub fn new(priority: u16, target: Name, mut params: Vec<SvcParam>) -> Result<Self, DnsError> {
+        params.sort_by_key(|param| param.key().to_int());
+        Self::from_sorted(priority, target, params)
+    }
+
+    fn from_sorted(priority: u16, target: Name, params: Vec<SvcParam>) -> Result<Self, DnsError> {
+        if params
+            .windows(2)
+            .any(|pair| pair[0].key().to_int() >= pair[1].key().to_int())
+        {
+            return Err(DnsError::FormatError);
+        }
+
+        for param in params.iter() {
+            match param {
+                // Known keys have their own variant
+                SvcParam::Unknown(key, _) if SvcParamKey::from_int(*key).name().is_some() => {
+                    return Err(DnsError::FormatError);
+                }
+                SvcParam::Mandatory(keys) => {
+                    let missing = |key: &SvcParamKey| {
+                        *key == SvcParamKey::Mandatory
+                            || !params.iter().any(|param| param.key() == *key)
+                    };
+                    if keys.is_empty() || keys.iter().any(missing) {
+                        return Err(DnsError::FormatError);
+                    }
+                }
+                SvcParam::Alpn(ids) if ids.is_empty() => return Err(DnsError::FormatError),
+                SvcParam::NoDefaultAlpn
+                    if !params.iter().any(|param| param.key() == SvcParamKey::Alpn) =>
+                {
+                    return Err(DnsError::FormatError)
+                }
+                SvcParam::Ipv4Hint(addresses) if addresses.is_empty() => {
+                    return Err(DnsError::FormatError)
+                }
+                SvcParam::Ipv6Hint(addresses) if addresses.is_empty() => {
+                    return Err(DnsError::FormatError)
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            priority,
+            target,
+            params,
+        })
+    }
+
+    /// 0 for AliasMode, otherwise the preference of the binding in ServiceMode, lower first
+    pub fn priority(&self) -> u16 {
+        self.priority
+    }
+
+    /// A binding in AliasMode points at another name, like a CNAME that can be at the apex
+    pub fn is_alias(&self) -> bool {
+        self.priority == 0
+    }
+
+    /// Where the service is, `.` means the owner name in ServiceMode
+    pub fn target(&self) -> &Name {
+        &self.target
+    }
+
+    /// The parameters, sorted by key
+    pub fn params(&self) -> &[SvcParam] {
+        &self.params
+    }
+
+    pub fn param(&self, key: SvcParamKey) -> Option<&SvcParam> {
+        self.params.iter().find(|param| param.key() == key)
+    }
+
+    pub(crate) fn from_bytes(rd_length: u16, bytes: &mut Cursor<&[u8]>) -> Result<Self, DnsError> {
+        let end = bytes.position() + rd_length as u64;
+
+        let priority = bytes.read_u16()?;
+        let target = Name::from_bytes(bytes)?;
+
+        let mut params = Vec::new();
+        while bytes.position() < end {
+            let key = SvcParamKey::from_int(bytes.read_u16()?);
+            let len = bytes.read_u16()?;
+            if bytes.position() + len as u64 > end {
+                return Err(DnsError::FormatError);
+            }
+
+            params.push(SvcParam::from_value(key, &bytes.read_bytes(len as usize)?)?);
+        }
+
+        // On the wire the keys have to be in increasing order already
+        Self::from_sorted(priority, target, params)
+    }
+
+    /// Write the data, the target is never compressed (RFC 9460 2.2)
+    pub(crate) fn encode(&self, encoder: &mut Encoder<'_, impl Sink>) {
+        encoder.put_u16(self.priority);
+        encoder.put_name_uncompressed(&self.target);
+
+        for param in self.params.iter() {
+            let mut len = WireLength(0);
+            param.write_value(&mut len);
+
+            encoder.put_u16(param.key().to_int());
+            encoder.put_u16(len.0 as u16);
+            param.write_value(encoder);
+        }
+    }
+}
+
+impl Display for ServiceBinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {:#}", self.priority, self.target)?;
+        for param in self.params.iter() {
+            write!(f, " {param}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Write `=` and the items separated by commas
+fn write_list(f: &mut std::fmt::Formatter<'_>, items: &[impl Display]) -> std::fmt::Result {
+    f.write_str("=")?;
+    for (i, item) in items.iter().enumerate() {
+        if i != 0 {
+            f.write_str(",")?;
+        }
+        write!(f, "{item}")?;
+    }
+
+    Ok(())
+}
+
+/// Parse the items of a comma-separated value
+fn parse_list<T: FromStr>(text: &str) -> Result<Vec<T>, DnsError> {
+    text.split(',')
+        .map(|item| item.parse().map_err(|_| DnsError::FormatError))
+        .collect()
+}
+
+/// Read items until the value runs out, there has to be at least one
+fn read_list<'a, T>(
+    bytes: &mut Cursor<&'a [u8]>,
+    read: impl Fn(&mut Cursor<&'a [u8]>) -> Result<T, DnsError>,
+) -> Result<Vec<T>, DnsError> {
+    let mut items = Vec::new();
+    while (bytes.position() as usize) < bytes.get_ref().len() {
+        items.push(read(bytes)?);
+    }
+
+    if items.is_empty() {
+        return Err(DnsError::FormatError);
+    }
+
+    Ok(items)
+}
+
+/// Split a comma-separated value where `\,` and `\\` are a literal comma and backslash
+fn split_value_list(value: &[u8]) -> Vec<Vec<u8>> {
+    let mut items = vec![Vec::new()];
+
+    let mut bytes = value.iter();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'\\' => items.last_mut().unwrap().extend(bytes.next().copied()),
+            b',' => items.push(Vec::new()),
+            byte => items.last_mut().unwrap().push(*byte),
+        }
+    }
+
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{ServiceBinding, SvcParam, SvcParamKey};
+    use crate::{CharacterString, DnsError, Name};
+
+    fn parse(bytes: &[u8]) -> Result<ServiceBinding, DnsError> {
+        ServiceBinding::from_bytes(bytes.len() as u16, &mut Cursor::new(bytes))
+    }
+
+    #[test]
+    fn reads_the_wire_format() {
+        // The ServiceMode example of RFC 9460 D.2 with the port, alpn and ipv6hint
+        let mut bytes = b"\x00\x10\x03foo\x07example\x03com\x00".to_vec();
+        bytes.extend_from_slice(b"\x00\x01\x00\x06\x02h2\x02h3");
+        bytes.extend_from_slice(b"\x00\x03\x00\x02\x00\x35");
+        bytes.extend_from_slice(b"\x00\x06\x00\x10\x20\x01\x0d\xb8");
+        bytes.extend_from_slice(&[0; 11]);
+        bytes.push(1);
+
+        let binding = parse(&bytes).unwrap();
+        assert_eq!(binding.priority(), 16);
+        assert!(!binding.is_alias());
+        assert_eq!(binding.target(), &Name::new("foo.example.com"));
+        assert_eq!(
+            binding.param(SvcParamKey::Alpn),
+            Some(&SvcParam::Alpn(vec![
+                CharacterString::try_from("h2").unwrap(),
+                CharacterString::try_from("h3").unwrap(),
+            ]))
+        );
+        assert_eq!(binding.param(SvcParamKey::Port), Some(&SvcParam::Port(53)));
+        assert_eq!(
+            binding.to_string(),
+            r#"16 foo.example.com. alpn="h2,h3" port=53 ipv6hint=2001:db8::1"#
+        );
+
+        let mut written = Vec::new();
+        binding.encode(&mut crate::encode::Encoder::new(&mut written));
+        assert_eq!(written, bytes);
+    }
+
+    #[test]
+    fn rejects_invalid_params() {
+        let invalid: [&[u8]; 8] = [
+            // Keys out of order
+            b"\x00\x01\x00\x00\x03\x00\x02\x00\x35\x00\x01\x00\x03\x02h2",
+            // A repeated key
+            b"\x00\x01\x00\x00\x03\x00\x02\x00\x35\x00\x03\x00\x02\x00\x35",
+            // A mandatory key that isn't there
+            b"\x00\x01\x00\x00\x00\x00\x02\x00\x01",
+            // A port that's too long
+            b"\x00\x01\x00\x00\x03\x00\x03\x00\x35\x00",
+            // An empty alpn id
+            b"\x00\x01\x00\x00\x01\x00\x01\x00",
+            // No default alpn without an alpn
+            b"\x00\x01\x00\x00\x02\x00\x00",
+            // An address hint with part of an address
+            b"\x00\x01\x00\x00\x04\x00\x03\x01\x02\x03",
+            // A value that runs past the data
+            b"\x00\x01\x00\x00\x03\x00\x04\x00\x35",
+        ];
+
+        for bytes in invalid {
+            assert!(matches!(parse(bytes), Err(DnsError::FormatError)));
+        }
+    }
+
+    #[test]
+    fn parses_presentation_params() {
+        let params = [
+            SvcParam::from_text("mandatory", Some(b"port,alpn")).unwrap(),
+            SvcParam::from_text("port", Some(b"8443")).unwrap(),
+            SvcParam::from_text("alpn", Some(br"f\\oo\,bar,h2")).unwrap(),
+            SvcParam::from_text("key65333", None).unwrap(),
+            SvcParam::from_text("ech", Some(b"AQID")).unwrap(),
+        ];
+
+        let binding = ServiceBinding::new(1, Name::root(), params.to_vec()).unwrap();
+        assert_eq!(
+            binding.to_string(),
+            r#"1 . mandatory=alpn,port alpn="f\\\\oo\\,bar,h2" port=8443 ech=AQID key65333"#
+        );
+        assert_eq!(
+            SvcParam::from_text("key1", Some(b"h2")).unwrap(),
+            SvcParam::Alpn(vec![CharacterString::try_from("h2").unwrap()])
+        );
+
+        // The mandatory alpn is missing
+        assert!(ServiceBinding::new(1, Name::root(), params[..2].to_vec()).is_err());
+
+        for (key, value) in [
+            ("port", None),
+            ("port", Some(&b"99999"[..])),
+            ("alpn", Some(b"h2,")),
+            ("mandatory", Some(b"port,port")),
+            ("no-default-alpn", Some(b"x")),
+            ("key", None),
+            ("unknown", None),
+        ] {
+            assert!(SvcParam::from_text(key, value).is_err());
+        }
+    }
+}
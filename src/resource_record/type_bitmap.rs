@@ -0,0 +1,166 @@
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::io::Cursor;
+
+use crate::encode::Sink;
+use crate::read::CheckedBuf;
+use crate::{DnsError, RecordType};
+
+/// The set of types that exist at a name, as NSEC and NSEC3 records list them
+///
+/// On the wire the types are split into windows of 256 types each,
+/// and each window is a bitmap of up to 32 bytes (RFC 4034 4.1.2)
+#[derive(Debug, Clone, Default, Hash, PartialEq, Eq)]
+pub struct TypeBitmap(BTreeSet<u16>);
+
+impl TypeBitmap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, type_: RecordType) {
+        self.0.insert(type_.to_int());
+    }
+
+    pub fn contains(&self, type_: RecordType) -> bool {
+        self.0.contains(&type_.to_int())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The types in increasing order of their value
+    pub fn iter(&self) -> impl Iterator<Item = RecordType> + '_ {
+        self.0.iter().map(|type_| RecordType::from_int(*type_))
+    }
+
+    /// Read the windows in the next `len` bytes
+    pub(crate) fn from_bytes(len: usize, bytes: &mut Cursor<&[u8]>) -> Result<Self, DnsError> {
+        let mut types = BTreeSet::new();
+
+        let mut remaining = len;
+        let mut previous_window = None;
+        while remaining > 0 {
+            let window = bytes.read_u8()?;
+            let bitmap_len = bytes.read_u8()? as usize;
+
+            // Windows are in increasing order and have between 1 and 32 bytes
+            if previous_window.is_some_and(|previous| previous >= window)
+                || !(1..=32).contains(&bitmap_len)
+                || remaining < 2 + bitmap_len
+            {
+                return Err(DnsError::FormatError);
+            }
+            previous_window = Some(window);
+            remaining -= 2 + bitmap_len;
+
+            for (i, byte) in bytes.read_bytes(bitmap_len)?.iter().enumerate() {
+                for bit in 0..8 {
+                    if byte & (0b1000_0000 >> bit) != 0 {
+                        types.insert(((window as u16) << 8) | (i * 8 + bit) as u16);
+                    }
+                }
+            }
+        }
+
+        Ok(Self(types))
+    }
+
+    pub(crate) fn write(&self, buf: &mut impl Sink) {
+        let mut types = self.0.iter().peekable();
+        while let Some(first) = types.peek() {
+            let window = (**first >> 8) as u8;
+
+            let mut bitmap = [0u8; 32];
+            let mut bitmap_len = 0;
+            while let Some(type_) = types.next_if(|type_| (*type_ >> 8) as u8 == window) {
+                let low = (type_ & 0xFF) as usize;
+                bitmap[low / 8] |= 0b1000_0000 >> (low % 8);
+                bitmap_len = low / 8 + 1;
+            }
+
+            buf.put_u8(window);
+            buf.put_u8(bitmap_len as u8);
+            buf.put_slice(&bitmap[..bitmap_len]);
+        }
+    }
+}
+
+impl FromIterator<RecordType> for TypeBitmap {
+    fn from_iter<T: IntoIterator<Item = RecordType>>(iter: T) -> Self {
+        Self(iter.into_iter().map(|type_| type_.to_int()).collect())
+    }
+}
+
+impl Display for TypeBitmap {
+    /// The types separated by spaces, e.g. `A MX RRSIG NSEC`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, type_) in self.iter().enumerate() {
+            if i != 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{type_}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::TypeBitmap;
+    use crate::{DnsError, RecordType};
+
+    #[test]
+    fn writes_windows() {
+        // The example of RFC 4034 4.3
+        let types: TypeBitmap = [
+            RecordType::A,
+            RecordType::Mx,
+            RecordType::Rrsig,
+            RecordType::Nsec,
+            RecordType::from_int(1234),
+        ]
+        .into_iter()
+        .collect();
+
+        let mut bytes = Vec::new();
+        types.write(&mut bytes);
+
+        let mut expected = vec![0x00, 0x06, 0x40, 0x01, 0x00, 0x00, 0x00, 0x03];
+        expected.extend_from_slice(&[0x04, 0x1b, 0, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(&[0; 16]);
+        expected.extend_from_slice(&[0, 0, 0x20]);
+        assert_eq!(bytes, expected);
+
+        let parsed = TypeBitmap::from_bytes(bytes.len(), &mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(parsed, types);
+        assert!(parsed.contains(RecordType::Mx));
+        assert!(!parsed.contains(RecordType::Aaaa));
+        assert_eq!(parsed.to_string(), "A MX RRSIG NSEC TYPE1234");
+    }
+
+    #[test]
+    fn rejects_invalid_windows() {
+        let invalid: [&[u8]; 4] = [
+            // Windows out of order
+            &[0x01, 0x01, 0x80, 0x00, 0x01, 0x40],
+            // An empty bitmap
+            &[0x00, 0x00],
+            // A bitmap longer than 32 bytes
+            &[0x00, 33],
+            // A bitmap that runs past the length
+            &[0x00, 0x02, 0x40],
+        ];
+
+        for bytes in invalid {
+            assert!(matches!(
+                TypeBitmap::from_bytes(bytes.len(), &mut Cursor::new(bytes)),
+                Err(DnsError::FormatError)
+            ));
+        }
+    }
+}
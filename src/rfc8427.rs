@@ -0,0 +1,570 @@
+//! Serde support, using the JSON representation of DNS messages from RFC 8427
+//!
+//! Records have their data as `rdata<TYPE>` in presentation format, or `RDATAHEX` for types
+//! that dnrs doesn't parse. The OPT record is an ordinary member of `additionalRRs`
+
+use std::io::Cursor;
+
+use serde::de::{self, Deserializer, IgnoredAny, MapAccess, Visitor};
+use serde::ser::{SerializeMap, Serializer};
+use serde::{Deserialize, Serialize};
+
+use crate::text::{decode_hex, encode_hex};
+use crate::zone::parse_rdata_text;
+use crate::{
+    DnsClass, Edns, Flags, Header, Message, Name, Opcode, Question, Rcode, RecordData, RecordType,
+    ResourceRecord,
+};
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+struct HeaderJson {
+    id: u16,
+    qr: bool,
+    #[serde(rename = "Opcode")]
+    opcode: u8,
+    aa: bool,
+    tc: bool,
+    rd: bool,
+    ra: bool,
+    ad: bool,
+    cd: bool,
+    /// The header bits for a header, or the whole response code for a message
+    rcode: u16,
+    #[serde(default)]
+    qdcount: u16,
+    #[serde(default)]
+    ancount: u16,
+    #[serde(default)]
+    nscount: u16,
+    #[serde(default)]
+    arcount: u16,
+}
+
+impl From<&Header> for HeaderJson {
+    fn from(header: &Header) -> Self {
+        let flags = header.flags;
+        Self {
+            id: header.id,
+            qr: flags.qr(),
+            opcode: flags.opcode().to_int(),
+            aa: flags.aa(),
+            tc: flags.tc(),
+            rd: flags.rd(),
+            ra: flags.ra(),
+            ad: flags.ad(),
+            cd: flags.cd(),
+            rcode: flags.rcode() as u16,
+            qdcount: header.num_questions,
+            ancount: header.num_answers,
+            nscount: header.num_authorities,
+            arcount: header.num_additionals,
+        }
+    }
+}
+
+impl HeaderJson {
+    fn to_header<E: de::Error>(&self) -> Result<Header, E> {
+        if self.opcode > 0xF {
+            return Err(E::custom(format!("invalid Opcode {}", self.opcode)));
+        }
+
+        let mut flags = Flags::default();
+        flags.set_qr(self.qr);
+        flags.set_opcode(Opcode::from_int(self.opcode));
+        flags.set_aa(self.aa);
+        flags.set_tc(self.tc);
+        flags.set_rd(self.rd);
+        flags.set_ra(self.ra);
+        flags.set_ad(self.ad);
+        flags.set_cd(self.cd);
+        flags.set_rcode(Rcode::from_int(self.rcode).header_bits());
+
+        Ok(Header {
+            id: self.id,
+            flags,
+            num_questions: self.qdcount,
+            num_answers: self.ancount,
+            num_authorities: self.nscount,
+            num_additionals: self.arcount,
+        })
+    }
+}
+
+impl Serialize for Header {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        HeaderJson::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Header {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        HeaderJson::deserialize(deserializer)?.to_header()
+    }
+}
+
+impl Serialize for Name {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{self:#}"))
+    }
+}
+
+impl<'de> Deserialize<'de> for Name {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse()
+            .map_err(|_| de::Error::custom(format!("invalid name {name:?}")))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct QuestionJson {
+    #[serde(rename = "NAME")]
+    name: Name,
+    #[serde(rename = "TYPE")]
+    type_: u16,
+    #[serde(rename = "TYPEname", default, skip_deserializing)]
+    type_name: String,
+    #[serde(rename = "CLASS")]
+    class: u16,
+    #[serde(rename = "CLASSname", default, skip_deserializing)]
+    class_name: String,
+}
+
+impl Serialize for Question {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        QuestionJson {
+            name: self.name.clone(),
+            type_: self.type_.to_int(),
+            type_name: self.type_.to_string(),
+            class: self.class.to_int(),
+            class_name: self.class.to_string(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Question {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let question = QuestionJson::deserialize(deserializer)?;
+        Ok(Self {
+            name: question.name,
+            type_: RecordType::from_int(question.type_),
+            class: DnsClass::from_int(question.class),
+        })
+    }
+}
+
+impl Serialize for RecordData {
+    /// A map with `rdata<TYPE>` in presentation format, or `RDATAHEX` for unknown data
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        match self.record_type() {
+            Some(type_) => map.serialize_entry(&format!("rdata{type_}"), &self.to_string())?,
+            None => map.serialize_entry("RDATAHEX", &encode_hex(&self.to_bytes()))?,
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for RecordData {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(RecordDataVisitor)
+    }
+}
+
+struct RecordDataVisitor;
+
+impl<'de> Visitor<'de> for RecordDataVisitor {
+    type Value = RecordData;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a map with an rdata<TYPE> or RDATAHEX member")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut data = None;
+
+        // Other members are ignored, so this works when flattened into a record
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "RDATAHEX" {
+                let hex = map.next_value::<String>()?;
+                let bytes = decode_hex(&hex)
+                    .map_err(|_| de::Error::custom(format!("invalid RDATAHEX {hex:?}")))?;
+                data = data.or(Some(RecordData::Unknown(bytes)));
+            } else if let Some(type_) = key.strip_prefix("rdata") {
+                let Ok(type_) = type_.parse::<RecordType>() else {
+                    map.next_value::<IgnoredAny>()?;
+                    continue;
+                };
+
+                let text = map.next_value::<String>()?;
+                let parsed = parse_rdata_text(type_, &text).map_err(de::Error::custom)?;
+                // The typed form wins over RDATAHEX if there are both
+                data = Some(parsed);
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+
+        data.ok_or_else(|| de::Error::custom("missing record data"))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ResourceRecordJson {
+    #[serde(rename = "NAME")]
+    name: Name,
+    #[serde(rename = "TYPE")]
+    type_: u16,
+    #[serde(rename = "TYPEname", default, skip_deserializing)]
+    type_name: String,
+    #[serde(rename = "CLASS")]
+    class: u16,
+    #[serde(rename = "CLASSname", default, skip_deserializing)]
+    class_name: String,
+    #[serde(rename = "TTL")]
+    ttl: u32,
+    #[serde(rename = "RDLENGTH", default, skip_serializing_if = "Option::is_none")]
+    rd_length: Option<u16>,
+    #[serde(flatten)]
+    data: RecordData,
+}
+
+impl Serialize for ResourceRecord {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ResourceRecordJson {
+            name: self.name.clone(),
+            type_: self.type_.to_int(),
+            type_name: self.type_.to_string(),
+            class: self.class.to_int(),
+            class_name: self.class.to_string(),
+            ttl: self.ttl,
+            rd_length: Some(self.data.to_bytes().len() as u16),
+            data: self.data.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ResourceRecord {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let record = ResourceRecordJson::deserialize(deserializer)?;
+        let type_ = RecordType::from_int(record.type_);
+
+        let data = match record.data {
+            // Hex data of a type that dnrs parses is turned into the typed form
+            RecordData::Unknown(bytes) if type_ != RecordType::Opt => {
+                let mut cursor = Cursor::new(&bytes[..]);
+                let data = RecordData::from_bytes(type_, bytes.len() as u16, &mut cursor)
+                    .map_err(|_| de::Error::custom("invalid RDATAHEX for the type"))?;
+                if cursor.position() != bytes.len() as u64 {
+                    return Err(de::Error::custom("invalid RDATAHEX for the type"));
+                }
+                data
+            }
+            data if data
+                .record_type()
+                .is_some_and(|data_type| data_type != type_) =>
+            {
+                return Err(de::Error::custom(format!(
+                    "record data doesn't match TYPE {type_}"
+                )));
+            }
+            data => data,
+        };
+
+        if record
+            .rd_length
+            .is_some_and(|rd_length| usize::from(rd_length) != data.to_bytes().len())
+        {
+            return Err(de::Error::custom("RDLENGTH doesn't match the record data"));
+        }
+
+        Ok(Self {
+            name: record.name,
+            type_,
+            class: DnsClass::from_int(record.class),
+            ttl: record.ttl,
+            data,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct MessageJson {
+    #[serde(flatten)]
+    header: HeaderJson,
+    // A single question is flattened into the message
+    #[serde(rename = "QNAME", default, skip_serializing_if = "Option::is_none")]
+    qname: Option<Name>,
+    #[serde(rename = "QTYPE", default, skip_serializing_if = "Option::is_none")]
+    qtype: Option<u16>,
+    #[serde(
+        rename = "QTYPEname",
+        default,
+        skip_serializing_if = "Option::is_none",
+        skip_deserializing
+    )]
+    qtype_name: Option<String>,
+    #[serde(rename = "QCLASS", default, skip_serializing_if = "Option::is_none")]
+    qclass: Option<u16>,
+    #[serde(
+        rename = "QCLASSname",
+        default,
+        skip_serializing_if = "Option::is_none",
+        skip_deserializing
+    )]
+    qclass_name: Option<String>,
+    #[serde(rename = "questionRRs", default, skip_serializing_if = "Vec::is_empty")]
+    questions: Vec<Question>,
+    #[serde(rename = "answerRRs", default, skip_serializing_if = "Vec::is_empty")]
+    answers: Vec<ResourceRecord>,
+    #[serde(
+        rename = "authorityRRs",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    authorities: Vec<ResourceRecord>,
+    #[serde(
+        rename = "additionalRRs",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    additionals: Vec<ResourceRecord>,
+}
+
+impl Serialize for Message {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut header = HeaderJson::from(&self.header);
+        header.rcode = self.rcode().to_int();
+        header.qdcount = self.questions.len() as u16;
+        header.ancount = self.answers.len() as u16;
+        header.nscount = self.authorities.len() as u16;
+        header.arcount = (self.additionals.len() + self.edns.is_some() as usize) as u16;
+
+        let mut json = MessageJson {
+            header,
+            qname: None,
+            qtype: None,
+            qtype_name: None,
+            qclass: None,
+            qclass_name: None,
+            questions: Vec::new(),
+            answers: self.answers.clone(),
+            authorities: self.authorities.clone(),
+            additionals: self.additionals.clone(),
+        };
+
+        match &self.questions[..] {
+            [question] => {
+                json.qname = Some(question.name.clone());
+                json.qtype = Some(question.type_.to_int());
+                json.qtype_name = Some(question.type_.to_string());
+                json.qclass = Some(question.class.to_int());
+                json.qclass_name = Some(question.class.to_string());
+            }
+            questions => json.questions = questions.to_vec(),
+        }
+
+        if let Some(edns) = &self.edns {
+            json.additionals.push(edns.to_record());
+        }
+
+        json.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Message {
+    /// The header counts in the JSON are ignored, they're taken from the sections
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = MessageJson::deserialize(deserializer)?;
+
+        let header = json.header.to_header()?;
+        let mut message = Message::new(Header::new(header.id, header.flags));
+
+        if let Some(name) = json.qname {
+            let type_ = json
+                .qtype
+                .ok_or_else(|| de::Error::missing_field("QTYPE"))?;
+            let mut question = Question::new(name, RecordType::from_int(type_));
+            question.class = json.qclass.map_or(DnsClass::In, DnsClass::from_int);
+            message.add_question(question);
+        }
+        for question in json.questions {
+            message.add_question(question);
+        }
+
+        for record in json.answers {
+            message.add_answer(record);
+        }
+        for record in json.authorities {
+            message.add_authority(record);
+        }
+        for record in json.additionals {
+            if record.type_ != RecordType::Opt {
+                message.add_additional(record);
+                continue;
+            }
+
+            if message.edns.is_some() {
+                return Err(de::Error::custom("more than one OPT record"));
+            }
+            let edns =
+                Edns::from_record(record).map_err(|_| de::Error::custom("invalid OPT record"))?;
+            message.edns = Some(edns);
+        }
+
+        message.set_rcode(Rcode::from_int(json.header.rcode));
+
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use serde_json::json;
+
+    use crate::message::tests::example_message as ns_response;
+    use crate::{
+        DnsClass, Edns, EdnsOption, Message, Name, Networkable, Rcode, RecordData, RecordType,
+        ResourceRecord,
+    };
+
+    fn example_message() -> Message {
+        let mut message = ns_response();
+        message.header.id = 4660;
+        message.header.flags.set_qr(true);
+        message.header.flags.set_rd(true);
+        message.header.flags.set_ra(true);
+        message.add_authority(ResourceRecord {
+            name: Name::new("example.com"),
+            type_: RecordType::from_int(65280),
+            class: DnsClass::In,
+            ttl: 60,
+            data: RecordData::Unknown(vec![0xAB, 0xCD]),
+        });
+
+        let mut edns = Edns::new(1232);
+        edns.options.push(EdnsOption::Unknown {
+            code: 65001,
+            data: vec![1, 2, 3],
+        });
+        message.edns = Some(edns);
+        message.set_rcode(Rcode::BadCookie);
+        message
+    }
+
+    #[test]
+    fn serializes_rfc_8427_members() {
+        let value = serde_json::to_value(example_message()).unwrap();
+
+        assert_eq!(value["ID"], 4660);
+        assert_eq!(value["QR"], true);
+        assert_eq!(value["Opcode"], 0);
+        assert_eq!(value["RCODE"], 23);
+        assert_eq!(value["QDCOUNT"], 1);
+        assert_eq!(value["ARCOUNT"], 2);
+        assert_eq!(value["QNAME"], "example.com.");
+        assert_eq!(value["QTYPEname"], "NS");
+        assert_eq!(value["QCLASSname"], "IN");
+        assert!(value.get("questionRRs").is_none());
+
+        assert_eq!(
+            value["answerRRs"][0],
+            json!({
+                "NAME": "example.com.",
+                "TYPE": 2,
+                "TYPEname": "NS",
+                "CLASS": 1,
+                "CLASSname": "IN",
+                "TTL": 300,
+                "RDLENGTH": 17,
+                "rdataNS": "ns1.example.com.",
+            })
+        );
+        assert_eq!(value["authorityRRs"][0]["RDATAHEX"], "ABCD");
+        assert_eq!(value["additionalRRs"][0]["rdataA"], "1.2.3.4");
+        assert_eq!(value["additionalRRs"][1]["TYPE"], 41);
+        assert_eq!(value["additionalRRs"][1]["CLASS"], 1232);
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let message = example_message();
+        let json = serde_json::to_string(&message).unwrap();
+        let parsed: Message = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed.rcode(), Rcode::BadCookie);
+        assert_eq!(parsed.edns, message.edns);
+        assert_eq!(parsed.to_bytes(), message.to_bytes());
+
+        let wire = Message::from_bytes(&mut Cursor::new(&parsed.to_bytes())).unwrap();
+        assert_eq!(serde_json::to_string(&wire).unwrap(), json);
+    }
+
+    #[test]
+    fn parses_hand_written_json() {
+        let message: Message = serde_json::from_value(json!({
+            "ID": 1, "QR": false, "Opcode": 0, "AA": false, "TC": false, "RD": true,
+            "RA": false, "AD": false, "CD": false, "RCODE": 0,
+            "QNAME": "example.com.", "QTYPE": 15, "QCLASS": 1,
+            "answerRRs": [
+                {"NAME": "example.com.", "TYPE": 15, "CLASS": 1, "TTL": 60,
+                 "rdataMX": "10 mail.example.com."},
+                // Hex for a type that's parsed is decoded
+                {"NAME": "example.com.", "TYPE": 1, "CLASS": 1, "TTL": 60,
+                 "RDATAHEX": "C0000201"},
+            ],
+        }))
+        .unwrap();
+
+        assert_eq!(message.header.num_questions, 1);
+        assert_eq!(message.header.num_answers, 2);
+        assert_eq!(message.questions[0].type_, RecordType::Mx);
+        assert_eq!(
+            message.answers[0].data,
+            RecordData::Mx {
+                preference: 10,
+                exchange: Name::new("mail.example.com"),
+            }
+        );
+        assert_eq!(
+            message.answers[1].data,
+            RecordData::A([192, 0, 2, 1].into())
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_json() {
+        let header = json!({
+            "ID": 1, "QR": false, "Opcode": 0, "AA": false, "TC": false, "RD": true,
+            "RA": false, "AD": false, "CD": false, "RCODE": 0,
+        });
+
+        let mut opcode = header.clone();
+        opcode["Opcode"] = json!(16);
+        assert!(serde_json::from_value::<Message>(opcode).is_err());
+
+        let mut mismatched = header.clone();
+        mismatched["answerRRs"] = json!([
+            {"NAME": "example.com.", "TYPE": 1, "CLASS": 1, "TTL": 60, "rdataNS": "ns.example."}
+        ]);
+        assert!(serde_json::from_value::<Message>(mismatched).is_err());
+
+        let mut bad_length = header.clone();
+        bad_length["answerRRs"] = json!([
+            {"NAME": "example.com.", "TYPE": 1, "CLASS": 1, "TTL": 60, "RDLENGTH": 5,
+             "rdataA": "192.0.2.1"}
+        ]);
+        assert!(serde_json::from_value::<Message>(bad_length).is_err());
+
+        let mut bad_hex = header;
+        bad_hex["answerRRs"] = json!([
+            {"NAME": "example.com.", "TYPE": 1, "CLASS": 1, "TTL": 60, "RDATAHEX": "C00002"}
+        ]);
+        assert!(serde_json::from_value::<Message>(bad_hex).is_err());
+    }
+}
@@ -0,0 +1,323 @@
+//! Helpers for the text (presentation) format of records
+
+use std::fmt::Write;
+
+use crate::DnsError;
+
+pub(crate) fn encode_hex(bytes: &[u8]) -> String {
+    let mut ret = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(ret, "{byte:02X}");
+    }
+
+    ret
+}
+
+/// Decode hex digits, whitespace between them is ignored
+pub(crate) fn decode_hex(s: &str) -> Result<Vec<u8>, DnsError> {
+    let digits = s
+        .bytes()
+        .filter(|b| !b.is_ascii_whitespace())
+        .map(|b| (b as char).to_digit(16).map(|d| d as u8))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or(DnsError::FormatError)?;
+
+    if digits.len() % 2 != 0 {
+        return Err(DnsError::FormatError);
+    }
+
+    Ok(digits
+        .chunks(2)
+        .map(|pair| (pair[0] << 4) | pair[1])
+        .collect())
+}
+
+/// Resolve the `\X` and `\DDD` escapes of a character-string (RFC 1035 5.1)
+pub(crate) fn decode_escapes(s: &str) -> Result<Vec<u8>, DnsError> {
+    let mut ret = Vec::with_capacity(s.len());
+
+    let mut bytes = s.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte != b'\\' {
+            ret.push(byte);
+            continue;
+        }
+
+        match bytes.next() {
+            Some(digit @ b'0'..=b'9') => {
+                let mut value = (digit - b'0') as u16;
+                for _ in 0..2 {
+                    match bytes.next() {
+                        Some(digit @ b'0'..=b'9') => value = value * 10 + (digit - b'0') as u16,
+                        _ => return Err(DnsError::FormatError),
+                    }
+                }
+
+                ret.push(u8::try_from(value).or(Err(DnsError::FormatError))?);
+            }
+            Some(escaped) => ret.push(escaped),
+            None => return Err(DnsError::FormatError),
+        }
+    }
+
+    Ok(ret)
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encode bytes as base64 with padding (RFC 4648 4)
+pub(crate) fn encode_base64(bytes: &[u8]) -> String {
+    let mut ret = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, byte)| {
+            group | (*byte as u32) << (16 - 8 * i)
+        });
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (group >> (18 - 6 * i)) & 0b11_1111;
+                ret.push(BASE64_ALPHABET[index as usize] as char);
+            } else {
+                ret.push('=');
+            }
+        }
+    }
+
+    ret
+}
+
+/// Decode base64 with padding, whitespace between the digits is ignored
+pub(crate) fn decode_base64(s: &str) -> Result<Vec<u8>, DnsError> {
+    let digits: Vec<u8> = s.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if !digits.len().is_multiple_of(4) {
+        return Err(DnsError::FormatError);
+    }
+
+    let mut ret = Vec::with_capacity(digits.len() / 4 * 3);
+    for (i, chunk) in digits.chunks(4).enumerate() {
+        let padding = chunk.iter().rev().take_while(|b| **b == b'=').count();
+        if padding > 2 || (padding > 0 && i != digits.len() / 4 - 1) {
+            return Err(DnsError::FormatError);
+        }
+
+        let mut group = 0u32;
+        for digit in &chunk[..4 - padding] {
+            let value = BASE64_ALPHABET
+                .iter()
+                .position(|b| b == digit)
+                .ok_or(DnsError::FormatError)?;
+            group = (group << 6) | value as u32;
+        }
+        group <<= 6 * padding;
+
+        ret.extend_from_slice(&group.to_be_bytes()[1..4 - padding]);
+    }
+
+    Ok(ret)
+}
+
+const BASE32HEX_ALPHABET: &[u8; 32] = b"0123456789abcdefghijklmnopqrstuv";
+
+/// Encode bytes as lowercase base32hex without padding, like NSEC3 hashes (RFC 5155 3.3)
+pub(crate) fn encode_base32hex(bytes: &[u8]) -> String {
+    let mut ret = String::with_capacity((bytes.len() * 8).div_ceil(5));
+
+    let mut bits = 0u16;
+    let mut bit_count = 0;
+    for byte in bytes {
+        bits = (bits << 8) | *byte as u16;
+        bit_count += 8;
+        while bit_count >= 5 {
+            bit_count -= 5;
+            ret.push(BASE32HEX_ALPHABET[((bits >> bit_count) & 0b1_1111) as usize] as char);
+        }
+    }
+
+    if bit_count > 0 {
+        ret.push(BASE32HEX_ALPHABET[((bits << (5 - bit_count)) & 0b1_1111) as usize] as char);
+    }
+
+    ret
+}
+
+/// Decode base32hex without padding, ignoring case
+pub(crate) fn decode_base32hex(s: &str) -> Result<Vec<u8>, DnsError> {
+    let mut ret = Vec::with_capacity(s.len() * 5 / 8);
+
+    let mut bits = 0u16;
+    let mut bit_count = 0;
+    for digit in s.bytes() {
+        let value = BASE32HEX_ALPHABET
+            .iter()
+            .position(|b| *b == digit.to_ascii_lowercase())
+            .ok_or(DnsError::FormatError)?;
+
+        bits = (bits << 5) | value as u16;
+        bit_count += 5;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            ret.push((bits >> bit_count) as u8);
+        }
+    }
+
+    // Whatever is left over is padding that has to be less than a digit
+    if bit_count >= 5 {
+        return Err(DnsError::FormatError);
+    }
+
+    Ok(ret)
+}
+
+/// Format seconds since the epoch as `YYYYMMDDHHmmSS` in UTC,
+/// the form of RRSIG inception and expiration times (RFC 4034 3.2)
+pub(crate) fn format_timestamp(seconds: u32) -> String {
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    let time = seconds % 86400;
+
+    format!(
+        "{year:04}{month:02}{day:02}{:02}{:02}{:02}",
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// Parse a `YYYYMMDDHHmmSS` timestamp, or a number of seconds since the epoch
+pub(crate) fn parse_timestamp(s: &str) -> Result<u32, DnsError> {
+    if !s.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(DnsError::FormatError);
+    }
+
+    if s.len() != 14 {
+        return s.parse().map_err(|_| DnsError::FormatError);
+    }
+
+    let field = |range: std::ops::Range<usize>| s[range].parse::<i64>().unwrap();
+    let (year, month, day) = (field(0..4), field(4..6), field(6..8));
+    let (hour, minute, second) = (field(8..10), field(10..12), field(12..14));
+
+    // Days that don't exist, like February 30, come back as another date
+    let days = days_from_civil(year, month, day);
+    if !(1..=12).contains(&month)
+        || civil_from_days(days) != (year, month, day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return Err(DnsError::FormatError);
+    }
+
+    let seconds = days * 86400 + hour * 3600 + minute * 60 + second;
+    u32::try_from(seconds).map_err(|_| DnsError::FormatError)
+}
+
+/// The date of a day counted from 1970-01-01
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+
+    // Months are counted from March, so that February's leap day comes last
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+
+    (year_of_era + era * 400 + (month <= 2) as i64, month, day)
+}
+
+/// The number of days from 1970-01-01 to a date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode_base32hex, decode_base64, encode_base32hex, encode_base64, format_timestamp,
+        parse_timestamp,
+    };
+
+    #[test]
+    fn converts_base64() {
+        // The test vectors of RFC 4648 10
+        let vectors = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+
+        for (bytes, text) in vectors {
+            assert_eq!(encode_base64(bytes.as_bytes()), text);
+            assert_eq!(decode_base64(text).unwrap(), bytes.as_bytes());
+        }
+
+        assert_eq!(decode_base64("Zm9v\n YmFy").unwrap(), b"foobar");
+        for invalid in ["Zm9", "Zg=a", "Z===", "Zg==Zm8=", "Zm9*"] {
+            assert!(decode_base64(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn converts_base32hex() {
+        // The test vectors of RFC 4648 10, without padding
+        let vectors = [
+            ("", ""),
+            ("f", "co"),
+            ("fo", "cpng"),
+            ("foo", "cpnmu"),
+            ("foob", "cpnmuog"),
+            ("fooba", "cpnmuoj1"),
+            ("foobar", "cpnmuoj1e8"),
+        ];
+
+        for (bytes, text) in vectors {
+            assert_eq!(encode_base32hex(bytes.as_bytes()), text);
+            assert_eq!(decode_base32hex(text).unwrap(), bytes.as_bytes());
+        }
+
+        assert_eq!(decode_base32hex("CPNMU").unwrap(), b"foo");
+        for invalid in ["c", "cpn", "cpnmuo", "w0", "c="] {
+            assert!(decode_base32hex(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn converts_timestamps() {
+        assert_eq!(format_timestamp(0), "19700101000000");
+        assert_eq!(parse_timestamp("19700101000000").unwrap(), 0);
+
+        // The expiration of the example RRSIG in RFC 4034 3.3
+        assert_eq!(parse_timestamp("20030322173103").unwrap(), 1048354263);
+        assert_eq!(format_timestamp(1048354263), "20030322173103");
+
+        assert_eq!(parse_timestamp("20240229120000").unwrap(), 1709208000);
+        assert_eq!(format_timestamp(u32::MAX), "21060207062815");
+        assert_eq!(parse_timestamp("1048354263").unwrap(), 1048354263);
+
+        for invalid in [
+            "20230229000000",
+            "20231301000000",
+            "20230101240000",
+            "21060207062816",
+            "2023-01-01",
+            "",
+        ] {
+            assert!(parse_timestamp(invalid).is_err());
+        }
+    }
+}
@@ -0,0 +1,24 @@
+use std::fmt::Display;
+
+/// A value that an enum like `DnsClass` doesn't have a variant for, which it keeps as `Unknown`.
+///
+/// Only the enums themselves make these, from values that none of their variants have,
+/// so a known value can't end up as `Unknown`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct UnknownValue<T>(T);
+
+impl<T: Copy> UnknownValue<T> {
+    pub(crate) fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    pub fn get(&self) -> T {
+        self.0
+    }
+}
+
+impl<T: Display> Display for UnknownValue<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
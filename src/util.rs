@@ -1,8 +1,29 @@
-use dnrs::Flags;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-pub fn set_response_flags(mut flags: Flags) -> Flags {
-    flags.set_qr(true);
-    flags.set_aa(false);
-    flags.set_ra(true);
-    flags
+use dnrs::{Edns, Header, Message, MessageBuilder, Rcode};
+
+/// A response to the message with `header` that has no records, only a response code
+pub fn error_response(header: &Header, rcode: Rcode, edns: Option<Edns>) -> Message {
+    MessageBuilder::response_to_header(header)
+        .rcode(rcode)
+        .edns(edns)
+        .build()
+}
+
+/// The OPT record to respond with, only clients that sent one get one back (RFC 6891 7)
+pub fn response_edns(request: Option<&Edns>, udp_payload_size: u16) -> Option<Edns> {
+    let request = request?;
+
+    let mut edns = Edns::new(udp_payload_size);
+    // The DO bit is copied from the query (RFC 3225 3)
+    edns.flags.set_dnssec_ok(request.flags.dnssec_ok());
+
+    Some(edns)
+}
+
+/// Seconds since the epoch, modulo 2^32 like the times in cookies and signatures
+pub fn unix_time() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs() as u32)
 }
@@ -0,0 +1,550 @@
+//! Reading records from zone (master) files (RFC 1035 5)
+
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{DnsClass, Name, RecordData, RecordType, ResourceRecord};
+
+mod lexer;
+use lexer::{Entry, Token};
+
+mod rdata;
+use rdata::parse_rdata;
+
+/// How deeply `$INCLUDE`s can nest, so a file that includes itself fails instead of looping
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// An error in a zone file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZoneError {
+    /// The file the error is in, `None` for the text given to `parse_zone`
+    pub file: Option<PathBuf>,
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for ZoneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:{}: {}", file.display(), self.line, self.message),
+            None => write!(f, "line {}: {}", self.line, self.message),
+        }
+    }
+}
+
+impl std::error::Error for ZoneError {}
+
+/// Parse the records in the text of a zone file, names without a trailing dot are relative to `origin`
+///
+/// Files in `$INCLUDE` directives are relative to the working directory
+pub fn parse_zone(input: &str, origin: &Name) -> Result<Vec<ResourceRecord>, ZoneError> {
+    let mut parser = Parser::new(origin.clone());
+    parser.parse(input, None, 0)?;
+
+    Ok(parser.records)
+}
+
+/// Read the records of a zone file, files in `$INCLUDE` directives are relative to its directory
+pub fn read_zone_file(
+    path: impl AsRef<Path>,
+    origin: &Name,
+) -> Result<Vec<ResourceRecord>, ZoneError> {
+    let path = path.as_ref();
+    let input = fs::read_to_string(path).map_err(|e| ZoneError {
+        file: Some(path.to_path_buf()),
+        line: 0,
+        message: e.to_string(),
+    })?;
+
+    let mut parser = Parser::new(origin.clone());
+    parser.parse(&input, Some(path), 0)?;
+
+    Ok(parser.records)
+}
+
+struct Parser {
+    origin: Name,
+    /// Set by `$TTL`, used for records without a TTL (RFC 2308 4)
+    default_ttl: Option<u32>,
+    /// Omitted owners, TTLs and classes are the ones of the previous record
+    last_owner: Option<Name>,
+    last_ttl: Option<u32>,
+    last_class: DnsClass,
+    records: Vec<ResourceRecord>,
+}
+
+impl Parser {
+    fn new(origin: Name) -> Self {
+        Self {
+            origin,
+            default_ttl: None,
+            last_owner: None,
+            last_ttl: None,
+            last_class: DnsClass::In,
+            records: Vec::new(),
+        }
+    }
+
+    fn parse(&mut self, input: &str, file: Option<&Path>, depth: usize) -> Result<(), ZoneError> {
+        let error = |line, message| ZoneError {
+            file: file.map(Path::to_path_buf),
+            line,
+            message,
+        };
+
+        let entries = lexer::entries(input).map_err(|(line, message)| error(line, message))?;
+        for entry in entries.iter() {
+            let first = &entry.tokens[0];
+            if !entry.blank_owner && !first.quoted && first.text.starts_with('$') {
+                self.directive(entry, file, depth)?;
+            } else {
+                self.record(entry)
+                    .map_err(|message| error(entry.line, message))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn directive(
+        &mut self,
+        entry: &Entry,
+        file: Option<&Path>,
+        depth: usize,
+    ) -> Result<(), ZoneError> {
+        let error = |message| ZoneError {
+            file: file.map(Path::to_path_buf),
+            line: entry.line,
+            message,
+        };
+
+        let (directive, args) = entry.tokens.split_first().unwrap();
+        match directive.text.to_ascii_uppercase().as_str() {
+            "$ORIGIN" => {
+                let [origin] = args else {
+                    return Err(error("$ORIGIN takes a name".to_owned()));
+                };
+                self.origin = parse_name(origin, &self.origin).map_err(error)?;
+            }
+            "$TTL" => {
+                let [ttl] = args else {
+                    return Err(error("$TTL takes a TTL".to_owned()));
+                };
+                let ttl = parse_ttl(&ttl.text)
+                    .ok_or_else(|| error(format!("invalid TTL {:?}", ttl.text)))?;
+                self.default_ttl = Some(ttl);
+            }
+            "$INCLUDE" => {
+                let (path, origin) = match args {
+                    [path] => (path, None),
+                    [path, origin] => {
+                        (path, Some(parse_name(origin, &self.origin).map_err(error)?))
+                    }
+                    _ => {
+                        return Err(error(
+                            "$INCLUDE takes a file name and an optional origin".to_owned(),
+                        ))
+                    }
+                };
+
+                if depth >= MAX_INCLUDE_DEPTH {
+                    return Err(error("$INCLUDEs are nested too deeply".to_owned()));
+                }
+
+                let path = match file.and_then(Path::parent) {
+                    Some(directory) => directory.join(&path.text),
+                    None => PathBuf::from(&path.text),
+                };
+                let input = fs::read_to_string(&path)
+                    .map_err(|e| error(format!("couldn't read {}: {e}", path.display())))?;
+
+                // The included file can't change the origin of the including one (RFC 1035 5.1)
+                let origin = origin.unwrap_or_else(|| self.origin.clone());
+                let parent_origin = std::mem::replace(&mut self.origin, origin);
+                self.parse(&input, Some(&path), depth + 1)?;
+                self.origin = parent_origin;
+            }
+            other => return Err(error(format!("unsupported directive {other}"))),
+        }
+
+        Ok(())
+    }
+
+    fn record(&mut self, entry: &Entry) -> Result<(), String> {
+        let mut rest = &entry.tokens[..];
+
+        let owner = if entry.blank_owner {
+            self.last_owner
+                .clone()
+                .ok_or("the first record needs an owner")?
+        } else {
+            let (owner, tokens) = rest.split_first().unwrap();
+            rest = tokens;
+            parse_name(owner, &self.origin)?
+        };
+
+        // The TTL and class are both optional and can be in either order
+        let mut ttl = None;
+        let mut class = None;
+        while let Some((token, tokens)) = rest.split_first() {
+            if token.quoted {
+                break;
+            } else if let (None, Some(value)) = (ttl, parse_ttl(&token.text)) {
+                ttl = Some(value);
+            } else if let (None, Ok(value)) = (class, token.text.parse::<DnsClass>()) {
+                class = Some(value);
+            } else {
+                break;
+            }
+            rest = tokens;
+        }
+
+        let (type_, rdata) = rest.split_first().ok_or("missing type")?;
+        let type_ = type_
+            .text
+            .parse::<RecordType>()
+            .map_err(|_| format!("unknown type {:?}", type_.text))?;
+        let data = parse_rdata(type_, rdata, &self.origin)?;
+
+        let class = class.unwrap_or(self.last_class);
+        let ttl = match (ttl.or(self.default_ttl).or(self.last_ttl), &data) {
+            (Some(ttl), _) => ttl,
+            // Zones without $TTL used the SOA minimum as the default
+            (None, RecordData::Soa { minimum, .. }) => *minimum,
+            (None, _) => return Err("missing TTL, and there's no $TTL".to_owned()),
+        };
+
+        self.last_owner = Some(owner.clone());
+        self.last_ttl = Some(ttl);
+        self.last_class = class;
+
+        self.records.push(ResourceRecord {
+            name: owner,
+            type_,
+            class,
+            ttl,
+            data,
+        });
+
+        Ok(())
+    }
+}
+
+/// Parse the presentation format of record data on its own, names in it have to be absolute
+#[cfg(feature = "serde")]
+pub(crate) fn parse_rdata_text(type_: RecordType, text: &str) -> Result<RecordData, String> {
+    let tokens: Vec<Token> = lexer::entries(text)
+        .map_err(|(_, message)| message)?
+        .into_iter()
+        .flat_map(|entry| entry.tokens)
+        .collect();
+
+    parse_rdata(type_, &tokens, &Name::root())
+}
+
+/// Parse a domain name field, names without a trailing dot are relative to `origin`
+fn parse_name(token: &Token, origin: &Name) -> Result<Name, String> {
+    let text = &token.text;
+    if text == "@" {
+        return Ok(origin.clone());
+    }
+
+    let invalid = || format!("invalid name {text:?}");
+    let name = text.parse::<Name>().map_err(|_| invalid())?;
+    if is_absolute(text) {
+        return Ok(name);
+    }
+
+    let labels = name
+        .labels()
+        .iter()
+        .chain(origin.labels())
+        .cloned()
+        .collect();
+    Name::from_labels(labels).map_err(|_| invalid())
+}
+
+/// Whether a name ends with a dot that isn't escaped, `a\.` is relative
+fn is_absolute(name: &str) -> bool {
+    let Some(rest) = name.strip_suffix('.') else {
+        return false;
+    };
+
+    rest.bytes().rev().take_while(|b| *b == b'\\').count() % 2 == 0
+}
+
+/// Parse a TTL in seconds, or with units like `1h30m` as BIND allows
+fn parse_ttl(text: &str) -> Option<u32> {
+    if text.is_empty() {
+        return None;
+    }
+
+    let mut total: u32 = 0;
+    let mut value: Option<u32> = None;
+    for c in text.chars() {
+        if let Some(digit) = c.to_digit(10) {
+            value = Some(value.unwrap_or(0).checked_mul(10)?.checked_add(digit)?);
+            continue;
+        }
+
+        let unit = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return None,
+        };
+        total = total.checked_add(value.take()?.checked_mul(unit)?)?;
+    }
+
+    total.checked_add(value.unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{parse_ttl, parse_zone, read_zone_file};
+    use crate::{
+        CharacterString, DnsClass, Name, RecordData, RecordType, ResourceRecord, ZoneError,
+    };
+
+    const ZONE: &str = r#"
+$ORIGIN example.com.
+$TTL 1h
+@       IN  SOA ns1 hostmaster (
+                2024010101 ; serial
+                2h         ; refresh
+                1h         ; retry
+                2w         ; expire
+                300 )      ; minimum
+        IN  NS  ns1
+        IN  NS  ns2.example.net.
+        IN  MX  10 mail
+ns1     600 A   192.0.2.1
+        IN 300 AAAA 2001:db8::1
+www         CNAME   @
+txt         TXT "v=spf1 -all; not a comment" "quote \" \059" unquoted
+any         TYPE65280 \# 3 ABCDEF
+_sip._udp   SRV 10 60 5060 sip
+1.2.0.192.in-addr.arpa. PTR ns1
+@           CAA 128 issue "ca.example.net; account=\"1\""
+@           NAPTR 100 10 "S" "SIP+D2U" "" _sip._udp
+@           HTTPS 1 . alpn="h3,h2" ipv4hint=192.0.2.1 ( port=8443
+                      mandatory=port )
+_dns        SVCB 0 dns.example.net.
+$ORIGIN sub.example.com.
+host    A   192.0.2.2
+"#;
+
+    fn name(name: &str) -> Name {
+        Name::new(name)
+    }
+
+    #[test]
+    fn parses_zones() {
+        let records = parse_zone(ZONE, &Name::root()).unwrap();
+        let summary: Vec<_> = records.iter().map(ResourceRecord::to_string).collect();
+        assert_eq!(
+            summary,
+            [
+                "example.com. 3600 IN SOA ns1.example.com. hostmaster.example.com. 2024010101 7200 3600 1209600 300",
+                "example.com. 3600 IN NS ns1.example.com.",
+                "example.com. 3600 IN NS ns2.example.net.",
+                "example.com. 3600 IN MX 10 mail.example.com.",
+                "ns1.example.com. 600 IN A 192.0.2.1",
+                "ns1.example.com. 300 IN AAAA 2001:db8::1",
+                "www.example.com. 3600 IN CNAME example.com.",
+                r#"txt.example.com. 3600 IN TXT "v=spf1 -all; not a comment" "quote \" ;" "unquoted""#,
+                r"any.example.com. 3600 IN TYPE65280 \# 3 ABCDEF",
+                "_sip._udp.example.com. 3600 IN SRV 10 60 5060 sip.example.com.",
+                "1.2.0.192.in-addr.arpa. 3600 IN PTR ns1.example.com.",
+                r#"example.com. 3600 IN CAA 128 issue "ca.example.net; account=\"1\"""#,
+                r#"example.com. 3600 IN NAPTR 100 10 "S" "SIP+D2U" "" _sip._udp.example.com."#,
+                r#"example.com. 3600 IN HTTPS 1 . mandatory=port alpn="h3,h2" port=8443 ipv4hint=192.0.2.1"#,
+                "_dns.example.com. 3600 IN SVCB 0 dns.example.net.",
+                "host.sub.example.com. 3600 IN A 192.0.2.2",
+            ]
+        );
+
+        assert_eq!(
+            records[7].data,
+            RecordData::Txt(vec![
+                CharacterString::try_from("v=spf1 -all; not a comment").unwrap(),
+                CharacterString::try_from("quote \" ;").unwrap(),
+                CharacterString::try_from("unquoted").unwrap(),
+            ])
+        );
+    }
+
+    #[test]
+    fn parses_dnssec_records() {
+        // The examples of RFC 4034 and RFC 5155
+        let zone = r#"
+$TTL 86400
+dskey.example.com. DNSKEY 256 3 5 ( AQOeiiR0GOMYkDshWoSKz9Xz
+                                    fwJr1AYtsmx3TGkJaNXVbfi/
+                                    2pHm822aJ5iI9BMzNXxeYCmZ
+                                    DRD99WYwYqUSdjMmmAphXdvx
+                                    egXd/M5+X7OrzKBaMbCVdFLU
+                                    Uh6DhweJBjEVv5f2wwjM9Xzc
+                                    nOf+EPbtG9DMBmADjFDc2w/r
+                                    ljwvFw==
+                                    ) ;  key id = 60485
+dskey.example.com. DS 60485 5 1 ( 2BB183AF5F22588179A53B0A9
+                                  8631FAD1A292118 )
+host.example.com. RRSIG A 5 3 86400 20030322173103 (
+                                  20030220173103 2642 example.com.
+                                  oJB1W6WNGv+ldvQ3WDG0MQkg5IEhjRip8WTr
+                                  PYGv07h108dUKGMeDPKijVCHX3DDKdfb+v6o
+                                  B9wfuh3DTJXUAfI/M0zmO/zz8bW0Rznl8O3t
+                                  GNazPwQKkRN20XPXV6nwwfoXmJQbsLNrLfkG
+                                  J5D6fwFm8nN+6pBzeDQfsS3Ap3o= )
+alfa.example.com. NSEC host.example.com. ( A MX RRSIG NSEC TYPE1234 )
+0p9mhaveqvm6t7vbl5lop2u3t2rp3tom.example. NSEC3 1 1 12 aabbccdd (
+                       2t7b4g4vsa5smi47k61mv5bv1a22bojr MX DNSKEY NS
+                       SOA NSEC3PARAM RRSIG )
+example. NSEC3PARAM 1 0 12 aabbccdd
+example. NSEC3PARAM 1 0 0 -
+"#;
+
+        let records = parse_zone(zone, &Name::root()).unwrap();
+        let summary: Vec<_> = records.iter().map(ResourceRecord::to_string).collect();
+        assert_eq!(
+            summary,
+            [
+                "dskey.example.com. 86400 IN DNSKEY 256 3 5 AQOeiiR0GOMYkDshWoSKz9XzfwJr1AYtsmx3TGkJaNXVbfi/2pHm822aJ5iI9BMzNXxeYCmZDRD99WYwYqUSdjMmmAphXdvxegXd/M5+X7OrzKBaMbCVdFLUUh6DhweJBjEVv5f2wwjM9XzcnOf+EPbtG9DMBmADjFDc2w/rljwvFw==",
+                "dskey.example.com. 86400 IN DS 60485 5 1 2BB183AF5F22588179A53B0A98631FAD1A292118",
+                "host.example.com. 86400 IN RRSIG A 5 3 86400 20030322173103 20030220173103 2642 example.com. oJB1W6WNGv+ldvQ3WDG0MQkg5IEhjRip8WTrPYGv07h108dUKGMeDPKijVCHX3DDKdfb+v6oB9wfuh3DTJXUAfI/M0zmO/zz8bW0Rznl8O3tGNazPwQKkRN20XPXV6nwwfoXmJQbsLNrLfkGJ5D6fwFm8nN+6pBzeDQfsS3Ap3o=",
+                "alfa.example.com. 86400 IN NSEC host.example.com. A MX RRSIG NSEC TYPE1234",
+                "0p9mhaveqvm6t7vbl5lop2u3t2rp3tom.example. 86400 IN NSEC3 1 1 12 AABBCCDD 2t7b4g4vsa5smi47k61mv5bv1a22bojr NS SOA MX RRSIG DNSKEY NSEC3PARAM",
+                "example. 86400 IN NSEC3PARAM 1 0 12 AABBCCDD",
+                "example. 86400 IN NSEC3PARAM 1 0 0 -",
+            ]
+        );
+
+        assert_eq!(records[0].data.key_tag(), Some(60485));
+        assert_eq!(records[1].data.key_tag(), None);
+        let RecordData::Rrsig { expiration, .. } = records[2].data else {
+            panic!("not an RRSIG");
+        };
+        assert_eq!(expiration, 1048354263);
+
+        let text: String = records.iter().map(|rr| format!("{rr}\n")).collect();
+        assert_eq!(parse_zone(&text, &Name::root()).unwrap(), records);
+    }
+
+    #[test]
+    fn reads_its_own_output() {
+        let records = parse_zone(ZONE, &Name::root()).unwrap();
+        let text: String = records.iter().map(|rr| format!("{rr}\n")).collect();
+
+        let reparsed = parse_zone(&text, &name("elsewhere.org")).unwrap();
+        assert_eq!(reparsed, records);
+        for (a, b) in reparsed.iter().zip(records.iter()) {
+            assert_eq!(a.ttl, b.ttl);
+        }
+    }
+
+    #[test]
+    fn inherits_ttl_and_class() {
+        let records = parse_zone(
+            "a 100 CH TXT one\n  TXT two\nb IN A 192.0.2.1\n",
+            &name("example.com"),
+        )
+        .unwrap();
+
+        assert_eq!(records[1].name, name("a.example.com"));
+        assert_eq!((records[1].ttl, records[1].class), (100, DnsClass::Ch));
+        assert_eq!((records[2].ttl, records[2].class), (100, DnsClass::In));
+
+        // Without $TTL the SOA minimum is used
+        let records = parse_zone("@ SOA ns1 host 1 2 3 4 5\n", &name("example.com")).unwrap();
+        assert_eq!(records[0].ttl, 5);
+    }
+
+    #[test]
+    fn reports_line_numbers() {
+        let error = |input: &str| parse_zone(input, &name("example.com")).unwrap_err();
+
+        let e = error("$TTL 60\na A 192.0.2.1\n\nb BOGUS 1\n");
+        assert_eq!(e.line, 4);
+        assert_eq!(e.message, "unknown type \"BOGUS\"");
+        assert_eq!(e.to_string(), "line 4: unknown type \"BOGUS\"");
+
+        assert_eq!(error("$TTL 60\na A 192.0.2.256\n").line, 2);
+        assert_eq!(error("$TTL 60\na MX 10\n").message, "missing exchange");
+        assert_eq!(error("$TTL 60\na A 192.0.2.1 extra\n").line, 2);
+        assert_eq!(error("  A 192.0.2.1\n").line, 1);
+        assert_eq!(
+            error("a A 192.0.2.1\n").message,
+            "missing TTL, and there's no $TTL"
+        );
+        assert_eq!(error("$TTL 60\n\na SOA ns1 host (\n1 2 3 4 5\n").line, 3);
+        assert_eq!(error("$TTL 60\na TXT \"open\n").line, 2);
+        assert_eq!(error("$TTL 60\n$GENERATE 1-2 a A 1.2.3.$\n").line, 2);
+        assert!(error("$TTL 60\nx HINFO a b\n").message.contains("generic"));
+        assert_eq!(
+            error("$TTL 60\nx HTTPS 1 . port=1 port=2\n").message,
+            "SvcParams repeat a key or miss a mandatory one"
+        );
+        assert_eq!(
+            error("$TTL 60\nx SVCB 1 . alpn\n").message,
+            "invalid SvcParam \"alpn\""
+        );
+    }
+
+    #[test]
+    fn parses_ttls() {
+        assert_eq!(parse_ttl("300"), Some(300));
+        assert_eq!(parse_ttl("1h30m"), Some(5400));
+        assert_eq!(parse_ttl("1W"), Some(604800));
+        assert_eq!(parse_ttl("1d1"), Some(86401));
+        assert_eq!(parse_ttl(""), None);
+        assert_eq!(parse_ttl("h"), None);
+        assert_eq!(parse_ttl("IN"), None);
+        assert_eq!(parse_ttl("99999999999"), None);
+    }
+
+    #[test]
+    fn includes_files() {
+        let directory = std::env::temp_dir().join(format!("dnrs-zone-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        fs::write(
+            directory.join("main.zone"),
+            "$TTL 60\n$INCLUDE hosts.zone hosts.example.com.\nafter A 192.0.2.3\n$INCLUDE loop.zone\n",
+        )
+        .unwrap();
+        fs::write(
+            directory.join("hosts.zone"),
+            "a A 192.0.2.1\nb A 192.0.2.2\n",
+        )
+        .unwrap();
+        fs::write(directory.join("loop.zone"), "$INCLUDE loop.zone\n").unwrap();
+
+        let error = read_zone_file(directory.join("main.zone"), &name("example.com")).unwrap_err();
+        assert_eq!(error.file, Some(directory.join("loop.zone")));
+        assert_eq!(error.message, "$INCLUDEs are nested too deeply");
+
+        fs::write(directory.join("loop.zone"), "").unwrap();
+        let records = read_zone_file(directory.join("main.zone"), &name("example.com")).unwrap();
+        let names: Vec<_> = records.iter().map(|rr| rr.name.to_string()).collect();
+        assert_eq!(
+            names,
+            [
+                "a.hosts.example.com",
+                "b.hosts.example.com",
+                "after.example.com"
+            ]
+        );
+        assert!(records.iter().all(|rr| rr.type_ == RecordType::A));
+
+        let missing = read_zone_file(directory.join("missing.zone"), &Name::root());
+        assert!(matches!(missing, Err(ZoneError { line: 0, .. })));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,128 @@
+/// A field of a zone file entry, with its escapes still in it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Token {
+    pub text: String,
+    /// Whether the field was a "quoted string"
+    pub quoted: bool,
+}
+
+/// One record or directive, which can span lines inside parentheses
+#[derive(Debug)]
+pub(super) struct Entry {
+    /// The line the entry starts on
+    pub line: usize,
+    /// Entries that start with whitespace have the owner of the previous record
+    pub blank_owner: bool,
+    pub tokens: Vec<Token>,
+}
+
+/// Split a zone file into entries, dropping comments and joining parenthesised lines
+pub(super) fn entries(input: &str) -> Result<Vec<Entry>, (usize, String)> {
+    let mut entries = Vec::new();
+
+    let mut line = 1;
+    let mut entry = Entry {
+        line,
+        blank_owner: false,
+        tokens: Vec::new(),
+    };
+    let mut token = String::new();
+    let mut depth = 0;
+    let mut line_start = true;
+
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        if line_start && depth == 0 {
+            entry.line = line;
+            entry.blank_owner = c == ' ' || c == '\t';
+        }
+        line_start = false;
+
+        match c {
+            '\n' => {
+                end_token(&mut token, &mut entry);
+                line += 1;
+                line_start = true;
+
+                if depth == 0 && !entry.tokens.is_empty() {
+                    entries.push(std::mem::replace(
+                        &mut entry,
+                        Entry {
+                            line,
+                            blank_owner: false,
+                            tokens: Vec::new(),
+                        },
+                    ));
+                }
+            }
+            ' ' | '\t' | '\r' => end_token(&mut token, &mut entry),
+            ';' => {
+                end_token(&mut token, &mut entry);
+                while chars.next_if(|c| *c != '\n').is_some() {}
+            }
+            '(' => {
+                end_token(&mut token, &mut entry);
+                depth += 1;
+            }
+            ')' => {
+                end_token(&mut token, &mut entry);
+                if depth == 0 {
+                    return Err((line, "unbalanced )".to_owned()));
+                }
+                depth -= 1;
+            }
+            '"' => {
+                end_token(&mut token, &mut entry);
+
+                let start = line;
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => {
+                            text.push('\\');
+                            if let Some(escaped) = chars.next() {
+                                line += (escaped == '\n') as usize;
+                                text.push(escaped);
+                            }
+                        }
+                        Some(c) => {
+                            line += (c == '\n') as usize;
+                            text.push(c);
+                        }
+                        None => return Err((start, "unterminated quoted string".to_owned())),
+                    }
+                }
+
+                entry.tokens.push(Token { text, quoted: true });
+            }
+            '\\' => {
+                token.push('\\');
+                if let Some(escaped) = chars.next() {
+                    line += (escaped == '\n') as usize;
+                    token.push(escaped);
+                }
+            }
+            c => token.push(c),
+        }
+    }
+
+    end_token(&mut token, &mut entry);
+    if depth != 0 {
+        return Err((entry.line, "unbalanced (".to_owned()));
+    }
+    if !entry.tokens.is_empty() {
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+fn end_token(token: &mut String, entry: &mut Entry) {
+    if !token.is_empty() {
+        entry.tokens.push(Token {
+            text: std::mem::take(token),
+            quoted: false,
+        });
+    }
+}
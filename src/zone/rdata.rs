@@ -0,0 +1,287 @@
+use std::str::FromStr;
+
+use super::lexer::Token;
+use super::{parse_name, parse_ttl};
+use crate::resource_record::is_caa_tag;
+use crate::text::{decode_base32hex, decode_base64, decode_escapes, decode_hex, parse_timestamp};
+use crate::{
+    CharacterString, DnsError, Name, RecordData, RecordType, ServiceBinding, SvcParam, TypeBitmap,
+};
+
+/// Parse the rdata fields of a record in the presentation format of its type,
+/// or in the generic format that works for every type (RFC 3597 5)
+pub(super) fn parse_rdata(
+    type_: RecordType,
+    tokens: &[Token],
+    origin: &Name,
+) -> Result<RecordData, String> {
+    if let Some(Token {
+        text,
+        quoted: false,
+    }) = tokens.first()
+    {
+        if text == r"\#" {
+            let generic: Vec<&str> = tokens.iter().map(|token| token.text.as_str()).collect();
+            return RecordData::from_generic(type_, &generic.join(" "))
+                .map_err(|_| "invalid generic record data".to_owned());
+        }
+    }
+
+    let mut fields = Fields {
+        tokens: tokens.iter(),
+        origin,
+    };
+
+    let data = match type_ {
+        RecordType::A => RecordData::A(fields.parse("address")?),
+        RecordType::Ns => RecordData::Ns(fields.name("nameserver")?),
+        RecordType::Cname => RecordData::Cname(fields.name("target")?),
+        RecordType::Soa => RecordData::Soa {
+            mname: fields.name("mname")?,
+            rname: fields.name("rname")?,
+            serial: fields.parse("serial")?,
+            refresh: fields.ttl("refresh")?,
+            retry: fields.ttl("retry")?,
+            expire: fields.ttl("expire")?,
+            minimum: fields.ttl("minimum")?,
+        },
+        RecordType::Mx => RecordData::Mx {
+            preference: fields.parse("preference")?,
+            exchange: fields.name("exchange")?,
+        },
+        RecordType::Ptr => RecordData::Ptr(fields.name("target")?),
+        RecordType::Txt => {
+            let mut strings = vec![fields.string("text")?];
+            while !fields.is_empty() {
+                strings.push(fields.string("text")?);
+            }
+            RecordData::Txt(strings)
+        }
+        RecordType::Aaaa => RecordData::Aaaa(fields.parse("address")?),
+        RecordType::Srv => RecordData::Srv {
+            priority: fields.parse("priority")?,
+            weight: fields.parse("weight")?,
+            port: fields.parse("port")?,
+            target: fields.name("target")?,
+        },
+        RecordType::Naptr => RecordData::Naptr {
+            order: fields.parse("order")?,
+            preference: fields.parse("preference")?,
+            flags: fields.string("flags")?,
+            services: fields.string("services")?,
+            regexp: fields.string("regexp")?,
+            replacement: fields.name("replacement")?,
+        },
+        RecordType::Caa => {
+            let flags = fields.parse("flags")?;
+
+            let tag = fields.next("tag")?;
+            if tag.quoted || !is_caa_tag(tag.text.as_bytes()) {
+                return Err(format!("invalid tag {:?}", tag.text));
+            }
+
+            // The value can be longer than a character-string
+            let value = fields.next("value")?;
+            let value = decode_escapes(&value.text)
+                .map_err(|_| format!("invalid value {:?}", value.text))?;
+
+            RecordData::Caa {
+                flags,
+                tag: tag.text.clone(),
+                value,
+            }
+        }
+        RecordType::Ds => RecordData::Ds {
+            key_tag: fields.parse("key tag")?,
+            algorithm: fields.parse("algorithm")?,
+            digest_type: fields.parse("digest type")?,
+            digest: fields.rest("digest", decode_hex)?,
+        },
+        RecordType::Rrsig => RecordData::Rrsig {
+            type_covered: fields.parse("type covered")?,
+            algorithm: fields.parse("algorithm")?,
+            labels: fields.parse("labels")?,
+            original_ttl: fields.ttl("original TTL")?,
+            expiration: fields.timestamp("expiration")?,
+            inception: fields.timestamp("inception")?,
+            key_tag: fields.parse("key tag")?,
+            signer: fields.name("signer")?,
+            signature: fields.rest("signature", decode_base64)?,
+        },
+        RecordType::Nsec => RecordData::Nsec {
+            next: fields.name("next name")?,
+            types: fields.types()?,
+        },
+        RecordType::Dnskey => RecordData::Dnskey {
+            flags: fields.parse("flags")?,
+            protocol: fields.parse("protocol")?,
+            algorithm: fields.parse("algorithm")?,
+            public_key: fields.rest("public key", decode_base64)?,
+        },
+        RecordType::Nsec3 => RecordData::Nsec3 {
+            hash_algorithm: fields.parse("hash algorithm")?,
+            flags: fields.parse("flags")?,
+            iterations: fields.parse("iterations")?,
+            salt: fields.salt()?,
+            next_hashed: {
+                let token = fields.next("next hashed owner")?;
+                decode_base32hex(&token.text)
+                    .ok()
+                    .filter(|hash| !hash.is_empty() && hash.len() <= 255)
+                    .ok_or_else(|| format!("invalid next hashed owner {:?}", token.text))?
+            },
+            types: fields.types()?,
+        },
+        RecordType::Nsec3param => RecordData::Nsec3param {
+            hash_algorithm: fields.parse("hash algorithm")?,
+            flags: fields.parse("flags")?,
+            iterations: fields.parse("iterations")?,
+            salt: fields.salt()?,
+        },
+        RecordType::Svcb => RecordData::Svcb(fields.service_binding()?),
+        RecordType::Https => RecordData::Https(fields.service_binding()?),
+        type_ => {
+            return Err(format!(
+                r"{type_} records have to use the generic \# format"
+            ))
+        }
+    };
+
+    fields.finish()?;
+
+    Ok(data)
+}
+
+/// The rdata fields of a record, taken in order
+struct Fields<'a> {
+    tokens: std::slice::Iter<'a, Token>,
+    origin: &'a Name,
+}
+
+impl<'a> Fields<'a> {
+    fn next(&mut self, field: &str) -> Result<&'a Token, String> {
+        self.tokens.next().ok_or_else(|| format!("missing {field}"))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tokens.len() == 0
+    }
+
+    fn parse<T: FromStr>(&mut self, field: &str) -> Result<T, String> {
+        let token = self.next(field)?;
+        token
+            .text
+            .parse()
+            .map_err(|_| format!("invalid {field} {:?}", token.text))
+    }
+
+    fn name(&mut self, field: &str) -> Result<Name, String> {
+        parse_name(self.next(field)?, self.origin)
+    }
+
+    fn ttl(&mut self, field: &str) -> Result<u32, String> {
+        let token = self.next(field)?;
+        parse_ttl(&token.text).ok_or_else(|| format!("invalid {field} {:?}", token.text))
+    }
+
+    fn string(&mut self, field: &str) -> Result<CharacterString, String> {
+        let token = self.next(field)?;
+        decode_escapes(&token.text)
+            .and_then(CharacterString::new)
+            .map_err(|_| format!("invalid {field} {:?}", token.text))
+    }
+
+    fn timestamp(&mut self, field: &str) -> Result<u32, String> {
+        let token = self.next(field)?;
+        parse_timestamp(&token.text).map_err(|_| format!("invalid {field} {:?}", token.text))
+    }
+
+    /// The rest of the fields decoded as one, for hex and base64 data
+    /// that can be split by whitespace
+    fn rest(
+        &mut self,
+        field: &str,
+        decode: impl Fn(&str) -> Result<Vec<u8>, DnsError>,
+    ) -> Result<Vec<u8>, String> {
+        let text: String = self
+            .tokens
+            .by_ref()
+            .map(|token| token.text.as_str())
+            .collect();
+        if text.is_empty() {
+            return Err(format!("missing {field}"));
+        }
+
+        decode(&text).map_err(|_| format!("invalid {field} {text:?}"))
+    }
+
+    /// An NSEC3 salt in hex, `-` for no salt
+    fn salt(&mut self) -> Result<Vec<u8>, String> {
+        let token = self.next("salt")?;
+        if token.text == "-" {
+            return Ok(Vec::new());
+        }
+
+        decode_hex(&token.text)
+            .ok()
+            .filter(|salt| !salt.is_empty() && salt.len() <= 255)
+            .ok_or_else(|| format!("invalid salt {:?}", token.text))
+    }
+
+    /// The types of an NSEC or NSEC3 record, which take the rest of the fields
+    fn types(&mut self) -> Result<TypeBitmap, String> {
+        self.tokens
+            .by_ref()
+            .map(|token| {
+                token
+                    .text
+                    .parse()
+                    .map_err(|_| format!("invalid type {:?}", token.text))
+            })
+            .collect()
+    }
+
+    /// The priority, the target and the SvcParams, which take the rest of the fields.
+    /// A parameter is `key` or `key=value`, and the value can be quoted (RFC 9460 2.1)
+    fn service_binding(&mut self) -> Result<ServiceBinding, String> {
+        let priority = self.parse("priority")?;
+        let target = self.name("target")?;
+
+        let mut params = Vec::new();
+        while let Some(token) = self.tokens.next() {
+            let invalid = || format!("invalid SvcParam {:?}", token.text);
+            if token.quoted {
+                return Err(invalid());
+            }
+
+            let (key, value) = match token.text.split_once('=') {
+                // The lexer splits a quoted value into its own token
+                Some((key, "")) => match self.tokens.as_slice().first() {
+                    Some(value) if value.quoted => {
+                        self.tokens.next();
+                        (key, Some(value.text.as_str()))
+                    }
+                    _ => (key, Some("")),
+                },
+                Some((key, value)) => (key, Some(value)),
+                None => (token.text.as_str(), None),
+            };
+
+            let value = value
+                .map(decode_escapes)
+                .transpose()
+                .map_err(|_| invalid())?;
+            params.push(SvcParam::from_text(key, value.as_deref()).map_err(|_| invalid())?);
+        }
+
+        ServiceBinding::new(priority, target, params)
+            .map_err(|_| "SvcParams repeat a key or miss a mandatory one".to_owned())
+    }
+
+    fn finish(mut self) -> Result<(), String> {
+        match self.tokens.next() {
+            Some(token) => Err(format!("unexpected {:?}", token.text)),
+            None => Ok(()),
+        }
+    }
+}